@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use wither::bson::oid::ObjectId;
+
+// Sweep expired entries once a map grows past this many entries.
+const SWEEP_THRESHOLD: usize = 10_000;
+
+/// The parts of an account that access tokens are checked against.
+#[derive(Debug, Clone, Copy)]
+pub struct AccountState {
+  pub token_version: i32,
+  pub locked: bool,
+}
+
+/// Short-lived, process-local cache of account states and revoked token ids,
+/// so authenticating a request doesn't hit Mongo every time. Changes made on
+/// another instance become visible once the entries expire.
+#[derive(Clone)]
+pub struct AccountCache {
+  ttl: Duration,
+  accounts: Arc<RwLock<HashMap<ObjectId, (Instant, AccountState)>>>,
+  revoked: Arc<RwLock<HashMap<String, (Instant, bool)>>>,
+}
+
+impl AccountCache {
+  pub fn new(ttl: Duration) -> Self {
+    Self {
+      ttl,
+      accounts: Arc::new(RwLock::new(HashMap::new())),
+      revoked: Arc::new(RwLock::new(HashMap::new())),
+    }
+  }
+
+  pub fn account(&self, id: &ObjectId) -> Option<AccountState> {
+    get(&self.accounts, id, self.ttl)
+  }
+
+  pub fn insert_account(&self, id: ObjectId, state: AccountState) {
+    insert(&self.accounts, id, state, self.ttl);
+  }
+
+  pub fn invalidate_account(&self, id: &ObjectId) {
+    self.accounts.write().unwrap().remove(id);
+  }
+
+  /// Returns whether the token id is known to be revoked, or `None` when
+  /// the answer isn't cached.
+  pub fn is_revoked(&self, jti: &str) -> Option<bool> {
+    get(&self.revoked, jti, self.ttl)
+  }
+
+  pub fn insert_revoked(&self, jti: String, revoked: bool) {
+    insert(&self.revoked, jti, revoked, self.ttl);
+  }
+}
+
+fn get<K, Q, V>(map: &RwLock<HashMap<K, (Instant, V)>>, key: &Q, ttl: Duration) -> Option<V>
+where
+  K: std::borrow::Borrow<Q> + std::hash::Hash + Eq,
+  Q: std::hash::Hash + Eq + ?Sized,
+  V: Copy,
+{
+  let map = map.read().unwrap();
+  match map.get(key) {
+    Some((cached_at, value)) if cached_at.elapsed() < ttl => Some(*value),
+    _ => None,
+  }
+}
+
+fn insert<K, V>(map: &RwLock<HashMap<K, (Instant, V)>>, key: K, value: V, ttl: Duration)
+where
+  K: std::hash::Hash + Eq,
+{
+  let mut map = map.write().unwrap();
+  if map.len() >= SWEEP_THRESHOLD {
+    map.retain(|_, (cached_at, _)| cached_at.elapsed() < ttl);
+  }
+  map.insert(key, (Instant::now(), value));
+}
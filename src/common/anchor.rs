@@ -196,6 +196,29 @@ pub async fn validate_geodata(
   account_id: &str,
   hash: &str,
   created_nanos: u64,
+) -> Result<(), Error> {
+  execute_validate(geodata_id, account_id, hash, created_nanos, MEMO.to_string()).await
+}
+
+/// anchors a revision of geodata on the blockchain, chained to the hash of
+/// the version it replaced, which the memo carries
+pub async fn anchor_revision(
+  geodata_id: &str,
+  account_id: &str,
+  hash: &str,
+  previous_hash: &str,
+  revised_nanos: u64,
+) -> Result<(), Error> {
+  let memo = format!("previous_hash:{}", previous_hash);
+  execute_validate(geodata_id, account_id, hash, revised_nanos, memo).await
+}
+
+async fn execute_validate(
+  geodata_id: &str,
+  account_id: &str,
+  hash: &str,
+  created_nanos: u64,
+  memo: String,
 ) -> Result<(), Error> {
   let contract_address = env::var("CONTRACT_ADDRESS").unwrap();
   let contract_account_id = AccountId::from_str(&contract_address).unwrap();
@@ -218,7 +241,7 @@ pub async fn validate_geodata(
   }
   .to_any()
   .unwrap();
-  let tx_body = tx::Body::new(vec![msg_execute], MEMO.to_string(), TIMEOUT_HEIGHT);
+  let tx_body = tx::Body::new(vec![msg_execute], memo, TIMEOUT_HEIGHT);
   let auth_info =
     SignerInfo::single_direct(Some(SENDER_PUBLIC_KEY.clone()), sequence_number).auth_info(FEE.clone());
   let sign_doc = SignDoc::new(&tx_body, &auth_info, &CHAIN_ID.parse().unwrap(), ACCOUNT_NUMBER).unwrap();
@@ -242,6 +265,7 @@ pub async fn validate_geodata(
   }
 
   poll_for_tx(&rpc_client, tx_commit_response.hash).await;
+  env::set_var("CURRENT_SEQUENCE", sequence_number.to_string());
   Ok(())
 }
 
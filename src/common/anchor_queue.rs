@@ -0,0 +1,144 @@
+//! Works through the geodata queued for anchoring, one request at a time as
+//! anchoring is slow and every transaction takes the next sequence number of
+//! the sender.
+use bson::doc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, error};
+
+use crate::common::anchor;
+use crate::common::date;
+use crate::common::models::ModelExt;
+use crate::context::Context;
+use crate::errors::Error;
+use crate::models::anchor_request::AnchorRequest;
+use crate::models::geodata;
+use crate::models::validation::Validity;
+
+// Wait between polls of an empty queue, and after a failed anchoring.
+const IDLE_DELAY: Duration = Duration::from_secs(5);
+// Seconds after which a claim is taken over by another worker.
+const CLAIM_LEASE: i64 = 600;
+// Seconds before the first retry of a failed request, doubled after each
+// further failure up to `MAX_BACKOFF`.
+const BASE_BACKOFF: i64 = 30;
+const MAX_BACKOFF: i64 = 3600;
+// Failed attempts after which a request is given up on.
+pub const MAX_ATTEMPTS: i32 = 10;
+// Seconds a revision has to be written before the worker takes its request
+// over, completing the revision or dropping it.
+pub const REVISION_GRACE: i64 = 60;
+
+pub fn spawn(context: Context) -> JoinHandle<()> {
+  tokio::spawn(async move {
+    loop {
+      match anchor_next(&context).await {
+        Ok(true) => continue,
+        Ok(false) => {}
+        Err(err) => error!("Failed to work the anchor queue: {}", err),
+      }
+      tokio::time::sleep(IDLE_DELAY).await;
+    }
+  })
+}
+
+/// Anchors the next queued request. Returns whether one was anchored. A
+/// failed request is retried after a growing delay, so it doesn't hold up
+/// the rest of the queue, until it failed `MAX_ATTEMPTS` times.
+pub async fn anchor_next(context: &Context) -> Result<bool, Error> {
+  let queue = &context.models.anchor_request;
+  let request = match queue.claim_next(CLAIM_LEASE).await? {
+    Some(request) => request,
+    None => return Ok(false),
+  };
+  let id = request.id.unwrap();
+  let nanos: u64 = request.created.to_chrono().timestamp_nanos() as u64;
+
+  let anchored = match &request.previous_hash {
+    Some(previous_hash) => {
+      if !complete_revision(context, &request).await? {
+        debug!("Dropping revision {} of geodata {}", request.version, request.geodata);
+        queue.delete_one(doc! { "_id": id }).await?;
+        return Ok(true);
+      }
+      anchor::anchor_revision(
+        &request.geodata.to_hex(),
+        &request.account.to_hex(),
+        &request.hash,
+        previous_hash,
+        nanos,
+      )
+      .await
+    }
+    None => {
+      anchor::anchor_geodata(
+        &request.geodata.to_hex(),
+        &request.account.to_hex(),
+        &request.hash,
+        nanos,
+      )
+      .await
+    }
+  };
+
+  match anchored {
+    Ok(()) => {
+      queue.mark_anchored(&id).await?;
+      debug!("Anchored geodata {}", request.geodata);
+      Ok(true)
+    }
+    Err(err) => {
+      error!("Failed to anchor geodata {}: {}", request.geodata, err);
+      let retry_at = retry_delay(request.attempts + 1).map(date::after);
+      queue.release(&id, err.to_string(), retry_at).await?;
+      Ok(false)
+    }
+  }
+}
+
+/// Makes sure the revision of a request was written whole, completing the
+/// steps after the write that an interrupted request left undone. Returns
+/// whether the revision was written at all.
+async fn complete_revision(context: &Context, request: &AnchorRequest) -> Result<bool, Error> {
+  let models = &context.models;
+  let current = match models.geodata.find_by_id(&request.geodata).await? {
+    Some(geodata) => geodata,
+    None => return Ok(false),
+  };
+  if current.version < request.version {
+    return Ok(false);
+  }
+  if current.version == request.version {
+    if geodata::hash_geodata(current).await? != request.hash {
+      return Ok(false);
+    }
+    let validity = Validity::new(request.account, request.hash.clone());
+    models.validation.authorize_revision(&request.geodata, validity).await?;
+  } else {
+    // Later revisions snapshotted this one when they replaced it.
+    let query = doc! {
+      "geodata": request.geodata,
+      "version": request.version,
+      "hash": &request.hash,
+    };
+    if !models.geodata_version.exists(query).await? {
+      return Ok(false);
+    }
+  }
+
+  models
+    .geodata_version
+    .supersede(&request.geodata, request.version - 1, &request.account, request.created)
+    .await?;
+  Ok(true)
+}
+
+/// Seconds to wait after the `attempts`th failure, none once there were too
+/// many.
+pub fn retry_delay(attempts: i32) -> Option<i64> {
+  if attempts >= MAX_ATTEMPTS {
+    return None;
+  }
+  let exponent = (attempts.max(1) - 1).min(16) as u32;
+  Some(BASE_BACKOFF.saturating_mul(2i64.pow(exponent)).min(MAX_BACKOFF))
+}
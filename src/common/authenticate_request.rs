@@ -1,21 +1,25 @@
 use axum::{
   async_trait,
-  extract::{FromRequest, RequestParts, TypedHeader},
+  extract::{ConnectInfo, FromRequest, RequestParts, TypedHeader},
   headers::{authorization::Bearer, Authorization},
 };
+use bson::doc;
+use std::net::{IpAddr, SocketAddr};
 use tracing::debug;
 
 use crate::common::token;
-use crate::common::token::TokenAccount;
-use crate::common::token::ADMIN_PATH;
-use crate::common::token::USER_PATH;
-use crate::common::token::VALIDATOR_PATH;
+use crate::common::account_cache::AccountState;
+use crate::common::date;
+use crate::common::models::ModelExt;
+use crate::common::token::{Claims, TokenAccount};
 use crate::context::Context;
 use crate::errors::AuthenticateError;
 use crate::errors::Error;
+use crate::models::api_key::{self, ApiKey};
+use crate::models::role;
 
 #[async_trait]
-impl<B> FromRequest<B> for TokenAccount
+impl<B> FromRequest<B> for Claims
 where
   B: Send,
 {
@@ -28,51 +32,213 @@ where
         .map_err(|_| AuthenticateError::InvalidToken)?;
     let extensions = req.extensions();
 
-    let context = extensions.get::<Context>().ok_or(Error::ReadContext)?;
-    let secret = context.settings.auth.secret.as_str();
-    let token_data =
-      token::decode(bearer.token(), secret).map_err(|_| AuthenticateError::InvalidToken)?;
-
-    match &req.uri().path()[..7] {
-      ADMIN_PATH => {
-        debug!("admin request");
-        if !token_data
-          .claims
-          .account
-          .roles
-          .iter()
-          .any(|i| i.name == "admin")
-        {
-          return Err(Error::Authenticate(AuthenticateError::WrongCredentials));
-        }
-      }
-      USER_PATH => {
-        debug!("user request");
-        if !token_data
-          .claims
-          .account
-          .roles
-          .iter()
-          .any(|i| i.name == "user")
-        {
-          return Err(Error::Authenticate(AuthenticateError::WrongCredentials));
-        }
-      }
-      VALIDATOR_PATH => {
-        debug!("validator request");
-        if !token_data
-          .claims
-          .account
-          .roles
-          .iter()
-          .any(|i| i.name == "validator")
-        {
-          return Err(Error::Authenticate(AuthenticateError::WrongCredentials));
-        }
-      }
-      _ => debug!("unprotected request"),
+    let context = extensions.get::<Context>().ok_or(Error::ReadContext)?.clone();
+    let token_data = token::decode(bearer.token(), &context.keys)
+      .map_err(|_| AuthenticateError::InvalidToken)?;
+
+    check_revocation(&context, &token_data.claims).await?;
+
+    Ok(token_data.claims)
+  }
+}
+
+/// Accepts either a bearer JWT or a bearer API key.
+#[async_trait]
+impl<B> FromRequest<B> for TokenAccount
+where
+  B: Send,
+{
+  type Rejection = Error;
+
+  async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+    let TypedHeader(Authorization(bearer)) =
+      TypedHeader::<Authorization<Bearer>>::from_request(req)
+        .await
+        .map_err(|_| AuthenticateError::InvalidToken)?;
+
+    if bearer.token().starts_with(api_key::KEY_MARKER) {
+      let extensions = req.extensions();
+      let context = extensions.get::<Context>().ok_or(Error::ReadContext)?.clone();
+      let client_ip = extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(address)| address.ip());
+      return authenticate_api_key(&context, bearer.token(), client_ip).await;
     }
 
-    Ok(token_data.claims.account)
+    Claims::from_request(req).await.map(|claims| claims.account)
+  }
+}
+
+/// Authenticated account holding the `admin` role.
+#[derive(Debug)]
+pub struct AdminAccount(pub TokenAccount);
+
+/// Authenticated account holding the `user` role.
+#[derive(Debug)]
+pub struct UserAccount(pub TokenAccount);
+
+/// Authenticated account holding the `validator` role.
+#[derive(Debug)]
+pub struct ValidatorAccount(pub TokenAccount);
+
+#[async_trait]
+impl<B> FromRequest<B> for AdminAccount
+where
+  B: Send,
+{
+  type Rejection = Error;
+
+  async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+    authorize(req, role::ADMIN).await.map(Self)
+  }
+}
+
+#[async_trait]
+impl<B> FromRequest<B> for UserAccount
+where
+  B: Send,
+{
+  type Rejection = Error;
+
+  async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+    authorize(req, role::USER).await.map(Self)
   }
 }
+
+#[async_trait]
+impl<B> FromRequest<B> for ValidatorAccount
+where
+  B: Send,
+{
+  type Rejection = Error;
+
+  async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+    authorize(req, role::VALIDATOR).await.map(Self)
+  }
+}
+
+async fn authorize<B>(req: &mut RequestParts<B>, role: &str) -> Result<TokenAccount, Error>
+where
+  B: Send,
+{
+  let account = TokenAccount::from_request(req).await?;
+  if !account.has_role(role) {
+    debug!("account {} is missing role {}", account.id, role);
+    return Err(Error::Authenticate(AuthenticateError::Forbidden));
+  }
+
+  Ok(account)
+}
+
+// Rejects tokens of locked accounts, tokens issued before the account's
+// token version was bumped, and tokens revoked one by one.
+async fn check_revocation(context: &Context, claims: &Claims) -> Result<(), Error> {
+  let account_id = &claims.account.id;
+  let state = match context.cache.account(account_id) {
+    Some(state) => state,
+    None => {
+      let account = context
+        .models
+        .account
+        .find_by_id(account_id)
+        .await?
+        .ok_or(Error::Authenticate(AuthenticateError::InvalidToken))?;
+      let state = AccountState {
+        token_version: account.token_version,
+        locked: account.locked_at.is_some(),
+      };
+      context.cache.insert_account(*account_id, state);
+      state
+    }
+  };
+
+  if state.locked {
+    debug!("account {} is locked", account_id);
+    return Err(Error::Authenticate(AuthenticateError::Locked));
+  }
+
+  if claims.ver != state.token_version {
+    debug!("token version {} of account {} is outdated", claims.ver, account_id);
+    return Err(Error::Authenticate(AuthenticateError::InvalidToken));
+  }
+
+  let revoked = match context.cache.is_revoked(&claims.jti) {
+    Some(revoked) => revoked,
+    None => {
+      let revoked = context
+        .models
+        .revoked_token
+        .exists(doc! { "jti": &claims.jti })
+        .await?;
+      context.cache.insert_revoked(claims.jti.clone(), revoked);
+      revoked
+    }
+  };
+
+  if revoked {
+    debug!("token {} is revoked", claims.jti);
+    return Err(Error::Authenticate(AuthenticateError::InvalidToken));
+  }
+
+  Ok(())
+}
+
+// Resolves an API key to its account, acting only with the roles the key is
+// scoped to.
+async fn authenticate_api_key(
+  context: &Context,
+  key: &str,
+  client_ip: Option<IpAddr>,
+) -> Result<TokenAccount, Error> {
+  let prefix = ApiKey::parse_prefix(key).ok_or(AuthenticateError::InvalidToken)?;
+  let api_key = context
+    .models
+    .api_key
+    .find_one(doc! { "prefix": prefix }, None)
+    .await?
+    .filter(|api_key| api_key.is_match(key))
+    .ok_or(AuthenticateError::InvalidToken)?;
+
+  if !api_key.is_active() {
+    debug!("api key {} is revoked or expired", api_key.prefix);
+    return Err(Error::Authenticate(AuthenticateError::InvalidToken));
+  }
+
+  if !client_ip.map_or(api_key.allowed_ips.is_empty(), |ip| api_key.allows_ip(ip)) {
+    debug!("api key {} is not allowed from {:?}", api_key.prefix, client_ip);
+    return Err(Error::Authenticate(AuthenticateError::Forbidden));
+  }
+
+  let account = context
+    .models
+    .account
+    .find_by_id(&api_key.account)
+    .await?
+    .ok_or(AuthenticateError::InvalidToken)?;
+
+  if account.locked_at.is_some() {
+    debug!("account {} of api key {} is locked", account.email, api_key.prefix);
+    return Err(Error::Authenticate(AuthenticateError::Locked));
+  }
+
+  // Recording every single use would turn each read into a write.
+  let now = date::now();
+  let recently_used = api_key
+    .last_used_at
+    .map_or(false, |last_used_at| now.timestamp_millis() - last_used_at.timestamp_millis() < 60_000);
+  if !recently_used {
+    context
+      .models
+      .api_key
+      .update_one(
+        doc! { "_id": api_key.id },
+        doc! { "$set": { "last_used_at": now } },
+        None,
+      )
+      .await?;
+  }
+
+  let mut account = TokenAccount::from(account);
+  account.roles.retain(|role| api_key.scopes.contains(&role.name));
+  Ok(account)
+}
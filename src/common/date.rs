@@ -1,7 +1,117 @@
 use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
 pub type Date = bson::DateTime;
 
 pub fn now() -> Date {
   Utc::now().into()
 }
+
+/// Returns the instant `seconds` from now.
+pub fn after(seconds: i64) -> Date {
+  (Utc::now() + chrono::Duration::seconds(seconds)).into()
+}
+
+/// Serializes an optional date as an RFC 3339 string, like
+/// `bson_datetime_as_rfc3339_string` does for required dates.
+pub mod optional_rfc3339 {
+  use serde::{Deserialize, Deserializer, Serializer};
+
+  use super::Date;
+
+  pub fn serialize<S: Serializer>(date: &Option<Date>, serializer: S) -> Result<S::Ok, S::Error> {
+    match date {
+      Some(date) => serializer.serialize_some(&date.to_rfc3339_string()),
+      None => serializer.serialize_none(),
+    }
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Date>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+      .map(|date| Date::parse_rfc3339_str(date).map_err(serde::de::Error::custom))
+      .transpose()
+  }
+}
+
+/// An instant, or the span of time between two instants. Intervals are
+/// written like ISO 8601 ones, as RFC 3339 dates joined by a slash, e.g.
+/// `2022-06-01T00:00:00Z/2022-06-02T00:00:00Z`. Instants start and end at
+/// the same time.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Interval {
+  pub start: Date,
+  pub end: Date,
+}
+
+impl Interval {
+  pub fn instant(date: Date) -> Self {
+    Self {
+      start: date,
+      end: date,
+    }
+  }
+
+  pub fn parse(interval: &str) -> Result<Self, String> {
+    let parse = |date: &str| {
+      Date::parse_rfc3339_str(date.trim())
+        .map_err(|_| format!("{} isn't an RFC 3339 date, e.g. 2022-06-01T00:00:00Z", date))
+    };
+    let interval = match interval.split_once('/') {
+      Some((start, end)) => Self {
+        start: parse(start)?,
+        end: parse(end)?,
+      },
+      None => Self::instant(parse(interval)?),
+    };
+    if interval.end < interval.start {
+      return Err("Interval ends before it starts".to_owned());
+    }
+
+    Ok(interval)
+  }
+
+  pub fn is_instant(&self) -> bool {
+    self.start == self.end
+  }
+}
+
+impl fmt::Display for Interval {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.is_instant() {
+      return write!(f, "{}", self.start.to_rfc3339_string());
+    }
+    write!(
+      f,
+      "{}/{}",
+      self.start.to_rfc3339_string(),
+      self.end.to_rfc3339_string()
+    )
+  }
+}
+
+/// Serializes an optional interval as its text, where documents store it
+/// with `start` and `end` dates.
+pub mod optional_interval {
+  use serde::{Deserialize, Deserializer, Serializer};
+
+  use super::Interval;
+
+  pub fn serialize<S: Serializer>(
+    interval: &Option<Interval>,
+    serializer: S,
+  ) -> Result<S::Ok, S::Error> {
+    match interval {
+      Some(interval) => serializer.serialize_some(&interval.to_string()),
+      None => serializer.serialize_none(),
+    }
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+  ) -> Result<Option<Interval>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+      .map(|interval| Interval::parse(&interval).map_err(serde::de::Error::custom))
+      .transpose()
+  }
+}
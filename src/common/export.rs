@@ -0,0 +1,109 @@
+//! Serialization of geodata exports a record at a time, so an export of any
+//! size streams in constant memory.
+use serde::Deserialize;
+
+use crate::common::geojson::{self, Feature, Geometry};
+use crate::models::geodata::PublicGeodata;
+
+const CSV_HEADER: &str =
+  "id,lon,lat,geotype,value,source,quality,observed_at,created,version,geometry\n";
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+  NdJson,
+  GeoJson,
+  Csv,
+}
+
+impl Format {
+  pub fn media_type(&self) -> &'static str {
+    match self {
+      Format::NdJson => "application/x-ndjson",
+      Format::GeoJson => geojson::MEDIA_TYPE,
+      Format::Csv => "text/csv; charset=utf-8",
+    }
+  }
+
+  pub fn extension(&self) -> &'static str {
+    match self {
+      Format::NdJson => "ndjson",
+      Format::GeoJson => "geojson",
+      Format::Csv => "csv",
+    }
+  }
+
+  /// What comes before the first record.
+  pub fn header(&self) -> &'static str {
+    match self {
+      Format::NdJson => "",
+      Format::GeoJson => r#"{"type":"FeatureCollection","features":["#,
+      Format::Csv => CSV_HEADER,
+    }
+  }
+
+  /// The `index`th record of the export, counting from 0.
+  pub fn record(&self, index: usize, geodata: PublicGeodata) -> String {
+    match self {
+      Format::NdJson => format!("{}\n", serde_json::to_string(&geodata).unwrap()),
+      Format::GeoJson => {
+        let feature = serde_json::to_string(&Feature::from(geodata)).unwrap();
+        if index == 0 {
+          return feature;
+        }
+        format!(",{}", feature)
+      }
+      Format::Csv => csv_record(geodata),
+    }
+  }
+
+  /// What comes after the last record.
+  pub fn footer(&self) -> &'static str {
+    match self {
+      Format::GeoJson => "]}",
+      _ => "",
+    }
+  }
+}
+
+/// A CSV row. `lon` and `lat` are those of the location when it's a Point,
+/// or of the first Point of a GeometryCollection, and `geometry` holds the
+/// whole location as GeoJSON.
+fn csv_record(geodata: PublicGeodata) -> String {
+  let (lon, lat) = match point(&geodata.location) {
+    Some(position) => (position[0].to_string(), position[1].to_string()),
+    None => (String::new(), String::new()),
+  };
+  let fields = [
+    geodata.id.to_hex(),
+    lon,
+    lat,
+    geodata.geotype.clone(),
+    geodata.value.to_string(),
+    geodata.source.clone(),
+    geodata.quality.to_string(),
+    geodata.observed_at.map(|observed_at| observed_at.to_string()).unwrap_or_default(),
+    geodata.created.to_rfc3339_string(),
+    geodata.version.to_string(),
+    serde_json::to_string(&geodata.location).unwrap(),
+  ];
+  let fields = fields.iter().map(|field| csv_field(field)).collect::<Vec<String>>();
+
+  format!("{}\n", fields.join(","))
+}
+
+fn point(geometry: &Geometry) -> Option<&[f64]> {
+  match geometry {
+    Geometry::Point { coordinates } => Some(coordinates.as_slice()),
+    Geometry::GeometryCollection { geometries } => geometries.iter().find_map(point),
+    _ => None,
+  }
+}
+
+/// Quotes a CSV field holding commas, quotes or line breaks.
+pub fn csv_field(field: &str) -> String {
+  if !field.contains(|c| matches!(c, ',' | '"' | '\n' | '\r')) {
+    return field.to_owned();
+  }
+  format!("\"{}\"", field.replace('"', "\"\""))
+}
@@ -0,0 +1,85 @@
+//! Geohashes: cells of a grid over the globe named by base 32 strings, each
+//! character splitting its cell in 32. Prefixes of a geohash name the larger
+//! cells holding it, so a cell at any precision is a prefix of the ones
+//! stored at full precision.
+use crate::common::geojson::{BoundingBox, Geometry};
+
+const BASE32: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// Characters of the geohashes stored on geodata, cells of a few
+/// centimeters.
+pub const PRECISION: usize = 12;
+
+/// The geohash of `precision` characters of the cell holding a position.
+pub fn encode(lon: f64, lat: f64, precision: usize) -> String {
+  let (mut lons, mut lats) = ((-180.0, 180.0), (-90.0, 90.0));
+  let mut hash = String::with_capacity(precision);
+  let mut bits = 0;
+  let mut index = 0;
+  // Bits alternate between longitude and latitude, longitude first.
+  let mut even = true;
+  while hash.len() < precision {
+    let (range, value) = if even { (&mut lons, lon) } else { (&mut lats, lat) };
+    let mid = (range.0 + range.1) / 2.0;
+    index <<= 1;
+    if value >= mid {
+      index |= 1;
+      range.0 = mid;
+    } else {
+      range.1 = mid;
+    }
+    even = !even;
+    bits += 1;
+    if bits == 5 {
+      hash.push(BASE32[index] as char);
+      bits = 0;
+      index = 0;
+    }
+  }
+
+  hash
+}
+
+/// The bounds of the cell a geohash names, unless it holds characters
+/// outside the geohash alphabet.
+pub fn bounds(hash: &str) -> Option<BoundingBox> {
+  let (mut lons, mut lats) = ((-180.0, 180.0), (-90.0, 90.0));
+  let mut even = true;
+  for c in hash.bytes() {
+    let index = BASE32.iter().position(|&b| b == c)?;
+    for bit in (0..5).rev() {
+      let range = if even { &mut lons } else { &mut lats };
+      let mid = (range.0 + range.1) / 2.0;
+      if (index >> bit) & 1 == 1 {
+        range.0 = mid;
+      } else {
+        range.1 = mid;
+      }
+      even = !even;
+    }
+  }
+
+  Some(BoundingBox {
+    west: lons.0,
+    south: lats.0,
+    east: lons.1,
+    north: lats.1,
+  })
+}
+
+/// The width and height in degrees of the cells of geohashes of
+/// `precision` characters.
+pub fn cell_size(precision: usize) -> (f64, f64) {
+  let bits = 5 * precision as i32;
+  // Longitude takes the odd bit of odd lengths.
+  let lon_bits = (bits + 1) / 2;
+  (360.0 / 2f64.powi(lon_bits), 180.0 / 2f64.powi(bits - lon_bits))
+}
+
+/// The full precision geohash of a location, that of the center of its
+/// bounding box. Locations are usually Points, or collections of Points
+/// close together.
+pub fn of(location: &Geometry) -> Option<String> {
+  let (lon, lat) = BoundingBox::around(location.positions())?.center();
+  Some(encode(lon, lat, PRECISION))
+}
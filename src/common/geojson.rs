@@ -0,0 +1,643 @@
+//! GeoJSON (RFC 7946): geometries, their validation and the spatial query
+//! filters built from them, and the features responses are made of.
+use axum::{
+  async_trait,
+  extract::{FromRequest, RequestParts},
+  http::header,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use wither::bson::{self, doc, Document};
+
+use crate::errors::{BadRequest, Error, FieldError, InvalidFields};
+
+pub const MEDIA_TYPE: &str = "application/geo+json";
+
+/// Mean earth radius in meters, as MongoDB uses for `$centerSphere`.
+pub const EARTH_RADIUS: f64 = 6_378_100.0;
+
+/// Longitude, latitude and an optional altitude.
+pub type Position = Vec<f64>;
+
+// MongoDB joins the vertices of query polygons along great circles, so the
+// edges of a box follow its parallels in steps of at most this many degrees.
+const EDGE_STEP: f64 = 1.0;
+// Widest part of a box as a query polygon, well within the hemisphere query
+// polygons are limited to.
+const MAX_PART_WIDTH: f64 = 90.0;
+/// Most positions a geometry may have.
+pub const MAX_POSITIONS: usize = 10_000;
+
+pub const GEOMETRY_TYPES: [&str; 7] = [
+  "Point",
+  "MultiPoint",
+  "LineString",
+  "MultiLineString",
+  "Polygon",
+  "MultiPolygon",
+  "GeometryCollection",
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Geometry {
+  Point { coordinates: Position },
+  MultiPoint { coordinates: Vec<Position> },
+  LineString { coordinates: Vec<Position> },
+  MultiLineString { coordinates: Vec<Vec<Position>> },
+  Polygon { coordinates: Vec<Vec<Position>> },
+  MultiPolygon { coordinates: Vec<Vec<Vec<Position>>> },
+  GeometryCollection { geometries: Vec<Geometry> },
+}
+
+impl Geometry {
+  /// A polygon covering a bounding box.
+  pub fn from_bbox(bbox: &BoundingBox) -> Self {
+    let BoundingBox { west, south, east, north } = *bbox;
+    Geometry::Polygon {
+      coordinates: vec![vec![
+        vec![west, south],
+        vec![east, south],
+        vec![east, north],
+        vec![west, north],
+        vec![west, south],
+      ]],
+    }
+  }
+
+  /// The area of a bounding box as a query geometry. Unlike `from_bbox`,
+  /// whose straight edges are what GeoJSON means, its edges follow the
+  /// parallels of the box on the sphere, and boxes wider than
+  /// `MAX_PART_WIDTH` are split into a MultiPolygon of narrower parts.
+  pub fn covering(bbox: &BoundingBox) -> Self {
+    let south = bbox.south.max(-90.0);
+    let north = bbox.north.min(90.0);
+    let width = (bbox.east - bbox.west).min(360.0);
+    let parts = (width / MAX_PART_WIDTH).ceil().max(1.0) as usize;
+    let mut polygons = (0..parts)
+      .map(|part| {
+        let west = bbox.west + width * part as f64 / parts as f64;
+        let east = bbox.west + width * (part + 1) as f64 / parts as f64;
+        vec![box_ring(west, south, east, north)]
+      })
+      .collect::<Vec<Vec<Vec<Position>>>>();
+
+    match polygons.len() {
+      1 => Geometry::Polygon {
+        coordinates: polygons.remove(0),
+      },
+      _ => Geometry::MultiPolygon {
+        coordinates: polygons,
+      },
+    }
+  }
+
+  /// The GeoJSON `type` of the geometry.
+  pub fn type_name(&self) -> &'static str {
+    match self {
+      Geometry::Point { .. } => "Point",
+      Geometry::MultiPoint { .. } => "MultiPoint",
+      Geometry::LineString { .. } => "LineString",
+      Geometry::MultiLineString { .. } => "MultiLineString",
+      Geometry::Polygon { .. } => "Polygon",
+      Geometry::MultiPolygon { .. } => "MultiPolygon",
+      Geometry::GeometryCollection { .. } => "GeometryCollection",
+    }
+  }
+
+  pub fn is_areal(&self) -> bool {
+    matches!(self, Geometry::Polygon { .. } | Geometry::MultiPolygon { .. })
+  }
+
+  /// All positions of the geometry, those of collection members included.
+  pub fn positions(&self) -> Vec<&Position> {
+    match self {
+      Geometry::Point { coordinates } => vec![coordinates],
+      Geometry::MultiPoint { coordinates } | Geometry::LineString { coordinates } => {
+        coordinates.iter().collect()
+      }
+      Geometry::MultiLineString { coordinates } | Geometry::Polygon { coordinates } => {
+        coordinates.iter().flatten().collect()
+      }
+      Geometry::MultiPolygon { coordinates } => coordinates.iter().flatten().flatten().collect(),
+      Geometry::GeometryCollection { geometries } => {
+        geometries.iter().flat_map(Geometry::positions).collect()
+      }
+    }
+  }
+
+  /// Validates a request body that is a geometry.
+  pub fn validate(&self) -> Result<(), Error> {
+    self.validate_field("")
+  }
+
+  /// Validates the geometry held by request `field`, reporting every
+  /// problem found under that field.
+  pub fn validate_field(&self, field: &str) -> Result<(), Error> {
+    let errors = self.errors(field);
+    if errors.is_empty() {
+      return Ok(());
+    }
+
+    Err(Error::InvalidFields(InvalidFields::new(errors)))
+  }
+
+  /// Every problem of the geometry held by `field`, none when it's valid.
+  /// Geometries of more than `MAX_POSITIONS` positions aren't checked
+  /// further, as checking rings for intersections is costly.
+  pub fn errors(&self, field: &str) -> Vec<FieldError> {
+    let mut errors = vec![];
+    if self.position_count() > MAX_POSITIONS {
+      let message = format!("A geometry may have at most {} positions", MAX_POSITIONS);
+      error(&mut errors, &join(field, "coordinates"), &message);
+      return errors;
+    }
+    self.check(field, true, &mut errors);
+    errors
+  }
+
+  fn position_count(&self) -> usize {
+    match self {
+      Geometry::Point { .. } => 1,
+      Geometry::MultiPoint { coordinates } | Geometry::LineString { coordinates } => {
+        coordinates.len()
+      }
+      Geometry::MultiLineString { coordinates } | Geometry::Polygon { coordinates } => {
+        coordinates.iter().map(Vec::len).sum()
+      }
+      Geometry::MultiPolygon { coordinates } => coordinates
+        .iter()
+        .flat_map(|rings| rings.iter().map(Vec::len))
+        .sum(),
+      Geometry::GeometryCollection { geometries } => {
+        geometries.iter().map(Geometry::position_count).sum()
+      }
+    }
+  }
+
+  /// Checks positions are in range, lines have two positions or more, and
+  /// polygon rings are closed, simple and wound by the right-hand rule:
+  /// exterior rings counterclockwise, holes clockwise.
+  fn check(&self, path: &str, top_level: bool, errors: &mut Vec<FieldError>) {
+    let coordinates = join(path, "coordinates");
+    match self {
+      Geometry::Point { coordinates: point } => check_position(point, &coordinates, errors),
+      Geometry::MultiPoint { coordinates: points } => {
+        check_not_empty(points, &coordinates, errors);
+        for (i, position) in points.iter().enumerate() {
+          check_position(position, &index(&coordinates, i), errors);
+        }
+      }
+      Geometry::LineString { coordinates: line } => check_line(line, &coordinates, errors),
+      Geometry::MultiLineString { coordinates: lines } => {
+        check_not_empty(lines, &coordinates, errors);
+        for (i, line) in lines.iter().enumerate() {
+          check_line(line, &index(&coordinates, i), errors);
+        }
+      }
+      Geometry::Polygon { coordinates: rings } => check_polygon(rings, &coordinates, errors),
+      Geometry::MultiPolygon { coordinates: polygons } => {
+        check_not_empty(polygons, &coordinates, errors);
+        for (i, rings) in polygons.iter().enumerate() {
+          check_polygon(rings, &index(&coordinates, i), errors);
+        }
+      }
+      Geometry::GeometryCollection { geometries } => {
+        let path = join(path, "geometries");
+        if !top_level {
+          error(errors, &path, "Geometry collections can't be nested");
+          return;
+        }
+        check_not_empty(geometries, &path, errors);
+        for (i, geometry) in geometries.iter().enumerate() {
+          geometry.check(&index(&path, i), false, errors);
+        }
+      }
+    }
+  }
+
+  /// The `$geometry` operand of geospatial query operators.
+  pub fn to_query(&self) -> Result<Document, Error> {
+    Ok(doc! { "$geometry": bson::to_bson(self)? })
+  }
+}
+
+/// The counterclockwise ring around a box, through vertices at most
+/// `EDGE_STEP` degrees apart. A pole is a single vertex.
+fn box_ring(west: f64, south: f64, east: f64, north: f64) -> Vec<Position> {
+  let mut ring = Vec::new();
+  for (from, to) in [
+    ([west, south], [east, south]),
+    ([east, south], [east, north]),
+    ([east, north], [west, north]),
+    ([west, north], [west, south]),
+  ] {
+    if from[1] == to[1] && from[1].abs() == 90.0 {
+      continue;
+    }
+    let span = (to[0] - from[0]).abs().max((to[1] - from[1]).abs());
+    let steps = (span / EDGE_STEP).ceil().max(1.0) as usize;
+    for step in 0..steps {
+      let share = step as f64 / steps as f64;
+      let lon = from[0] + (to[0] - from[0]) * share;
+      let lat = from[1] + (to[1] - from[1]) * share;
+      ring.push(vec![wrap_longitude(lon), lat]);
+    }
+  }
+  ring.push(ring[0].clone());
+
+  ring
+}
+
+/// The longitude within -180 and 180 of one past the antimeridian.
+fn wrap_longitude(lon: f64) -> f64 {
+  match lon {
+    lon if lon > 180.0 => lon - 360.0,
+    lon if lon < -180.0 => lon + 360.0,
+    lon => lon,
+  }
+}
+
+/// A bounding box, parsed from `west,south,east,north` in degrees. The east
+/// of a box crossing the antimeridian, as OGC API boxes may, is past 180.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+  pub west: f64,
+  pub south: f64,
+  pub east: f64,
+  pub north: f64,
+}
+
+impl BoundingBox {
+  pub fn parse(bbox: &str) -> Result<Self, Error> {
+    let values = bbox
+      .split(',')
+      .map(|value| value.trim().parse::<f64>())
+      .collect::<Result<Vec<f64>, _>>()
+      .map_err(|_| invalid("bbox", "Bounding box must be west,south,east,north"))?;
+    match values.as_slice() {
+      [west, south, east, north] => Self::new(*west, *south, *east, *north),
+      _ => Err(invalid("bbox", "Bounding box must be west,south,east,north")),
+    }
+  }
+
+  /// A box from its edges, which must be in range with `west < east` and
+  /// `south < north`.
+  pub fn new(west: f64, south: f64, east: f64, north: f64) -> Result<Self, Error> {
+    let bbox = BoundingBox { west, south, east, north };
+    for corner in [[bbox.west, bbox.south], [bbox.east, bbox.north]] {
+      if let Some(message) = position_error(&corner) {
+        return Err(invalid("bbox", message));
+      }
+    }
+    if bbox.west >= bbox.east || bbox.south >= bbox.north {
+      return Err(invalid("bbox", "Bounding box must have west < east and south < north"));
+    }
+
+    Ok(bbox)
+  }
+
+  /// The smallest box holding all `positions`, if there are any.
+  pub fn around<'a, I>(positions: I) -> Option<Self>
+  where
+    I: IntoIterator<Item = &'a Position>,
+  {
+    positions.into_iter().fold(None, |bbox, position| {
+      let (lon, lat) = (position[0], position[1]);
+      Some(match bbox {
+        None => BoundingBox {
+          west: lon,
+          south: lat,
+          east: lon,
+          north: lat,
+        },
+        Some(bbox) => BoundingBox {
+          west: bbox.west.min(lon),
+          south: bbox.south.min(lat),
+          east: bbox.east.max(lon),
+          north: bbox.north.max(lat),
+        },
+      })
+    })
+  }
+
+  /// The longitude and latitude of the center of the box.
+  pub fn center(&self) -> (f64, f64) {
+    ((self.west + self.east) / 2.0, (self.south + self.north) / 2.0)
+  }
+
+  /// The `bbox` member of GeoJSON objects.
+  pub fn to_array(&self) -> [f64; 4] {
+    [self.west, self.south, self.east, self.north]
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Feature {
+  pub r#type: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub id: Option<String>,
+  pub geometry: Geometry,
+  pub properties: Map<String, Value>,
+}
+
+impl Feature {
+  pub fn new(id: Option<String>, geometry: Geometry, properties: Map<String, Value>) -> Self {
+    Self {
+      r#type: "Feature".to_owned(),
+      id,
+      geometry,
+      properties,
+    }
+  }
+}
+
+/// A FeatureCollection, with the paging members of OGC API Features:
+/// `numberMatched`, `numberReturned` and `self`/`next` links.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeatureCollection {
+  pub r#type: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub bbox: Option<[f64; 4]>,
+  pub features: Vec<Feature>,
+  pub links: Vec<Link>,
+  pub number_matched: u64,
+  pub number_returned: usize,
+}
+
+impl FeatureCollection {
+  pub fn new(features: Vec<Feature>, links: Vec<Link>, number_matched: u64) -> Self {
+    let bbox = BoundingBox::around(features.iter().flat_map(|feature| feature.geometry.positions()))
+      .map(|bbox| bbox.to_array());
+    Self {
+      r#type: "FeatureCollection".to_owned(),
+      bbox,
+      number_returned: features.len(),
+      features,
+      links,
+      number_matched,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Link {
+  pub href: String,
+  pub rel: String,
+  pub r#type: String,
+}
+
+impl Link {
+  pub fn new(href: String, rel: &str, media_type: &str) -> Self {
+    Self {
+      href,
+      rel: rel.to_owned(),
+      r#type: media_type.to_owned(),
+    }
+  }
+}
+
+/// Whether the client asked for GeoJSON in its `Accept` header. Responses
+/// that depend on it say so with `Vary: Accept`.
+#[derive(Debug, Clone, Copy)]
+pub struct AcceptGeoJson(pub bool);
+
+#[async_trait]
+impl<B> FromRequest<B> for AcceptGeoJson
+where
+  B: Send,
+{
+  type Rejection = Error;
+
+  async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+    let accepts = req
+      .headers()
+      .get_all(header::ACCEPT)
+      .iter()
+      .filter_map(|value| value.to_str().ok())
+      .flat_map(|value| value.split(','))
+      .any(accepts_geojson);
+
+    Ok(AcceptGeoJson(accepts))
+  }
+}
+
+/// Whether a media range of an `Accept` header is GeoJSON, and not refused
+/// with a quality of 0.
+fn accepts_geojson(media_range: &str) -> bool {
+  let mut parts = media_range.split(';').map(str::trim);
+  if parts.next() != Some(MEDIA_TYPE) {
+    return false;
+  }
+
+  !parts.any(|parameter| match parameter.split_once('=') {
+    Some((name, quality)) if name.trim().eq_ignore_ascii_case("q") => {
+      quality.trim().parse::<f64>().map_or(false, |quality| quality == 0.0)
+    }
+    _ => false,
+  })
+}
+
+/// A `$centerSphere` filter for points within `radius` meters of a point.
+pub fn circle(lon: f64, lat: f64, radius: f64) -> Result<Document, Error> {
+  if let Some(message) = position_error(&[lon, lat]) {
+    return Err(invalid("lon", message));
+  }
+  if !radius.is_finite() || radius <= 0.0 {
+    return Err(invalid("radius", "Radius must be a positive number of meters"));
+  }
+
+  Ok(doc! { "$centerSphere": [[lon, lat], radius / EARTH_RADIUS] })
+}
+
+fn position_error(position: &[f64]) -> Option<&'static str> {
+  match position {
+    [lon, lat] | [lon, lat, _] => {
+      if !position.iter().all(|value| value.is_finite()) {
+        Some("Coordinates must be finite numbers")
+      } else if !(-180.0..=180.0).contains(lon) || !(-90.0..=90.0).contains(lat) {
+        Some("Longitude must be within -180 and 180, latitude within -90 and 90")
+      } else {
+        None
+      }
+    }
+    _ => Some("A position has a longitude, a latitude and optionally an altitude"),
+  }
+}
+
+fn check_position(position: &[f64], path: &str, errors: &mut Vec<FieldError>) {
+  if let Some(message) = position_error(position) {
+    error(errors, path, message);
+  }
+}
+
+fn check_line(line: &[Position], path: &str, errors: &mut Vec<FieldError>) {
+  if line.len() < 2 {
+    error(errors, path, "A line needs two positions or more");
+  }
+  for (i, position) in line.iter().enumerate() {
+    check_position(position, &index(path, i), errors);
+  }
+}
+
+fn check_polygon(rings: &[Vec<Position>], path: &str, errors: &mut Vec<FieldError>) {
+  check_not_empty(rings, path, errors);
+  let before = errors.len();
+  for (i, ring) in rings.iter().enumerate() {
+    let path = index(path, i);
+    if ring.len() < 4 {
+      error(errors, &path, "A polygon ring needs four positions or more");
+      continue;
+    }
+    if ring.first() != ring.last() {
+      error(errors, &path, "A polygon ring must end where it starts");
+    }
+    for (j, position) in ring.iter().enumerate() {
+      check_position(position, &index(&path, j), errors);
+    }
+  }
+  // Winding and intersections only make sense for well-formed rings.
+  if errors.len() > before {
+    return;
+  }
+
+  for (i, ring) in rings.iter().enumerate() {
+    let path = index(path, i);
+    let area = signed_area(ring);
+    if area == 0.0 {
+      error(errors, &path, "A polygon ring must enclose an area");
+    } else if i == 0 && area < 0.0 {
+      error(errors, &path, "The exterior ring must be counterclockwise");
+    } else if i > 0 && area > 0.0 {
+      error(errors, &path, "Holes must be clockwise");
+    }
+    if is_self_intersecting(ring) {
+      error(errors, &path, "A polygon ring must not intersect itself");
+    }
+  }
+  for (i, a) in rings.iter().enumerate() {
+    for (j, b) in rings.iter().enumerate().skip(i + 1) {
+      if rings_intersect(a, b) {
+        error(errors, &index(path, j), "Polygon rings must not cross each other");
+      }
+    }
+  }
+}
+
+fn check_not_empty<T>(coordinates: &[T], path: &str, errors: &mut Vec<FieldError>) {
+  if coordinates.is_empty() {
+    error(errors, path, "Must not be empty");
+  }
+}
+
+type Segment<'a> = (&'a [f64], &'a [f64]);
+
+/// Twice the planar area of a closed ring, positive when counterclockwise.
+fn signed_area(ring: &[Position]) -> f64 {
+  ring
+    .windows(2)
+    .map(|pair| pair[0][0] * pair[1][1] - pair[1][0] * pair[0][1])
+    .sum()
+}
+
+/// The segments of a closed ring, leaving out repeated positions.
+fn segments(ring: &[Position]) -> Vec<Segment> {
+  ring
+    .windows(2)
+    .filter(|pair| pair[0] != pair[1])
+    .map(|pair| (pair[0].as_slice(), pair[1].as_slice()))
+    .collect()
+}
+
+fn is_self_intersecting(ring: &[Position]) -> bool {
+  let segments = segments(ring);
+  let count = segments.len();
+  any_overlapping(&segments, |i, j| {
+    // Neighbouring segments share an end, the first and last included.
+    let neighbours = j == i + 1 || (i == 0 && j == count - 1);
+    !neighbours && segments_intersect(segments[i], segments[j])
+  })
+}
+
+fn rings_intersect(a: &[Position], b: &[Position]) -> bool {
+  let mut both = segments(a);
+  let split = both.len();
+  both.extend(segments(b));
+  any_overlapping(&both, |i, j| i < split && j >= split && segments_intersect(both[i], both[j]))
+}
+
+/// Whether `test` holds for a pair of segments, `i < j`. Only the pairs
+/// overlapping in longitude are tested: sorted by their west end, each
+/// segment is paired with the ones starting before it ends, which keeps
+/// the pairs few unless the segments are all stacked.
+fn any_overlapping<F>(segments: &[Segment], mut test: F) -> bool
+where
+  F: FnMut(usize, usize) -> bool,
+{
+  let west = |i: usize| segments[i].0[0].min(segments[i].1[0]);
+  let east = |i: usize| segments[i].0[0].max(segments[i].1[0]);
+  let mut order = (0..segments.len()).collect::<Vec<usize>>();
+  order.sort_by(|a, b| west(*a).partial_cmp(&west(*b)).unwrap_or(Ordering::Equal));
+  for (k, i) in order.iter().enumerate() {
+    let end = east(*i);
+    for j in order[k + 1..].iter().take_while(|j| west(**j) <= end) {
+      if test(*i.min(j), *i.max(j)) {
+        return true;
+      }
+    }
+  }
+  false
+}
+
+/// Whether two segments touch or cross, on the plane.
+fn segments_intersect(a: Segment, b: Segment) -> bool {
+  let d1 = orientation(b.0, b.1, a.0);
+  let d2 = orientation(b.0, b.1, a.1);
+  let d3 = orientation(a.0, a.1, b.0);
+  let d4 = orientation(a.0, a.1, b.1);
+
+  if d1 * d2 < 0.0 && d3 * d4 < 0.0 {
+    return true;
+  }
+
+  (d1 == 0.0 && on_segment(b.0, b.1, a.0))
+    || (d2 == 0.0 && on_segment(b.0, b.1, a.1))
+    || (d3 == 0.0 && on_segment(a.0, a.1, b.0))
+    || (d4 == 0.0 && on_segment(a.0, a.1, b.1))
+}
+
+fn orientation(a: &[f64], b: &[f64], c: &[f64]) -> f64 {
+  (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+/// Whether `c`, collinear with `a` and `b`, lies between them.
+fn on_segment(a: &[f64], b: &[f64], c: &[f64]) -> bool {
+  c[0] >= a[0].min(b[0])
+    && c[0] <= a[0].max(b[0])
+    && c[1] >= a[1].min(b[1])
+    && c[1] <= a[1].max(b[1])
+}
+
+fn join(path: &str, field: &str) -> String {
+  if path.is_empty() {
+    return field.to_owned();
+  }
+  format!("{}.{}", path, field)
+}
+
+fn index(path: &str, i: usize) -> String {
+  format!("{}[{}]", path, i)
+}
+
+fn error(errors: &mut Vec<FieldError>, field: &str, message: &str) {
+  errors.push(FieldError {
+    field: field.to_owned(),
+    message: message.to_owned(),
+  });
+}
+
+fn invalid(field: &str, message: &str) -> Error {
+  Error::BadRequest(BadRequest::new(field.to_owned(), message.to_owned()))
+}
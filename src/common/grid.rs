@@ -0,0 +1,88 @@
+//! Summaries of geodata values per geohash cell.
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use wither::bson::{doc, Bson, Document};
+
+use crate::common::geohash;
+use crate::common::geojson::{Feature, Geometry};
+
+/// The values of the geodata whose location centers in a cell. The
+/// quality-weighted mean is absent when the qualities add up to nothing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GridCell {
+  #[serde(alias = "_id")]
+  pub cell: String,
+  pub count: u64,
+  pub min: f64,
+  pub max: f64,
+  pub mean: f64,
+  pub weighted_mean: Option<f64>,
+}
+
+/// A Feature with the cell as a Polygon geometry, and its geohash as id.
+impl From<GridCell> for Feature {
+  fn from(cell: GridCell) -> Self {
+    let geometry = geohash::bounds(&cell.cell)
+      .map(|bbox| Geometry::from_bbox(&bbox))
+      .unwrap_or_else(|| Geometry::GeometryCollection { geometries: vec![] });
+    let mut properties = match serde_json::to_value(&cell) {
+      Ok(Value::Object(properties)) => properties,
+      _ => Map::new(),
+    };
+    properties.remove("cell");
+
+    Feature::new(Some(cell.cell), geometry, properties)
+  }
+}
+
+/// A cell of the geodata of one geotype, as tiles cluster it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeotypeCell {
+  pub geotype: String,
+  #[serde(flatten)]
+  pub cell: GridCell,
+}
+
+/// The stages summarizing matched geodata into the first `limit` cells of
+/// `precision` characters, in geohash order.
+pub fn cells(precision: usize, limit: i64) -> Vec<Document> {
+  summarize(cell(precision).into(), doc! {}, limit)
+}
+
+/// Like `cells`, with a cell per geotype.
+pub fn geotype_cells(precision: usize, limit: i64) -> Vec<Document> {
+  let id = doc! { "geotype": "$geotype", "cell": cell(precision) };
+  let fields = doc! { "_id": 0, "geotype": "$_id.geotype", "cell": "$_id.cell" };
+  summarize(id.into(), fields, limit)
+}
+
+fn cell(precision: usize) -> Document {
+  doc! { "$substrCP": ["$geohash", 0, precision as i64] }
+}
+
+fn summarize(id: Bson, mut fields: Document, limit: i64) -> Vec<Document> {
+  fields.extend(doc! {
+    "count": 1,
+    "min": 1,
+    "max": 1,
+    "mean": 1,
+    "weighted_mean": {
+      "$cond": [{ "$gt": ["$weight", 0] }, { "$divide": ["$weighted", "$weight"] }, null],
+    },
+  });
+  vec![
+    doc! { "$match": { "geohash": { "$ne": null } } },
+    doc! { "$group": {
+      "_id": id,
+      "count": { "$sum": 1 },
+      "min": { "$min": "$value" },
+      "max": { "$max": "$value" },
+      "mean": { "$avg": "$value" },
+      "weighted": { "$sum": { "$multiply": ["$value", "$quality"] } },
+      "weight": { "$sum": "$quality" },
+    } },
+    doc! { "$sort": { "_id": 1 } },
+    doc! { "$limit": limit },
+    doc! { "$project": fields },
+  ]
+}
@@ -2,6 +2,10 @@ use base16ct;
 use sha3::{Digest, Keccak256};
 
 pub fn hash(data: &str) -> String {
+  hash_bytes(data.as_bytes())
+}
+
+pub fn hash_bytes(data: &[u8]) -> String {
   let mut hasher = Keccak256::new();
   hasher.update(data);
 
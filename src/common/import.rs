@@ -0,0 +1,265 @@
+//! Parsing of bulk geodata imports. NDJSON and CSV are read line by line as
+//! the body streams in, a GeoJSON FeatureCollection is read whole. Each row
+//! is numbered from 1 and either holds a record to insert, or the reasons it
+//! was rejected.
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::common::date::{self, Interval};
+use crate::common::geojson::{self, Geometry};
+use crate::errors::{BadRequest, Error, FieldError};
+use crate::models::geodata::Location;
+
+const CSV_COLUMNS: [&str; 6] = ["lon", "lat", "geotype", "value", "source", "quality"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+  NdJson,
+  GeoJson,
+  Csv,
+}
+
+impl Format {
+  /// The format of a body of `content_type`, parameters such as the charset
+  /// aside.
+  pub fn from_content_type(content_type: &str) -> Option<Self> {
+    let media_type = content_type.split(';').next().unwrap_or("").trim();
+    match media_type.to_ascii_lowercase().as_str() {
+      "application/x-ndjson" | "application/ndjson" => Some(Format::NdJson),
+      geojson::MEDIA_TYPE | "application/json" => Some(Format::GeoJson),
+      "text/csv" => Some(Format::Csv),
+      _ => None,
+    }
+  }
+}
+
+/// A record to import, shaped like the body of a single geodata create.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportRow {
+  pub location: Location,
+  pub geotype: String,
+  pub value: f64,
+  pub source: String,
+  pub quality: i32,
+  #[serde(default, with = "date::optional_interval")]
+  pub observed_at: Option<Interval>,
+}
+
+impl ImportRow {
+  /// Every problem of the row. The location is reported under
+  /// `location_field`.
+  pub fn errors(&self, location_field: &str) -> Vec<FieldError> {
+    let mut errors = self.location.errors(location_field);
+    if self.geotype.trim().is_empty() {
+      errors.push(field_error("geotype", "Geotype is required"));
+    }
+    if self.source.trim().is_empty() {
+      errors.push(field_error("source", "Source is required"));
+    }
+    if !self.value.is_finite() {
+      errors.push(field_error("value", "Value must be a finite number"));
+    }
+
+    errors
+  }
+}
+
+/// A row ready to insert, or the reasons it can't be.
+pub type ParsedRow = Result<ImportRow, Vec<FieldError>>;
+
+/// Parses and validates a line of NDJSON.
+pub fn parse_ndjson_line(line: &str) -> ParsedRow {
+  let row: ImportRow =
+    serde_json::from_str(line).map_err(|err| vec![field_error("row", &err.to_string())])?;
+  validated(row, "location")
+}
+
+/// Positions of the columns of a CSV import, read from its header row.
+/// Columns are matched by name, in any order and case. `longitude`, `lng`
+/// and `latitude` are accepted for `lon` and `lat`, `observed_at` is
+/// optional, and other columns are ignored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvHeader {
+  columns: usize,
+  // Indexes of `CSV_COLUMNS`.
+  positions: [usize; 6],
+  observed_at: Option<usize>,
+}
+
+impl CsvHeader {
+  pub fn parse(line: &str) -> Result<Self, Error> {
+    let names = split_csv_line(line).map_err(|message| invalid("header", message))?;
+    let names = names
+      .iter()
+      .map(|name| match name.trim().to_ascii_lowercase().as_str() {
+        "longitude" | "lng" => "lon".to_owned(),
+        "latitude" => "lat".to_owned(),
+        name => name.to_owned(),
+      })
+      .collect::<Vec<String>>();
+
+    let mut positions = [0; 6];
+    for (i, column) in CSV_COLUMNS.iter().enumerate() {
+      positions[i] = names
+        .iter()
+        .position(|name| name == column)
+        .ok_or_else(|| invalid("header", &format!("Missing column {}", column)))?;
+    }
+
+    Ok(Self {
+      columns: names.len(),
+      positions,
+      observed_at: names.iter().position(|name| name == "observed_at"),
+    })
+  }
+
+  /// Parses and validates a CSV row. The location is a Point.
+  pub fn parse_row(&self, line: &str) -> ParsedRow {
+    let fields = split_csv_line(line).map_err(|message| vec![field_error("row", message)])?;
+    if fields.len() != self.columns {
+      let message = format!("Expected {} columns, found {}", self.columns, fields.len());
+      return Err(vec![field_error("row", &message)]);
+    }
+
+    let mut errors = vec![];
+    let field = |i: usize| fields[self.positions[i]].trim();
+    let lon = parse_number::<f64>(field(0), CSV_COLUMNS[0], &mut errors);
+    let lat = parse_number::<f64>(field(1), CSV_COLUMNS[1], &mut errors);
+    let value = parse_number::<f64>(field(3), CSV_COLUMNS[3], &mut errors);
+    let quality = parse_number::<i32>(field(5), CSV_COLUMNS[5], &mut errors);
+    let observed_at = match self.observed_at.map(|i| fields[i].trim()) {
+      Some(observed_at) if !observed_at.is_empty() => Interval::parse(observed_at)
+        .map_err(|message| errors.push(field_error("observed_at", &message)))
+        .ok(),
+      _ => None,
+    };
+    match (lon, lat, value, quality) {
+      (Some(lon), Some(lat), Some(value), Some(quality)) if errors.is_empty() => {
+        let row = ImportRow {
+          location: Geometry::Point {
+            coordinates: vec![lon, lat],
+          },
+          geotype: field(2).to_owned(),
+          value,
+          source: field(4).to_owned(),
+          quality,
+          observed_at,
+        };
+        validated(row, "location")
+      }
+      _ => Err(errors),
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+struct ImportProperties {
+  geotype: String,
+  value: f64,
+  source: String,
+  quality: i32,
+  #[serde(default, with = "date::optional_interval")]
+  observed_at: Option<Interval>,
+}
+
+/// Parses a FeatureCollection into a row per feature. The geometry of a
+/// feature is the location, and its properties hold the other fields.
+pub fn parse_feature_collection(body: &[u8]) -> Result<Vec<ParsedRow>, Error> {
+  let collection: Value = serde_json::from_slice(body)
+    .map_err(|err| invalid("body", &format!("Invalid GeoJSON: {}", err)))?;
+  if collection["type"] != "FeatureCollection" {
+    return Err(invalid("type", "Import a FeatureCollection"));
+  }
+  let features = collection["features"]
+    .as_array()
+    .ok_or_else(|| invalid("features", "Features must be an array"))?;
+
+  Ok(features.iter().map(parse_feature).collect())
+}
+
+fn parse_feature(feature: &Value) -> ParsedRow {
+  let mut errors = vec![];
+  if feature["type"] != "Feature" {
+    errors.push(field_error("type", "Expected a Feature"));
+  }
+  let geometry = Geometry::deserialize(&feature["geometry"])
+    .map_err(|err| errors.push(field_error("geometry", &err.to_string())))
+    .ok();
+  let properties = ImportProperties::deserialize(&feature["properties"])
+    .map_err(|err| errors.push(field_error("properties", &err.to_string())))
+    .ok();
+
+  match (geometry, properties) {
+    (Some(geometry), Some(properties)) if errors.is_empty() => {
+      let row = ImportRow {
+        location: geometry,
+        geotype: properties.geotype,
+        value: properties.value,
+        source: properties.source,
+        quality: properties.quality,
+        observed_at: properties.observed_at,
+      };
+      validated(row, "geometry")
+    }
+    _ => Err(errors),
+  }
+}
+
+fn validated(row: ImportRow, location_field: &str) -> ParsedRow {
+  let errors = row.errors(location_field);
+  if errors.is_empty() {
+    return Ok(row);
+  }
+  Err(errors)
+}
+
+/// Splits a CSV line into its fields. Fields may be quoted to hold commas,
+/// with quotes doubled inside. Quoted line breaks aren't supported, as rows
+/// are read line by line.
+pub fn split_csv_line(line: &str) -> Result<Vec<String>, &'static str> {
+  let mut fields = vec![];
+  let mut field = String::new();
+  let mut quoted = false;
+  let mut chars = line.chars().peekable();
+  while let Some(c) = chars.next() {
+    match c {
+      '"' if quoted && chars.peek() == Some(&'"') => {
+        field.push('"');
+        chars.next();
+      }
+      '"' if quoted => quoted = false,
+      '"' if field.is_empty() => quoted = true,
+      ',' if !quoted => fields.push(std::mem::take(&mut field)),
+      c => field.push(c),
+    }
+  }
+  if quoted {
+    return Err("Unterminated quoted field");
+  }
+
+  fields.push(field);
+  Ok(fields)
+}
+
+fn parse_number<N: std::str::FromStr>(
+  field: &str,
+  column: &str,
+  errors: &mut Vec<FieldError>,
+) -> Option<N> {
+  let number = field.parse().ok();
+  if number.is_none() {
+    errors.push(field_error(column, &format!("Invalid number {:?}", field)));
+  }
+  number
+}
+
+fn field_error(field: &str, message: &str) -> FieldError {
+  FieldError {
+    field: field.to_owned(),
+    message: message.to_owned(),
+  }
+}
+
+fn invalid(field: &str, message: &str) -> Error {
+  Error::BadRequest(BadRequest::new(field.to_owned(), message.to_owned()))
+}
@@ -0,0 +1,168 @@
+//! Interpolation of point values onto a regular grid of longitudes and
+//! latitudes. Distances are great circle distances in meters.
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+use crate::common::geojson::{BoundingBox, EARTH_RADIUS};
+
+/// What ESRI ASCII grids write for cells without a value.
+const NODATA: f64 = -9999.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Method {
+  /// Inverse distance weighting: the mean of the values, weighted by their
+  /// inverse distance to the power `power`.
+  Idw,
+  /// The value of the nearest point.
+  Nearest,
+}
+
+/// A value observed at a position.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+  pub lon: f64,
+  pub lat: f64,
+  pub value: f64,
+}
+
+/// How to interpolate. Cells only take values from points within `radius`
+/// meters of their center, or from every point when there's no radius.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Parameters {
+  pub method: Method,
+  pub power: f64,
+  pub radius: Option<f64>,
+}
+
+/// A grid of `nrows` rows of `ncols` cells of `resolution` degrees, with
+/// its northwest corner at `west`, `north`. Rows run north to south, as in
+/// ESRI ASCII grids. Cells without points in reach have no value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Grid {
+  pub west: f64,
+  pub north: f64,
+  pub resolution: f64,
+  pub ncols: usize,
+  pub nrows: usize,
+  pub values: Vec<Vec<Option<f64>>>,
+}
+
+impl Grid {
+  /// The size of the grid covering `bbox` with cells of `resolution`
+  /// degrees, as columns and rows.
+  pub fn size(bbox: &BoundingBox, resolution: f64) -> (usize, usize) {
+    let cells = |degrees: f64| ((degrees / resolution) - 1e-9).ceil().max(1.0) as usize;
+    (cells(bbox.east - bbox.west), cells(bbox.north - bbox.south))
+  }
+
+  /// The center of a cell.
+  pub fn center(&self, row: usize, col: usize) -> (f64, f64) {
+    (
+      self.west + (col as f64 + 0.5) * self.resolution,
+      self.north - (row as f64 + 0.5) * self.resolution,
+    )
+  }
+
+  /// A line of `lon,lat,value` per cell center, the value empty when
+  /// missing.
+  pub fn to_csv(&self) -> String {
+    let mut csv = String::from("lon,lat,value\n");
+    for (row, values) in self.values.iter().enumerate() {
+      for (col, value) in values.iter().enumerate() {
+        let (lon, lat) = self.center(row, col);
+        let value = value.map(|value| value.to_string()).unwrap_or_default();
+        writeln!(csv, "{},{},{}", lon, lat, value).unwrap();
+      }
+    }
+    csv
+  }
+
+  /// The grid as an ESRI ASCII grid (.asc).
+  pub fn to_asc(&self) -> String {
+    let south = self.north - self.nrows as f64 * self.resolution;
+    let mut asc = String::new();
+    writeln!(asc, "ncols {}", self.ncols).unwrap();
+    writeln!(asc, "nrows {}", self.nrows).unwrap();
+    writeln!(asc, "xllcorner {}", self.west).unwrap();
+    writeln!(asc, "yllcorner {}", south).unwrap();
+    writeln!(asc, "cellsize {}", self.resolution).unwrap();
+    writeln!(asc, "NODATA_value {}", NODATA).unwrap();
+    for values in &self.values {
+      let values = values
+        .iter()
+        .map(|value| value.unwrap_or(NODATA).to_string())
+        .collect::<Vec<String>>();
+      writeln!(asc, "{}", values.join(" ")).unwrap();
+    }
+    asc
+  }
+}
+
+/// Interpolates `samples` onto the grid of cells of `resolution` degrees
+/// covering `bbox`.
+pub fn interpolate(
+  samples: &[Sample],
+  bbox: &BoundingBox,
+  resolution: f64,
+  parameters: &Parameters,
+) -> Grid {
+  let (ncols, nrows) = Grid::size(bbox, resolution);
+  let mut grid = Grid {
+    west: bbox.west,
+    north: bbox.north,
+    resolution,
+    ncols,
+    nrows,
+    values: vec![],
+  };
+  grid.values = (0..nrows)
+    .map(|row| {
+      (0..ncols)
+        .map(|col| {
+          let (lon, lat) = grid.center(row, col);
+          estimate(samples, lon, lat, parameters)
+        })
+        .collect()
+    })
+    .collect();
+  grid
+}
+
+fn estimate(samples: &[Sample], lon: f64, lat: f64, parameters: &Parameters) -> Option<f64> {
+  let in_reach = samples
+    .iter()
+    .map(|sample| (distance(lon, lat, sample.lon, sample.lat), sample.value))
+    .filter(|(distance, _)| parameters.radius.map_or(true, |radius| *distance <= radius));
+
+  match parameters.method {
+    Method::Nearest => in_reach
+      .min_by(|a, b| a.0.total_cmp(&b.0))
+      .map(|(_, value)| value),
+    Method::Idw => {
+      let (mut weighted, mut weights) = (0.0, 0.0);
+      for (distance, value) in in_reach {
+        // Cells on a point take its value.
+        if distance < 1e-6 {
+          return Some(value);
+        }
+        let weight = 1.0 / distance.powf(parameters.power);
+        weighted += weight * value;
+        weights += weight;
+      }
+      if weights == 0.0 {
+        return None;
+      }
+      Some(weighted / weights)
+    }
+  }
+}
+
+/// The haversine distance in meters between two positions.
+pub fn distance(lon1: f64, lat1: f64, lon2: f64, lat2: f64) -> f64 {
+  let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+  let dlat = lat2 - lat1;
+  let dlon = (lon2 - lon1).to_radians();
+  let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+  2.0 * EARTH_RADIUS * a.sqrt().min(1.0).asin()
+}
@@ -0,0 +1,171 @@
+use anyhow::{anyhow, Context as _};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine as _;
+use jsonwebtoken::jwk::{
+  AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, OctetKeyPairParameters,
+  OctetKeyPairType, PublicKeyUse,
+};
+use jsonwebtoken::{errors::Error, Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation};
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashMap;
+use std::fs;
+
+use crate::settings::Auth;
+
+// An Ed25519 SubjectPublicKeyInfo is a fixed 12 byte prefix and the key.
+const ED25519_KEY_LEN: usize = 32;
+const ED25519_SPKI_LEN: usize = 44;
+
+/// Keys used to sign and verify access tokens.
+///
+/// With `auth.keys` configured, tokens are signed with EdDSA by the key named
+/// in `auth.signing_key` and carry its `kid`. Every configured public key
+/// verifies tokens, so retired keys can keep verifying until their tokens
+/// expire, and all public keys are published as a JWKS. Without keys, tokens
+/// fall back to HS256 with `auth.secret`, which is only allowed in the
+/// `development` environment.
+#[derive(Clone)]
+pub struct KeyStore {
+  header: Header,
+  encoding: EncodingKey,
+  decoding: HashMap<String, DecodingKey>,
+  fallback: Option<DecodingKey>,
+  jwks: JwkSet,
+}
+
+impl KeyStore {
+  pub fn from_settings(auth: &Auth, environment: &str) -> anyhow::Result<Self> {
+    if auth.keys.is_empty() {
+      if environment != "development" {
+        return Err(anyhow!(
+          "auth.keys must list a signing key in the {} environment",
+          environment
+        ));
+      }
+      return Ok(Self {
+        header: Header::default(),
+        encoding: EncodingKey::from_secret(auth.secret.as_ref()),
+        decoding: HashMap::new(),
+        fallback: Some(DecodingKey::from_secret(auth.secret.as_ref())),
+        jwks: JwkSet { keys: vec![] },
+      });
+    }
+
+    let signing_kid = match &auth.signing_key {
+      Some(kid) => kid.clone(),
+      None => auth
+        .keys
+        .iter()
+        .find(|key| key.private_key.is_some())
+        .map(|key| key.kid.clone())
+        .ok_or_else(|| anyhow!("no key with a private_key to sign tokens"))?,
+    };
+
+    let mut encoding = None;
+    let mut decoding = HashMap::new();
+    let mut jwks = JwkSet { keys: vec![] };
+    for key in &auth.keys {
+      let public_pem = fs::read(&key.public_key)
+        .with_context(|| format!("failed to read public key {}", key.public_key))?;
+      let public_key = ed25519_public_key(&public_pem)
+        .with_context(|| format!("invalid Ed25519 public key {}", key.public_key))?;
+
+      decoding.insert(key.kid.clone(), DecodingKey::from_ed_der(&public_key));
+      jwks.keys.push(jwk(&key.kid, &public_key));
+
+      if key.kid == signing_kid {
+        let private_path = key
+          .private_key
+          .as_ref()
+          .ok_or_else(|| anyhow!("signing key {} has no private_key", key.kid))?;
+        let private_pem = fs::read(private_path)
+          .with_context(|| format!("failed to read private key {}", private_path))?;
+        encoding = Some(EncodingKey::from_ed_pem(&private_pem)?);
+      }
+    }
+
+    let encoding = encoding.ok_or_else(|| anyhow!("signing key {} is not configured", signing_kid))?;
+    let mut header = Header::new(Algorithm::EdDSA);
+    header.kid = Some(signing_kid.clone());
+    verify_pair(&header, &encoding, &decoding[&signing_kid])
+      .with_context(|| format!("public key of {} doesn't match its private key", signing_kid))?;
+
+    Ok(Self {
+      header,
+      encoding,
+      decoding,
+      fallback: None,
+      jwks,
+    })
+  }
+
+  pub fn encode<T: Serialize>(&self, claims: &T) -> Result<String, Error> {
+    jsonwebtoken::encode(&self.header, claims, &self.encoding)
+  }
+
+  /// Verifies a token with the key named by its `kid` header.
+  pub fn decode<T: DeserializeOwned>(&self, token: &str) -> Result<TokenData<T>, Error> {
+    if let Some(key) = &self.fallback {
+      return jsonwebtoken::decode::<T>(token, key, &Validation::default());
+    }
+
+    let header = jsonwebtoken::decode_header(token)?;
+    let key = header
+      .kid
+      .as_ref()
+      .and_then(|kid| self.decoding.get(kid))
+      .ok_or_else(|| Error::from(jsonwebtoken::errors::ErrorKind::InvalidToken))?;
+
+    jsonwebtoken::decode::<T>(token, key, &Validation::new(Algorithm::EdDSA))
+  }
+
+  /// Public verification keys, empty when signing with the shared secret.
+  pub fn jwks(&self) -> &JwkSet {
+    &self.jwks
+  }
+}
+
+// Signs a short-lived token and verifies it, so a private key paired with
+// the wrong public key fails at startup rather than on every request.
+fn verify_pair(
+  header: &Header,
+  encoding: &EncodingKey,
+  decoding: &DecodingKey,
+) -> anyhow::Result<()> {
+  let claims = serde_json::json!({ "exp": chrono::Utc::now().timestamp() + 60 });
+  let token = jsonwebtoken::encode(header, &claims, encoding)?;
+  jsonwebtoken::decode::<serde_json::Value>(&token, decoding, &Validation::new(Algorithm::EdDSA))?;
+
+  Ok(())
+}
+
+// Extracts the raw key from a PEM encoded Ed25519 SubjectPublicKeyInfo.
+fn ed25519_public_key(pem: &[u8]) -> anyhow::Result<Vec<u8>> {
+  let pem = std::str::from_utf8(pem)?;
+  let body = pem
+    .lines()
+    .filter(|line| !line.starts_with("-----"))
+    .collect::<String>();
+  let der = STANDARD.decode(body.trim())?;
+  if der.len() != ED25519_SPKI_LEN {
+    return Err(anyhow!("expected {} DER bytes, found {}", ED25519_SPKI_LEN, der.len()));
+  }
+
+  Ok(der[ED25519_SPKI_LEN - ED25519_KEY_LEN..].to_vec())
+}
+
+fn jwk(kid: &str, public_key: &[u8]) -> Jwk {
+  Jwk {
+    common: CommonParameters {
+      public_key_use: Some(PublicKeyUse::Signature),
+      algorithm: Some(Algorithm::EdDSA),
+      key_id: Some(kid.to_owned()),
+      ..CommonParameters::default()
+    },
+    algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+      key_type: OctetKeyPairType::OctetKeyPair,
+      curve: EllipticCurve::Ed25519,
+      x: URL_SAFE_NO_PAD.encode(public_key),
+    }),
+  }
+}
@@ -0,0 +1,61 @@
+//! One-off data migrations, applied at startup or in the background. Each
+//! is recorded once applied so later startups skip it, and is idempotent, as
+//! replicas starting together may apply it side by side.
+use bson::doc;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::common::models::ModelExt;
+use crate::errors::Error;
+use crate::models::Models;
+
+const REGISTER_GEOTYPES_IN_USE: &str = "register_geotypes_in_use";
+const BACKFILL_GEOHASHES: &str = "backfill_geohashes";
+// Geodata given a geohash per bulk update.
+const BACKFILL_BATCH: i64 = 1000;
+
+pub async fn run(models: &Models) -> Result<(), Error> {
+  // Geodata may only be revised or imported with registered geotypes.
+  if !models.migration.is_applied(REGISTER_GEOTYPES_IN_USE).await? {
+    let count = models.geotype.register_in_use(&models.geodata).await?;
+    info!("Registered {} geotypes used by geodata", count);
+    models.migration.mark_applied(REGISTER_GEOTYPES_IN_USE).await?;
+  }
+
+  Ok(())
+}
+
+/// Stores the geohash of geodata from before geohashes were kept, in the
+/// background as it may take long. Geodata without one is left out of
+/// grids meanwhile.
+pub fn spawn_backfill(models: Models) -> JoinHandle<()> {
+  tokio::spawn(async move {
+    if let Err(err) = backfill_geohashes(&models).await {
+      error!("Failed to backfill geohashes: {}", err);
+    }
+  })
+}
+
+pub async fn backfill_geohashes(models: &Models) -> Result<(), Error> {
+  if models.migration.is_applied(BACKFILL_GEOHASHES).await? {
+    return Ok(());
+  }
+
+  let mut count = 0;
+  loop {
+    let updated = models.geodata.backfill_geohashes(BACKFILL_BATCH).await?;
+    if updated == 0 {
+      break;
+    }
+    count += updated;
+  }
+  info!("Stored the geohash of {} geodata", count);
+
+  // Records a batch failed to update are tried again on the next start.
+  let missing = doc! { "geohash": { "$exists": false } };
+  if models.geodata.exists(missing).await? {
+    warn!("Some geodata is still without a geohash");
+    return Ok(());
+  }
+  models.migration.mark_applied(BACKFILL_GEOHASHES).await
+}
@@ -1,7 +1,25 @@
+pub mod account_cache;
 pub mod authenticate_request;
 pub mod date;
 pub mod models;
 pub mod token;
 pub mod hasher;
+pub mod keys;
+pub mod secret;
+pub mod notifier;
+pub mod totp;
+pub mod tenant;
+pub mod pagination;
+pub mod geojson;
+pub mod mvt;
+pub mod ogc;
+pub mod geohash;
+pub mod grid;
+pub mod object_id;
 pub mod anchor;
+pub mod anchor_queue;
+pub mod import;
+pub mod export;
+pub mod interpolation;
+pub mod series;
 pub mod msg;
\ No newline at end of file
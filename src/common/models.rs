@@ -17,6 +17,7 @@ use wither::mongodb::results::UpdateResult;
 use wither::Model as WitherModel;
 use wither::ModelCursor;
 
+use crate::common::pagination::{Page, Pagination};
 use crate::database::Database;
 use crate::errors::BadRequest;
 use crate::errors::Error;
@@ -40,6 +41,34 @@ pub trait ModelExt {
     Ok(model)
   }
 
+  /// Validates and inserts `models` in a single round trip, returning them
+  /// with their ids set.
+  async fn insert_many(&self, mut models: Vec<Self::T>) -> Result<Vec<Self::T>, Error> {
+    let mut documents = Vec::with_capacity(models.len());
+    for model in &models {
+      model
+        .validate()
+        .map_err(|_error| Error::BadRequest(BadRequest::empty()))?;
+      documents.push(model.document_from_instance().map_err(Error::Wither)?);
+    }
+    if documents.is_empty() {
+      return Ok(models);
+    }
+
+    let db = self.get_database();
+    let result = Self::T::collection(&db.conn)
+      .insert_many(documents, None)
+      .await
+      .map_err(Error::Mongo)?;
+    for (index, id) in result.inserted_ids {
+      if let Bson::ObjectId(id) = id {
+        models[index].set_id(id);
+      }
+    }
+
+    Ok(models)
+  }
+
   async fn find_by_id(&self, id: &ObjectId) -> Result<Option<Self::T>, Error> {
     let db = self.get_database();
     Self::T::find_one(&db.conn, doc! { "_id": id }, None)
@@ -80,6 +109,20 @@ pub trait ModelExt {
       .map_err(Error::Wither)
   }
 
+  /// A page of the documents matching `query`.
+  async fn find_page(
+    &self,
+    query: Document,
+    pagination: &Pagination,
+  ) -> Result<Page<Self::T>, Error> {
+    let total = self.count(query.clone()).await?;
+    let items = self
+      .find(pagination.filter(query)?, pagination.options())
+      .await?;
+
+    pagination.page(items, total)
+  }
+
   async fn find_one_and_update(
     &self,
     query: Document,
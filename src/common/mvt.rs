@@ -0,0 +1,307 @@
+//! Mapbox Vector Tiles (version 2.1): the tiles of the Web Mercator pyramid,
+//! and an encoder of their protocol buffers. Only what tiles of geodata
+//! need is written: layers of features with tags and geometry commands.
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+use crate::common::geojson::{BoundingBox, Geometry, Position};
+
+pub const MEDIA_TYPE: &str = "application/vnd.mapbox-vector-tile";
+
+/// Tile coordinates span 0 to `EXTENT` across a tile.
+pub const EXTENT: u32 = 4096;
+
+/// Latitudes Web Mercator reaches, beyond which tiles are cut.
+pub const MAX_LATITUDE: f64 = 85.051_128_779_806_59;
+
+/// The address of a tile: its zoom level, then its column and row counted
+/// from the northwest.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileId {
+  pub z: u32,
+  pub x: u32,
+  pub y: u32,
+}
+
+impl TileId {
+  /// The tile, unless it lies outside the pyramid. Zoom levels stop at 24.
+  pub fn new(z: u32, x: u32, y: u32) -> Option<Self> {
+    if z > 24 || x >= 1 << z || y >= 1 << z {
+      return None;
+    }
+    Some(Self { z, x, y })
+  }
+
+  /// The extent of the tile in degrees.
+  pub fn bounds(&self) -> BoundingBox {
+    let tiles = f64::from(1u32 << self.z);
+    let lon = |x: u32| f64::from(x) / tiles * 360.0 - 180.0;
+    let lat = |y: u32| {
+      let n = PI * (1.0 - 2.0 * f64::from(y) / tiles);
+      n.sinh().atan().to_degrees()
+    };
+    BoundingBox {
+      west: lon(self.x),
+      south: lat(self.y + 1),
+      east: lon(self.x + 1),
+      north: lat(self.y),
+    }
+  }
+
+  /// The tile coordinates of a position, outside `0..EXTENT` when it lies
+  /// outside the tile.
+  pub fn project(&self, lon: f64, lat: f64) -> (i32, i32) {
+    let tiles = f64::from(1u32 << self.z);
+    let lat = lat.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+    let x = (lon + 180.0) / 360.0 * tiles;
+    let y = (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / PI) / 2.0 * tiles;
+    let extent = f64::from(EXTENT);
+    (
+      ((x - f64::from(self.x)) * extent).round() as i32,
+      ((y - f64::from(self.y)) * extent).round() as i32,
+    )
+  }
+
+  /// Whether tile coordinates lie within the tile.
+  pub fn contains(&self, (x, y): (i32, i32)) -> bool {
+    let extent = EXTENT as i32;
+    (0..extent).contains(&x) && (0..extent).contains(&y)
+  }
+}
+
+/// A feature attribute.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+  String(String),
+  Double(f64),
+  Int(i64),
+  Bool(bool),
+}
+
+impl Value {
+  fn encode(&self) -> Vec<u8> {
+    let mut buffer = vec![];
+    match self {
+      Value::String(value) => write_bytes(&mut buffer, 1, value.as_bytes()),
+      Value::Double(value) => {
+        write_key(&mut buffer, 3, 1);
+        buffer.extend_from_slice(&value.to_le_bytes());
+      }
+      Value::Int(value) => {
+        write_key(&mut buffer, 6, 0);
+        write_varint(&mut buffer, zigzag(*value));
+      }
+      Value::Bool(value) => {
+        write_key(&mut buffer, 7, 0);
+        write_varint(&mut buffer, u64::from(*value));
+      }
+    }
+    buffer
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GeomType {
+  Point = 1,
+  LineString = 2,
+  Polygon = 3,
+}
+
+/// A layer of features, which share its tables of attribute keys and
+/// values.
+#[derive(Debug, Default)]
+pub struct Layer {
+  name: String,
+  features: Vec<Vec<u8>>,
+  keys: Vec<String>,
+  values: Vec<Vec<u8>>,
+  key_indexes: HashMap<String, u32>,
+  value_indexes: HashMap<Vec<u8>, u32>,
+}
+
+impl Layer {
+  pub fn new(name: &str) -> Self {
+    Self {
+      name: name.to_owned(),
+      ..Default::default()
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.features.is_empty()
+  }
+
+  /// Adds a feature of a geometry projected into `tile`. A
+  /// GeometryCollection adds a feature per member, each with the same
+  /// attributes.
+  pub fn add(&mut self, tile: &TileId, geometry: &Geometry, attributes: &[(&str, Value)]) {
+    let position = |position: &Position| tile.project(position[0], position[1]);
+    let line = |line: &Vec<Position>| line.iter().map(position).collect::<Vec<(i32, i32)>>();
+    let polygon = |rings: &Vec<Vec<Position>>| rings.iter().map(line).collect::<Vec<_>>();
+    let (geom_type, parts, closed) = match geometry {
+      Geometry::Point { coordinates } => {
+        (GeomType::Point, vec![vec![position(coordinates)]], false)
+      }
+      Geometry::MultiPoint { coordinates } => (GeomType::Point, vec![line(coordinates)], false),
+      Geometry::LineString { coordinates } => {
+        (GeomType::LineString, vec![line(coordinates)], false)
+      }
+      Geometry::MultiLineString { coordinates } => {
+        (GeomType::LineString, coordinates.iter().map(line).collect(), false)
+      }
+      Geometry::Polygon { coordinates } => (GeomType::Polygon, polygon(coordinates), true),
+      Geometry::MultiPolygon { coordinates } => {
+        (GeomType::Polygon, coordinates.iter().flat_map(polygon).collect(), true)
+      }
+      Geometry::GeometryCollection { geometries } => {
+        for geometry in geometries {
+          self.add(tile, geometry, attributes);
+        }
+        return;
+      }
+    };
+
+    let tags = attributes
+      .iter()
+      .flat_map(|(key, value)| [self.key_index(key), self.value_index(value)])
+      .collect::<Vec<u32>>();
+    let commands = match geom_type {
+      GeomType::Point => point_commands(&parts[0]),
+      _ => path_commands(&parts, closed),
+    };
+    let mut feature = vec![];
+    write_packed(&mut feature, 2, &tags);
+    write_key(&mut feature, 3, 0);
+    write_varint(&mut feature, geom_type as u64);
+    write_packed(&mut feature, 4, &commands);
+    self.features.push(feature);
+  }
+
+  fn key_index(&mut self, key: &str) -> u32 {
+    if let Some(index) = self.key_indexes.get(key) {
+      return *index;
+    }
+    let index = self.keys.len() as u32;
+    self.keys.push(key.to_owned());
+    self.key_indexes.insert(key.to_owned(), index);
+    index
+  }
+
+  fn value_index(&mut self, value: &Value) -> u32 {
+    let encoded = value.encode();
+    if let Some(index) = self.value_indexes.get(&encoded) {
+      return *index;
+    }
+    let index = self.values.len() as u32;
+    self.values.push(encoded.clone());
+    self.value_indexes.insert(encoded, index);
+    index
+  }
+
+  fn encode(&self) -> Vec<u8> {
+    let mut buffer = vec![];
+    write_key(&mut buffer, 15, 0);
+    write_varint(&mut buffer, 2);
+    write_bytes(&mut buffer, 1, self.name.as_bytes());
+    for feature in &self.features {
+      write_bytes(&mut buffer, 2, feature);
+    }
+    for key in &self.keys {
+      write_bytes(&mut buffer, 3, key.as_bytes());
+    }
+    for value in &self.values {
+      write_bytes(&mut buffer, 4, value);
+    }
+    write_key(&mut buffer, 5, 0);
+    write_varint(&mut buffer, u64::from(EXTENT));
+    buffer
+  }
+}
+
+/// Encodes a tile of `layers`, leaving out the empty ones.
+pub fn encode(layers: &[Layer]) -> Vec<u8> {
+  let mut buffer = vec![];
+  for layer in layers.iter().filter(|layer| !layer.is_empty()) {
+    write_bytes(&mut buffer, 3, &layer.encode());
+  }
+  buffer
+}
+
+const MOVE_TO: u32 = 1;
+const LINE_TO: u32 = 2;
+const CLOSE_PATH: u32 = 7;
+
+fn command(id: u32, count: usize) -> u32 {
+  id | ((count as u32) << 3)
+}
+
+fn point_commands(points: &[(i32, i32)]) -> Vec<u32> {
+  let mut commands = vec![command(MOVE_TO, points.len())];
+  let mut cursor = (0, 0);
+  for point in points {
+    push_delta(&mut commands, &mut cursor, *point);
+  }
+  commands
+}
+
+// Rings of polygons repeat their first position last. Tiles close them
+// with a command instead.
+fn path_commands(paths: &[Vec<(i32, i32)>], closed: bool) -> Vec<u32> {
+  let mut commands = vec![];
+  let mut cursor = (0, 0);
+  for path in paths {
+    let path = if closed {
+      &path[..path.len().saturating_sub(1)]
+    } else {
+      &path[..]
+    };
+    if let Some((first, rest)) = path.split_first() {
+      commands.push(command(MOVE_TO, 1));
+      push_delta(&mut commands, &mut cursor, *first);
+      commands.push(command(LINE_TO, rest.len()));
+      for position in rest {
+        push_delta(&mut commands, &mut cursor, *position);
+      }
+      if closed {
+        commands.push(command(CLOSE_PATH, 1));
+      }
+    }
+  }
+  commands
+}
+
+fn push_delta(commands: &mut Vec<u32>, cursor: &mut (i32, i32), (x, y): (i32, i32)) {
+  commands.push(zigzag(i64::from(x - cursor.0)) as u32);
+  commands.push(zigzag(i64::from(y - cursor.1)) as u32);
+  *cursor = (x, y);
+}
+
+fn zigzag(value: i64) -> u64 {
+  ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn write_key(buffer: &mut Vec<u8>, field: u32, wire_type: u32) {
+  write_varint(buffer, u64::from((field << 3) | wire_type));
+}
+
+fn write_varint(buffer: &mut Vec<u8>, mut value: u64) {
+  while value >= 0x80 {
+    buffer.push(((value & 0x7f) as u8) | 0x80);
+    value >>= 7;
+  }
+  buffer.push(value as u8);
+}
+
+fn write_bytes(buffer: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+  write_key(buffer, field, 2);
+  write_varint(buffer, bytes.len() as u64);
+  buffer.extend_from_slice(bytes);
+}
+
+fn write_packed(buffer: &mut Vec<u8>, field: u32, values: &[u32]) {
+  let mut packed = vec![];
+  for value in values {
+    write_varint(&mut packed, u64::from(*value));
+  }
+  write_bytes(buffer, field, &packed);
+}
@@ -0,0 +1,28 @@
+use async_trait::async_trait;
+use std::sync::Arc;
+use tracing::info;
+
+use crate::errors::Error;
+use crate::models::account::Account;
+
+/// Delivers messages to account holders, such as password reset tokens.
+/// Implement it for the transport of your deployment (mail, SMS, ...) and
+/// pass it to `Context::with_notifier`.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+  async fn send_password_reset(&self, account: &Account, token: &str) -> Result<(), Error>;
+}
+
+pub type SharedNotifier = Arc<dyn Notifier>;
+
+/// Writes notifications to the log. Only meant for development, since reset
+/// tokens end up in the logs.
+pub struct LogNotifier;
+
+#[async_trait]
+impl Notifier for LogNotifier {
+  async fn send_password_reset(&self, account: &Account, token: &str) -> Result<(), Error> {
+    info!("Password reset token for {}: {}", account.email, token);
+    Ok(())
+  }
+}
@@ -0,0 +1,14 @@
+use bson::oid::ObjectId;
+use serde::Serializer;
+
+/// Serializes an optional id as a hex string, like
+/// `serialize_object_id_as_hex_string` does for required ids.
+pub fn serialize_optional_hex<S: Serializer>(
+  id: &Option<ObjectId>,
+  serializer: S,
+) -> Result<S::Ok, S::Error> {
+  match id {
+    Some(id) => serializer.serialize_some(&id.to_hex()),
+    None => serializer.serialize_none(),
+  }
+}
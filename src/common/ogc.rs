@@ -0,0 +1,206 @@
+//! OGC API - Features (Part 1: Core): the documents describing the API and
+//! its collections, and the parsing of its `datetime` parameter. Geodata
+//! is served in collections of one geotype each.
+use bson::{doc, Document};
+use serde::{Deserialize, Serialize};
+
+use crate::common::date::Date;
+use crate::common::geojson::{self, BoundingBox, Link};
+use crate::errors::{BadRequest, Error};
+use crate::models::geotype::Geotype;
+
+/// Where the API is mounted. Links are relative to the host.
+pub const ROOT: &str = "/ogc";
+
+pub const CONFORMANCE: [&str; 2] = [
+  "http://www.opengis.net/spec/ogcapi-features-1/1.0/conf/core",
+  "http://www.opengis.net/spec/ogcapi-features-1/1.0/conf/geojson",
+];
+
+/// Longitudes and latitudes on WGS 84, the only coordinate system served.
+pub const CRS84: &str = "http://www.opengis.net/def/crs/OGC/1.3/CRS84";
+
+const JSON: &str = "application/json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LandingPage {
+  pub title: String,
+  pub description: String,
+  pub links: Vec<Link>,
+}
+
+impl LandingPage {
+  pub fn new() -> Self {
+    Self {
+      title: "dClimate geodata".to_owned(),
+      description: "Geodata served as OGC API Features, a collection per geotype".to_owned(),
+      links: vec![
+        Link::new(ROOT.to_owned(), "self", JSON),
+        Link::new(format!("{}/conformance", ROOT), "conformance", JSON),
+        Link::new(format!("{}/collections", ROOT), "data", JSON),
+      ],
+    }
+  }
+}
+
+impl Default for LandingPage {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Conformance {
+  pub conforms_to: Vec<String>,
+}
+
+impl Conformance {
+  pub fn new() -> Self {
+    Self {
+      conforms_to: CONFORMANCE.iter().map(|class| class.to_string()).collect(),
+    }
+  }
+}
+
+impl Default for Conformance {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Collections {
+  pub links: Vec<Link>,
+  pub collections: Vec<Collection>,
+}
+
+impl Collections {
+  pub fn new(collections: Vec<Collection>) -> Self {
+    let links = vec![Link::new(format!("{}/collections", ROOT), "self", JSON)];
+    Self { links, collections }
+  }
+}
+
+/// The geodata of a geotype. Its extent is the whole world, as geotypes
+/// don't bound where geodata lies.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Collection {
+  pub id: String,
+  pub title: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub description: Option<String>,
+  pub extent: Extent,
+  pub item_type: String,
+  pub crs: Vec<String>,
+  pub links: Vec<Link>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Extent {
+  pub spatial: SpatialExtent,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpatialExtent {
+  pub bbox: Vec<[f64; 4]>,
+  pub crs: String,
+}
+
+impl From<&Geotype> for Collection {
+  fn from(geotype: &Geotype) -> Self {
+    let href = format!("{}/collections/{}", ROOT, geotype.name);
+    Self {
+      id: geotype.name.clone(),
+      title: geotype.name.clone(),
+      description: geotype.description.clone(),
+      extent: Extent {
+        spatial: SpatialExtent {
+          bbox: vec![[-180.0, -90.0, 180.0, 90.0]],
+          crs: CRS84.to_owned(),
+        },
+      },
+      item_type: "feature".to_owned(),
+      crs: vec![CRS84.to_owned()],
+      links: vec![
+        Link::new(href.clone(), "self", JSON),
+        Link::new(format!("{}/items", href), "items", geojson::MEDIA_TYPE),
+      ],
+    }
+  }
+}
+
+/// The box of a `bbox` parameter: `west,south,east,north`, or
+/// `west,south,min,east,north,max` with heights, which geodata doesn't have
+/// and are ignored. A box whose west is east of its east crosses the
+/// antimeridian.
+pub fn bbox(bbox: &str) -> Result<BoundingBox, Error> {
+  let message = "Bounding box must be west,south,east,north, with heights or not";
+  let values = bbox
+    .split(',')
+    .map(|value| value.trim().parse::<f64>())
+    .collect::<Result<Vec<f64>, _>>()
+    .map_err(|_| invalid("bbox", message))?;
+  let (west, south, east, north) = match values.as_slice() {
+    [west, south, east, north] | [west, south, _, east, north, _] => (*west, *south, *east, *north),
+    _ => return Err(invalid("bbox", message)),
+  };
+  if west <= east {
+    return BoundingBox::new(west, south, east, north);
+  }
+
+  // Each side of the antimeridian must be a box of its own.
+  let western = BoundingBox::new(west, south, 180.0, north)?;
+  BoundingBox::new(-180.0, south, east, north)?;
+  Ok(BoundingBox {
+    east: east + 360.0,
+    ..western
+  })
+}
+
+/// The Mongo filter of a `datetime` parameter: an RFC 3339 instant, or an
+/// interval of two joined by a slash, either end open when `..` or empty.
+/// Geodata matches when observed at any time within it, or when created
+/// within it if it has no observation time. Bounds are included.
+pub fn datetime_query(datetime: &str) -> Result<Document, Error> {
+  let bound = |date: &str| match date.trim() {
+    "" | ".." => Ok(None),
+    date => Date::parse_rfc3339_str(date).map(Some).map_err(|_| {
+      let message = format!("{} isn't an RFC 3339 date, e.g. 2022-06-01T00:00:00Z", date);
+      invalid("datetime", &message)
+    }),
+  };
+  let (start, end) = match datetime.split_once('/') {
+    Some((start, end)) => (bound(start)?, bound(end)?),
+    None => {
+      let instant = bound(datetime)?
+        .ok_or_else(|| invalid("datetime", "An instant can't be open"))?;
+      (Some(instant), Some(instant))
+    }
+  };
+  if let (Some(start), Some(end)) = (start, end) {
+    if end < start {
+      return Err(invalid("datetime", "Interval ends before it starts"));
+    }
+  }
+  if start.is_none() && end.is_none() {
+    return Ok(doc! {});
+  }
+
+  let mut observed = doc! {};
+  let mut created = doc! {};
+  if let Some(start) = start {
+    observed.insert("observed_at.end", doc! { "$gte": start });
+    created.insert("$gte", start);
+  }
+  if let Some(end) = end {
+    observed.insert("observed_at.start", doc! { "$lte": end });
+    created.insert("$lte", end);
+  }
+  Ok(doc! { "$or": [observed, { "observed_at": null, "created": created }] })
+}
+
+fn invalid(field: &str, message: &str) -> Error {
+  Error::BadRequest(BadRequest::new(field.to_owned(), message.to_owned()))
+}
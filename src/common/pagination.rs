@@ -0,0 +1,286 @@
+//! Cursor pagination for list endpoints. Pages are ordered by a date field
+//! with `_id` breaking ties, or by `_id` alone, and the opaque cursor holds
+//! the position of the last item returned. Items inserted between requests
+//! are therefore neither repeated nor skipped.
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use axum::http::Uri;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use wither::bson::{self, doc, oid::ObjectId, Bson, Document};
+use wither::mongodb::options::FindOptions;
+
+use crate::common::date::Date;
+use crate::errors::{BadRequest, Error};
+use crate::settings;
+
+const DEFAULT_SORT: &str = "created";
+
+/// Query parameters shared by list endpoints.
+#[derive(Debug, Default, Deserialize)]
+pub struct PageQuery {
+  pub cursor: Option<String>,
+  pub limit: Option<i64>,
+  // `created` or `id`, descending with a leading `-`.
+  pub sort: Option<String>,
+}
+
+/// The envelope list endpoints respond with. `next_cursor` is absent on the
+/// last page, and `total` counts the matches across all pages.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Page<T> {
+  pub items: Vec<T>,
+  pub next_cursor: Option<String>,
+  pub total: u64,
+}
+
+impl<T> Page<T> {
+  pub fn map<U, F>(self, f: F) -> Page<U>
+  where
+    F: FnMut(T) -> U,
+  {
+    Page {
+      items: self.items.into_iter().map(f).collect(),
+      next_cursor: self.next_cursor,
+      total: self.total,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SortKey {
+  // A date field, with `_id` breaking ties.
+  Date(&'static str),
+  Id,
+  // The order of the query itself, such as the distance for `$near`, which
+  // can't be combined with a sort. Pages by offset.
+  Natural,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Cursor {
+  // The sort the cursor was issued for.
+  sort: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  date: Option<i64>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  id: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  offset: Option<u64>,
+}
+
+impl Cursor {
+  fn encode(&self) -> String {
+    URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap())
+  }
+
+  fn decode(cursor: &str) -> Result<Self, Error> {
+    URL_SAFE_NO_PAD
+      .decode(cursor)
+      .ok()
+      .and_then(|json| serde_json::from_slice(&json).ok())
+      .ok_or_else(invalid_cursor)
+  }
+}
+
+#[derive(Debug)]
+pub struct Pagination {
+  pub limit: i64,
+  sort: String,
+  key: SortKey,
+  descending: bool,
+  after: Option<Cursor>,
+}
+
+impl Pagination {
+  /// Pages sorted by `created`, the `date_field` of the collection, or by
+  /// `id`. Defaults to the oldest first.
+  pub fn new(
+    query: &PageQuery,
+    settings: &settings::Pagination,
+    date_field: &'static str,
+  ) -> Result<Self, Error> {
+    let sort = query.sort.clone().unwrap_or_else(|| DEFAULT_SORT.to_owned());
+    let (descending, name) = match sort.strip_prefix('-') {
+      Some(name) => (true, name),
+      None => (false, sort.as_str()),
+    };
+    let key = match name {
+      "created" => SortKey::Date(date_field),
+      "id" => SortKey::Id,
+      _ => return Err(invalid("sort", "Sort by created or id, prefixed with - to reverse")),
+    };
+
+    Self::build(query, settings, sort, key, descending)
+  }
+
+  /// Pages in the order of the query itself.
+  pub fn natural(query: &PageQuery, settings: &settings::Pagination) -> Result<Self, Error> {
+    if query.sort.is_some() {
+      return Err(invalid("sort", "Results can't be sorted"));
+    }
+
+    Self::build(query, settings, String::new(), SortKey::Natural, false)
+  }
+
+  fn build(
+    query: &PageQuery,
+    settings: &settings::Pagination,
+    sort: String,
+    key: SortKey,
+    descending: bool,
+  ) -> Result<Self, Error> {
+    let limit = query.limit.unwrap_or(settings.default_limit);
+    if limit < 1 {
+      return Err(invalid("limit", "Limit must be at least 1"));
+    }
+
+    let after = match &query.cursor {
+      Some(cursor) => {
+        let cursor = Cursor::decode(cursor)?;
+        if cursor.sort != sort {
+          return Err(invalid("cursor", "Cursor belongs to another sort"));
+        }
+        Some(cursor)
+      }
+      None => None,
+    };
+
+    Ok(Self {
+      limit: limit.min(settings.max_limit),
+      sort,
+      key,
+      descending,
+      after,
+    })
+  }
+
+  /// Restricts `query` to the items after the cursor.
+  pub fn filter(&self, mut query: Document) -> Result<Document, Error> {
+    let after = match &self.after {
+      Some(after) => after,
+      None => return Ok(query),
+    };
+    let operator = if self.descending { "$lt" } else { "$gt" };
+    let condition = match self.key {
+      SortKey::Date(field) => {
+        let date = Date::from_millis(after.date.ok_or_else(invalid_cursor)?);
+        let id = cursor_id(after)?;
+        doc! { "$or": [
+          { field: { operator: date } },
+          { field: date, "_id": { operator: id } },
+        ] }
+      }
+      SortKey::Id => doc! { "_id": { operator: cursor_id(after)? } },
+      SortKey::Natural => return Ok(query),
+    };
+
+    if let Some(Bson::Array(clauses)) = query.get_mut("$and") {
+      clauses.push(Bson::Document(condition));
+      return Ok(query);
+    }
+    if query.contains_key("$and") {
+      return Ok(doc! { "$and": [query, condition] });
+    }
+
+    query.insert("$and", vec![condition]);
+    Ok(query)
+  }
+
+  /// Sort, limit and offset of the page. One item more than the limit is
+  /// fetched to tell whether another page follows.
+  pub fn options(&self) -> FindOptions {
+    let direction = if self.descending { -1 } else { 1 };
+    let sort = match self.key {
+      SortKey::Date(field) => Some(doc! { field: direction, "_id": direction }),
+      SortKey::Id => Some(doc! { "_id": direction }),
+      SortKey::Natural => None,
+    };
+    let skip = match self.key {
+      SortKey::Natural => self.offset(),
+      _ => None,
+    };
+
+    FindOptions::builder()
+      .sort(sort)
+      .skip(skip)
+      .limit(self.limit + 1)
+      .build()
+  }
+
+  /// Builds the page from the items found with `options`.
+  pub fn page<T: Serialize>(&self, mut items: Vec<T>, total: u64) -> Result<Page<T>, Error> {
+    if items.len() as i64 <= self.limit {
+      return Ok(Page {
+        items,
+        next_cursor: None,
+        total,
+      });
+    }
+
+    items.truncate(self.limit as usize);
+    let last = bson::to_document(items.last().unwrap())?;
+    let mut cursor = Cursor {
+      sort: self.sort.clone(),
+      date: None,
+      id: None,
+      offset: None,
+    };
+    let last_id = || {
+      last
+        .get_object_id("_id")
+        .map(|id| id.to_hex())
+        .map_err(|_| invalid_cursor())
+    };
+    match self.key {
+      SortKey::Date(field) => {
+        let date = last.get_datetime(field).map_err(|_| invalid_cursor())?;
+        cursor.date = Some(date.timestamp_millis());
+        cursor.id = Some(last_id()?);
+      }
+      SortKey::Id => cursor.id = Some(last_id()?),
+      SortKey::Natural => {
+        cursor.offset = Some(self.offset().unwrap_or(0) + self.limit as u64);
+      }
+    }
+
+    Ok(Page {
+      items,
+      next_cursor: Some(cursor.encode()),
+      total,
+    })
+  }
+
+  fn offset(&self) -> Option<u64> {
+    self.after.as_ref().and_then(|after| after.offset)
+  }
+}
+
+/// The URI of the page after the one `uri` requested.
+pub fn next_page_uri(uri: &Uri, cursor: &str) -> String {
+  let mut query = uri
+    .query()
+    .unwrap_or("")
+    .split('&')
+    .filter(|param| !param.is_empty() && !param.starts_with("cursor="))
+    .map(str::to_owned)
+    .collect::<Vec<String>>();
+  query.push(format!("cursor={}", cursor));
+
+  format!("{}?{}", uri.path(), query.join("&"))
+}
+
+fn cursor_id(cursor: &Cursor) -> Result<ObjectId, Error> {
+  cursor
+    .id
+    .as_deref()
+    .and_then(|id| ObjectId::parse_str(id).ok())
+    .ok_or_else(invalid_cursor)
+}
+
+fn invalid_cursor() -> Error {
+  invalid("cursor", "Invalid cursor")
+}
+
+fn invalid(field: &str, message: &str) -> Error {
+  Error::BadRequest(BadRequest::new(field.to_owned(), message.to_owned()))
+}
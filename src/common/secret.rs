@@ -0,0 +1,10 @@
+use rand::rngs::OsRng;
+use rand::RngCore;
+
+/// Returns `len` bytes from the OS random generator, hex-encoded. Used for
+/// opaque credentials that are handed out once and stored hashed.
+pub fn generate(len: usize) -> String {
+  let mut bytes = vec![0u8; len];
+  OsRng.fill_bytes(&mut bytes);
+  base16ct::lower::encode_string(&bytes)
+}
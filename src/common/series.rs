@@ -0,0 +1,129 @@
+//! Time series of geodata values. Geodata is placed in time by the start of
+//! its observation, or by its ingestion when it has no observation time.
+use bson::serde_helpers::bson_datetime_as_rfc3339_string;
+use bson::serde_helpers::serialize_object_id_as_hex_string;
+use serde::{Deserialize, Serialize};
+use wither::bson::{doc, oid::ObjectId, Document};
+
+use crate::common::date::Date;
+
+// How `$dateToString` writes dates, parsed back as RFC 3339.
+const DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S.%LZ";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Bucket {
+  Hourly,
+  Daily,
+  Monthly,
+}
+
+impl Bucket {
+  /// The expression truncating `$time` to the start of its bucket, in UTC.
+  pub fn start(&self) -> Document {
+    let mut parts = doc! {
+      "year": { "$year": "$time" },
+      "month": { "$month": "$time" },
+    };
+    if *self != Bucket::Monthly {
+      parts.insert("day", doc! { "$dayOfMonth": "$time" });
+    }
+    if *self == Bucket::Hourly {
+      parts.insert("hour", doc! { "$hour": "$time" });
+    }
+
+    doc! { "$dateFromParts": parts }
+  }
+}
+
+/// A value of the series, as recorded by a geodata.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeriesPoint {
+  #[serde(alias = "_id", serialize_with = "serialize_object_id_as_hex_string")]
+  pub id: ObjectId,
+  #[serde(with = "bson_datetime_as_rfc3339_string")]
+  pub time: Date,
+  pub value: f64,
+  pub quality: i32,
+  pub source: String,
+}
+
+/// The values of a bucket, which starts at `start`. Empty buckets are left
+/// out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeriesBucket {
+  #[serde(with = "bson_datetime_as_rfc3339_string")]
+  pub start: Date,
+  pub count: u64,
+  pub min: f64,
+  pub max: f64,
+  pub mean: f64,
+}
+
+/// A series in time order, of points or, when bucketed, of buckets.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TimeSeries {
+  pub geotype: String,
+  #[serde(with = "bson_datetime_as_rfc3339_string")]
+  pub start: Date,
+  #[serde(with = "bson_datetime_as_rfc3339_string")]
+  pub end: Date,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub bucket: Option<Bucket>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub points: Option<Vec<SeriesPoint>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub buckets: Option<Vec<SeriesBucket>>,
+}
+
+/// The stages placing matched geodata in time, from `start` up to `end`.
+/// Dates leave the pipeline as text, the way the series serializes them.
+fn timed(start: Date, end: Date) -> Vec<Document> {
+  vec![
+    doc! { "$addFields": { "time": { "$ifNull": ["$observed_at.start", "$created"] } } },
+    doc! { "$match": { "time": { "$gte": start, "$lt": end } } },
+  ]
+}
+
+/// The stages turning matched geodata into the first `limit` points of the
+/// series.
+pub fn points(start: Date, end: Date, limit: i64) -> Vec<Document> {
+  let mut stages = timed(start, end);
+  stages.extend([
+    doc! { "$sort": { "time": 1, "_id": 1 } },
+    doc! { "$limit": limit },
+    doc! { "$project": {
+      "time": { "$dateToString": { "date": "$time", "format": DATE_FORMAT } },
+      "value": 1,
+      "quality": 1,
+      "source": 1,
+    } },
+  ]);
+  stages
+}
+
+/// The stages summarizing matched geodata into the first `limit` buckets of
+/// the series.
+pub fn buckets(start: Date, end: Date, bucket: Bucket, limit: i64) -> Vec<Document> {
+  let mut stages = timed(start, end);
+  stages.extend([
+    doc! { "$group": {
+      "_id": bucket.start(),
+      "count": { "$sum": 1 },
+      "min": { "$min": "$value" },
+      "max": { "$max": "$value" },
+      "mean": { "$avg": "$value" },
+    } },
+    doc! { "$sort": { "_id": 1 } },
+    doc! { "$limit": limit },
+    doc! { "$project": {
+      "_id": 0,
+      "start": { "$dateToString": { "date": "$_id", "format": DATE_FORMAT } },
+      "count": 1,
+      "min": 1,
+      "max": 1,
+      "mean": 1,
+    } },
+  ]);
+  stages
+}
@@ -0,0 +1,72 @@
+use bson::{doc, oid::ObjectId, Bson, Document};
+
+use crate::common::token::TokenAccount;
+use crate::context::Context;
+use crate::errors::Error;
+use crate::models::sharing_grant::SharingGrant;
+
+/// The data an account may read: that of its organization, and that shared
+/// with its organization by others. Accounts without an organization only
+/// see data without one.
+#[derive(Debug, Clone)]
+pub struct Tenant {
+  pub organization: Option<ObjectId>,
+  pub grants: Vec<SharingGrant>,
+}
+
+impl Tenant {
+  pub async fn load(context: &Context, account: &TokenAccount) -> Result<Self, Error> {
+    let grants = match &account.organization {
+      Some(organization) => {
+        context
+          .models
+          .sharing_grant
+          .find_for_grantee(organization)
+          .await?
+      }
+      None => vec![],
+    };
+
+    Ok(Self {
+      organization: account.organization,
+      grants,
+    })
+  }
+
+  /// A filter on the `organization` field matching the readable documents.
+  pub fn filter(&self) -> Document {
+    let own = doc! { "organization": self.organization.map_or(Bson::Null, Bson::ObjectId) };
+    if self.grants.is_empty() {
+      return own;
+    }
+
+    let mut clauses = vec![Bson::Document(own)];
+    for grant in &self.grants {
+      let mut shared = doc! { "organization": grant.owner };
+      if !grant.geotypes.is_empty() {
+        shared.insert("geotype", doc! { "$in": grant.geotypes.clone() });
+      }
+      clauses.push(Bson::Document(shared));
+    }
+
+    doc! { "$or": clauses }
+  }
+
+  /// Restricts `query` to the readable documents. Top-level operators such
+  /// as `$near` stay at the top level, where MongoDB requires them.
+  pub fn scope(&self, mut query: Document) -> Document {
+    let filter = self.filter();
+    if filter.keys().any(|key| query.contains_key(key)) {
+      return doc! { "$and": [query, filter] };
+    }
+
+    query.extend(filter);
+    query
+  }
+
+  /// Whether documents of `organization` belong to this tenant itself, as
+  /// opposed to being shared with it.
+  pub fn owns(&self, organization: Option<ObjectId>) -> bool {
+    self.organization == organization
+  }
+}
@@ -1,20 +1,15 @@
 use bson::oid::ObjectId;
-use jsonwebtoken::{errors::Error, DecodingKey, EncodingKey, Header, TokenData, Validation};
-use once_cell::sync::Lazy;
+use jsonwebtoken::{errors::Error, TokenData};
 use serde::{Deserialize, Serialize};
 
+use crate::common::keys::KeyStore;
+use crate::common::secret;
 use crate::models::account::Account;
 use crate::models::role::Role;
 
 type TokenResult = Result<TokenData<Claims>, Error>;
 
-// TODO: move these to enum struct
-pub const ADMIN_PATH: &str = "/6a2dda";
-pub const VALIDATOR_PATH: &str = "/5be0da";
-pub const USER_PATH: &str = "/6b0866";
-
-static VALIDATION: Lazy<Validation> = Lazy::new(Validation::default);
-static HEADER: Lazy<Header> = Lazy::new(Header::default);
+const MFA_PURPOSE: &str = "mfa";
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TokenAccount {
@@ -22,6 +17,14 @@ pub struct TokenAccount {
   pub name: String,
   pub email: String,
   pub roles: Vec<Role>,
+  #[serde(default)]
+  pub organization: Option<ObjectId>,
+}
+
+impl TokenAccount {
+  pub fn has_role(&self, name: &str) -> bool {
+    self.roles.iter().any(|role| role.name == name)
+  }
 }
 
 impl From<Account> for TokenAccount {
@@ -31,6 +34,7 @@ impl From<Account> for TokenAccount {
       name: account.name.clone(),
       email: account.email,
       roles: account.roles,
+      organization: account.organization,
     }
   }
 }
@@ -39,28 +43,69 @@ impl From<Account> for TokenAccount {
 pub struct Claims {
   pub exp: usize, // Expiration time (as UTC timestamp). validate_exp defaults to true in validation
   pub iat: usize, // Issued at (as UTC timestamp)
+  pub jti: String, // Unique token id, checked against the revocation list
+  pub ver: i32,    // Account token version at issue time
   pub account: TokenAccount,
 }
 
 impl Claims {
-  pub fn new(account: Account) -> Self {
+  pub fn new(account: Account, ttl: i64) -> Self {
+    let now = chrono::Local::now();
     Self {
-      exp: (chrono::Local::now() + chrono::Duration::days(30)).timestamp() as usize,
-      iat: chrono::Local::now().timestamp() as usize,
+      exp: (now + chrono::Duration::seconds(ttl)).timestamp() as usize,
+      iat: now.timestamp() as usize,
+      jti: secret::generate(16),
+      ver: account.token_version,
       account: TokenAccount::from(account),
     }
   }
 }
 
-pub fn create(account: Account, secret: &str) -> Result<String, Error> {
-  let encoding_key = EncodingKey::from_secret(secret.as_ref());
-  let claims = Claims::new(account);
+/// Claims of the token handed out after a correct password when a second
+/// factor is still needed. It lacks the access token claims, so it can only
+/// complete the login.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MfaClaims {
+  pub exp: usize,
+  pub iat: usize,
+  pub sub: ObjectId, // Account id
+  pub ver: i32,      // Account token version at issue time
+  pub purpose: String,
+}
 
-  jsonwebtoken::encode(&HEADER, &claims, &encoding_key)
+impl MfaClaims {
+  pub fn new(account: &Account, ttl: i64) -> Self {
+    let now = chrono::Local::now();
+    Self {
+      exp: (now + chrono::Duration::seconds(ttl)).timestamp() as usize,
+      iat: now.timestamp() as usize,
+      sub: account.id.unwrap(),
+      ver: account.token_version,
+      purpose: MFA_PURPOSE.to_owned(),
+    }
+  }
 }
 
-pub fn decode(token: &str, secret: &str) -> TokenResult {
-  let decoding_key = DecodingKey::from_secret(secret.as_ref());
+/// Creates an access token for `account` that expires after `ttl` seconds.
+pub fn create(account: Account, keys: &KeyStore, ttl: i64) -> Result<String, Error> {
+  let claims = Claims::new(account, ttl);
+
+  keys.encode(&claims)
+}
+
+pub fn decode(token: &str, keys: &KeyStore) -> TokenResult {
+  keys.decode::<Claims>(token)
+}
+
+pub fn create_mfa(account: &Account, keys: &KeyStore, ttl: i64) -> Result<String, Error> {
+  keys.encode(&MfaClaims::new(account, ttl))
+}
+
+pub fn decode_mfa(token: &str, keys: &KeyStore) -> Result<MfaClaims, Error> {
+  let claims = keys.decode::<MfaClaims>(token)?.claims;
+  if claims.purpose != MFA_PURPOSE {
+    return Err(Error::from(jsonwebtoken::errors::ErrorKind::InvalidToken));
+  }
 
-  jsonwebtoken::decode::<Claims>(token, &decoding_key, &VALIDATION)
+  Ok(claims)
 }
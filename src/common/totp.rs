@@ -0,0 +1,122 @@
+//! Time-based one-time passwords (RFC 6238) with the parameters authenticator
+//! apps expect: HMAC-SHA1, 30 second steps and 6 digits.
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha1::Sha1;
+
+pub const STEP_SECONDS: i64 = 30;
+pub const DIGITS: u32 = 6;
+const SECRET_LEN: usize = 20;
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Creates a random shared secret, base32 encoded.
+pub fn generate_secret() -> String {
+  let mut secret = [0u8; SECRET_LEN];
+  OsRng.fill_bytes(&mut secret);
+  base32_encode(&secret)
+}
+
+/// The time step containing `timestamp` (in seconds).
+pub fn step(timestamp: i64) -> i64 {
+  timestamp.div_euclid(STEP_SECONDS)
+}
+
+/// Computes the code of `secret` for a time step.
+pub fn code_at(secret: &[u8], step: u64) -> String {
+  let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC can take key of any size");
+  mac.update(&step.to_be_bytes());
+  let digest = mac.finalize().into_bytes();
+
+  // Dynamic truncation, RFC 4226 section 5.3.
+  let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+  let binary = u32::from_be_bytes([
+    digest[offset] & 0x7f,
+    digest[offset + 1],
+    digest[offset + 2],
+    digest[offset + 3],
+  ]);
+
+  format!("{:0width$}", binary % 10u32.pow(DIGITS), width = DIGITS as usize)
+}
+
+/// Checks `code` against the steps around `timestamp`, allowing `skew` steps
+/// of clock drift either way, and returns the matching step.
+pub fn verify(secret: &str, code: &str, timestamp: i64, skew: i64) -> Option<i64> {
+  let secret = base32_decode(secret)?;
+  let code = code.trim();
+  let current = step(timestamp);
+
+  (current - skew..=current + skew)
+    .filter(|step| *step >= 0)
+    .find(|step| constant_time_eq(code_at(&secret, *step as u64).as_bytes(), code.as_bytes()))
+}
+
+/// The `otpauth://` URI authenticator apps enroll from, usually shown as a
+/// QR code.
+pub fn otpauth_uri(issuer: &str, account: &str, secret: &str) -> String {
+  format!(
+    "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+    issuer = percent_encode(issuer),
+    account = percent_encode(account),
+    secret = secret,
+    digits = DIGITS,
+    period = STEP_SECONDS,
+  )
+}
+
+/// RFC 4648 base32 without padding.
+pub fn base32_encode(data: &[u8]) -> String {
+  let mut encoded = String::with_capacity((data.len() * 8 + 4) / 5);
+  let mut buffer: u32 = 0;
+  let mut bits = 0;
+  for byte in data {
+    buffer = (buffer << 8) | u32::from(*byte);
+    bits += 8;
+    while bits >= 5 {
+      bits -= 5;
+      encoded.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+    }
+  }
+  if bits > 0 {
+    encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+  }
+
+  encoded
+}
+
+/// Decodes base32, ignoring case, padding and spaces.
+pub fn base32_decode(encoded: &str) -> Option<Vec<u8>> {
+  let mut decoded = Vec::with_capacity(encoded.len() * 5 / 8);
+  let mut buffer: u32 = 0;
+  let mut bits = 0;
+  for c in encoded.chars().filter(|c| *c != '=' && !c.is_whitespace()) {
+    let value = BASE32_ALPHABET
+      .iter()
+      .position(|a| *a as char == c.to_ascii_uppercase())?;
+    buffer = (buffer << 5) | value as u32;
+    bits += 5;
+    if bits >= 8 {
+      bits -= 8;
+      decoded.push((buffer >> bits) as u8);
+    }
+  }
+
+  Some(decoded)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+  a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn percent_encode(value: &str) -> String {
+  value
+    .bytes()
+    .map(|byte| match byte {
+      b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+        (byte as char).to_string()
+      }
+      _ => format!("%{:02X}", byte),
+    })
+    .collect()
+}
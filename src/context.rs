@@ -1,3 +1,9 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::common::account_cache::AccountCache;
+use crate::common::keys::KeyStore;
+use crate::common::notifier::{LogNotifier, Notifier, SharedNotifier};
 use crate::models::Models;
 use crate::settings::Settings;
 
@@ -5,10 +11,26 @@ use crate::settings::Settings;
 pub struct Context {
   pub models: Models,
   pub settings: Settings,
+  pub cache: AccountCache,
+  pub keys: KeyStore,
+  pub notifier: SharedNotifier,
 }
 
 impl Context {
-  pub fn new(models: Models, settings: Settings) -> Self {
-    Self { models, settings }
+  pub fn new(models: Models, settings: Settings, keys: KeyStore) -> Self {
+    let cache = AccountCache::new(Duration::from_secs(settings.auth.cache_ttl));
+    Self {
+      models,
+      settings,
+      cache,
+      keys,
+      notifier: Arc::new(LogNotifier),
+    }
+  }
+
+  /// Replaces the notifier, which by default only logs.
+  pub fn with_notifier<N: Notifier + 'static>(mut self, notifier: N) -> Self {
+    self.notifier = Arc::new(notifier);
+    self
   }
-}
\ No newline at end of file
+}
@@ -1,11 +1,12 @@
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use axum::Json;
-use bcrypt::BcryptError;
+use argon2::password_hash::Error as PasswordHashError;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use tokio::task::JoinError;
 use wither::bson;
-use wither::mongodb::error::Error as MongoError;
+use wither::mongodb::error::{Error as MongoError, ErrorKind, WriteFailure};
 use wither::WitherError;
 
 #[derive(thiserror::Error, Debug)]
@@ -26,23 +27,55 @@ pub enum Error {
   #[error("{0}")]
   SerializeMongoResponse(#[from] bson::de::Error),
 
+  #[error("{0}")]
+  SerializeMongoRequest(#[from] bson::ser::Error),
+
   #[error("{0}")]
   Authenticate(#[from] AuthenticateError),
 
   #[error("{0}")]
   BadRequest(#[from] BadRequest),
 
+  #[error("{0}")]
+  InvalidFields(#[from] InvalidFields),
+
   #[error("{0}")]
   NotFound(#[from] NotFound),
 
+  #[error("{0}")]
+  Conflict(#[from] Conflict),
+
   #[error("{0}")]
   RunSyncTask(#[from] JoinError),
 
   #[error("{0}")]
-  HashPassword(#[from] BcryptError),
+  HashPassword(#[from] PasswordHashError),
 }
 
 impl Error {
+  pub fn status(&self) -> StatusCode {
+    self.get_codes().0
+  }
+
+  /// Whether a write was refused for breaking a unique index (E11000).
+  pub fn is_duplicate_key(&self) -> bool {
+    let error = match self {
+      Error::Mongo(error) => error,
+      Error::Wither(WitherError::Mongo(error)) => error,
+      _ => return false,
+    };
+    match error.kind.as_ref() {
+      ErrorKind::Write(WriteFailure::WriteError(error)) => error.code == 11000,
+      ErrorKind::Command(error) => error.code == 11000,
+      ErrorKind::BulkWrite(failure) => failure
+        .write_errors
+        .iter()
+        .flatten()
+        .any(|error| error.code == 11000),
+      _ => false,
+    }
+  }
+
   fn get_codes(&self) -> (StatusCode, u16) {
     match *self {
       // 4XX Errors
@@ -58,11 +91,14 @@ impl Error {
       //   }
       // }
       Error::BadRequest(_) => (StatusCode::BAD_REQUEST, 40003),
+      Error::InvalidFields(_) => (StatusCode::BAD_REQUEST, 40003),
       Error::NotFound(_) => (StatusCode::NOT_FOUND, 40003),
+      Error::Conflict(_) => (StatusCode::CONFLICT, 40003),
 
       Error::Authenticate(AuthenticateError::WrongCredentials) => (StatusCode::UNAUTHORIZED, 40003),
       Error::Authenticate(AuthenticateError::InvalidToken) => (StatusCode::UNAUTHORIZED, 40003),
       Error::Authenticate(AuthenticateError::Locked) => (StatusCode::LOCKED, 40003),
+      Error::Authenticate(AuthenticateError::Forbidden) => (StatusCode::FORBIDDEN, 40003),
 
       // 5XX Errors
       Error::ReadContext => (StatusCode::INTERNAL_SERVER_ERROR, 5001),
@@ -72,6 +108,7 @@ impl Error {
       Error::Wither(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5001),
       Error::Mongo(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5003),
       Error::SerializeMongoResponse(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5009),
+      Error::SerializeMongoRequest(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5009),
       Error::RunSyncTask(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5009),
       Error::HashPassword(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5009),
     }
@@ -82,9 +119,12 @@ impl IntoResponse for Error {
   fn into_response(self) -> Response {
     let (status_code, code) = self.get_codes();
     let message = self.to_string();
-    let body = Json(json!({ "code": code, "message": message }));
+    let mut body = json!({ "code": code, "message": message });
+    if let Error::InvalidFields(invalid) = &self {
+      body["errors"] = json!(invalid.errors);
+    }
 
-    (status_code, body).into_response()
+    (status_code, Json(body)).into_response()
   }
 }
 
@@ -99,6 +139,8 @@ pub enum AuthenticateError {
   InvalidToken,
   #[error("Account is locked")]
   Locked,
+  #[error("Account is not allowed to access this resource")]
+  Forbidden,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -122,6 +164,34 @@ impl BadRequest {
   }
 }
 
+/// Every invalid field of a request, with the reason. Fields are paths such
+/// as `location.geometries[0].coordinates[1]`.
+#[derive(thiserror::Error, Debug)]
+#[error("Invalid fields: {}", fields(.errors))]
+pub struct InvalidFields {
+  pub errors: Vec<FieldError>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldError {
+  pub field: String,
+  pub message: String,
+}
+
+impl InvalidFields {
+  pub fn new(errors: Vec<FieldError>) -> Self {
+    InvalidFields { errors }
+  }
+}
+
+fn fields(errors: &[FieldError]) -> String {
+  errors
+    .iter()
+    .map(|error| error.field.as_str())
+    .collect::<Vec<&str>>()
+    .join(", ")
+}
+
 #[derive(thiserror::Error, Debug)]
 #[error("Not found")]
 pub struct NotFound {
@@ -137,3 +207,15 @@ impl NotFound {
     }
   }
 }
+
+#[derive(thiserror::Error, Debug)]
+#[error("{message}")]
+pub struct Conflict {
+  message: String,
+}
+
+impl Conflict {
+  pub fn new(message: String) -> Self {
+    Conflict { message }
+  }
+}
@@ -17,6 +17,7 @@ mod models;
 mod routes;
 mod settings;
 
+use common::keys::KeyStore;
 use context::Context;
 use database::Database;
 use logger::Logger;
@@ -42,12 +43,44 @@ async fn main() {
     Err(err) => panic!("Failed to setup models {}", err),
   };
 
-  let context = Context::new(models, settings.clone());
+  match common::migrations::run(&models).await {
+    Ok(()) => {}
+    Err(err) => panic!("Failed to migrate the database {}", err),
+  };
+
+  // Also checks the Argon2 parameters before any password is hashed.
+  match models::account::setup_dummy_password(&settings.auth.argon2).await {
+    Ok(()) => {}
+    Err(err) => panic!("Failed to setup password hashing {}", err),
+  };
+
+  let keys = match KeyStore::from_settings(&settings.auth, &settings.environment) {
+    Ok(value) => value,
+    Err(err) => panic!("Failed to load token keys {:#}", err),
+  };
+
+  let context = Context::new(models, settings.clone(), keys);
+
+  // Geodata can't be gridded until it has a geohash.
+  common::migrations::spawn_backfill(context.models.clone());
+
+  // Anchor the geodata queued by bulk imports in the background.
+  common::anchor_queue::spawn(context.clone());
 
   let app = Router::new()
     .merge(routes::account::create_route())
     .merge(routes::geodata::create_route())
+    .merge(routes::geodata_grid::create_route())
+    .merge(routes::geodata_import::create_route())
+    .merge(routes::geodata_interpolation::create_route())
+    .merge(routes::geodata_series::create_route())
+    .merge(routes::geotype::create_route())
     .merge(routes::validation::create_route())
+    .merge(routes::api_key::create_route())
+    .merge(routes::ogc::create_route())
+    .merge(routes::organization::create_route())
+    .merge(routes::tiles::create_route())
+    .merge(routes::well_known::create_route())
     .fallback(handler_404.into_service())
     // High level logging of requests and responses
     .layer(
@@ -76,7 +109,8 @@ async fn main() {
   info!("listening on {}", &address);
 
   axum::Server::bind(&address)
-    .serve(app.into_make_service())
+    // Client addresses are needed to enforce API key IP allowlists.
+    .serve(app.into_make_service_with_connect_info::<SocketAddr>())
     .await
     .expect("Failed to start server");
 }
@@ -1,10 +1,14 @@
 #![allow(dead_code)]
 use bson::serde_helpers::bson_datetime_as_rfc3339_string;
 use bson::serde_helpers::serialize_object_id_as_hex_string;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
 use tokio::task;
 use validator::Validate;
-use wither::bson::{doc, oid::ObjectId};
+use wither::bson::{doc, oid::ObjectId, Document};
 use wither::Model as WitherModel;
 
 use crate::database::Database;
@@ -12,7 +16,11 @@ use crate::errors::Error;
 use crate::common::date;
 use crate::common::date::Date;
 use crate::common::models::ModelExt;
+use crate::common::object_id;
 use crate::models::role::Role;
+use crate::settings;
+
+pub const MIN_PASSWORD_LENGTH: usize = 8;
 
 #[derive(Clone)]
 pub struct Model {
@@ -23,6 +31,187 @@ impl Model {
   pub fn new(db: Database) -> Self {
     Self { db }
   }
+
+  /// Applies `set` to an account and bumps its token version, which
+  /// invalidates every access token issued before.
+  pub async fn update_and_revoke_tokens(
+    &self,
+    id: &ObjectId,
+    mut set: Document,
+  ) -> Result<Option<Account>, Error> {
+    set.insert("updated_at", date::now());
+    self
+      .find_one_and_update(
+        doc! { "_id": id },
+        doc! { "$set": set, "$inc": { "token_version": 1 } },
+      )
+      .await
+  }
+
+  /// Counts a failed login and returns the updated account.
+  pub async fn record_failed_login(&self, id: &ObjectId) -> Result<Option<Account>, Error> {
+    self
+      .find_one_and_update(doc! { "_id": id }, doc! { "$inc": { "failed_logins": 1 } })
+      .await
+  }
+
+  /// Locks an account out of logging in until `until`. Existing tokens stay
+  /// valid; this only slows down password guessing.
+  pub async fn lock_out(&self, id: &ObjectId, until: Date) -> Result<Option<Account>, Error> {
+    self
+      .find_one_and_update(
+        doc! { "_id": id },
+        doc! { "$set": { "failed_logins": 0, "lockout_until": until } },
+      )
+      .await
+  }
+
+  pub async fn reset_failed_logins(&self, id: &ObjectId) -> Result<Option<Account>, Error> {
+    self
+      .find_one_and_update(
+        doc! { "_id": id },
+        doc! { "$set": { "failed_logins": 0, "lockout_until": null } },
+      )
+      .await
+  }
+
+  pub async fn lock(&self, id: &ObjectId) -> Result<Option<Account>, Error> {
+    self
+      .update_and_revoke_tokens(id, doc! { "locked_at": date::now() })
+      .await
+  }
+
+  pub async fn unlock(&self, id: &ObjectId) -> Result<Option<Account>, Error> {
+    self
+      .find_one_and_update(
+        doc! { "_id": id },
+        doc! { "$set": { "locked_at": null, "updated_at": date::now() } },
+      )
+      .await
+  }
+
+  /// Sets a new password, which also clears failed logins and invalidates
+  /// every access token issued before.
+  pub async fn set_password(
+    &self,
+    id: &ObjectId,
+    password_hash: String,
+  ) -> Result<Option<Account>, Error> {
+    self
+      .update_and_revoke_tokens(
+        id,
+        doc! { "password": password_hash, "failed_logins": 0, "lockout_until": null },
+      )
+      .await
+  }
+
+  /// Replaces the stored hash of a password that was just verified. Unlike a
+  /// password change this keeps existing tokens valid.
+  pub async fn rehash_password(
+    &self,
+    id: &ObjectId,
+    password_hash: String,
+  ) -> Result<Option<Account>, Error> {
+    self
+      .find_one_and_update(
+        doc! { "_id": id },
+        doc! { "$set": { "password": password_hash } },
+      )
+      .await
+  }
+
+  /// Stores a new TOTP secret and recovery codes, replacing a pending
+  /// enrollment. Two-factor authentication is enabled once a code from the
+  /// new secret has been verified.
+  pub async fn start_mfa_enrollment(
+    &self,
+    id: &ObjectId,
+    secret: String,
+    recovery_code_hashes: Vec<String>,
+  ) -> Result<Option<Account>, Error> {
+    self
+      .find_one_and_update(
+        doc! { "_id": id, "totp_enabled_at": null },
+        doc! { "$set": {
+          "totp_secret": secret,
+          "totp_last_step": null,
+          "recovery_codes": recovery_code_hashes,
+        } },
+      )
+      .await
+  }
+
+  pub async fn enable_mfa(&self, id: &ObjectId) -> Result<Option<Account>, Error> {
+    self
+      .find_one_and_update(
+        doc! { "_id": id },
+        doc! { "$set": { "totp_enabled_at": date::now(), "updated_at": date::now() } },
+      )
+      .await
+  }
+
+  pub async fn disable_mfa(&self, id: &ObjectId) -> Result<Option<Account>, Error> {
+    self
+      .find_one_and_update(
+        doc! { "_id": id },
+        doc! { "$set": {
+          "totp_secret": null,
+          "totp_enabled_at": null,
+          "totp_last_step": null,
+          "recovery_codes": [],
+          "updated_at": date::now(),
+        } },
+      )
+      .await
+  }
+
+  /// Records `step` as used, unless it or a later step was used already, so
+  /// every code works only once.
+  pub async fn use_totp_step(&self, id: &ObjectId, step: i64) -> Result<Option<Account>, Error> {
+    self
+      .find_one_and_update(
+        doc! {
+          "_id": id,
+          "$or": [{ "totp_last_step": null }, { "totp_last_step": { "$lt": step } }],
+        },
+        doc! { "$set": { "totp_last_step": step } },
+      )
+      .await
+  }
+
+  /// Removes a recovery code, returning `None` when it was not (or no
+  /// longer) there.
+  pub async fn use_recovery_code(
+    &self,
+    id: &ObjectId,
+    code_hash: &str,
+  ) -> Result<Option<Account>, Error> {
+    self
+      .find_one_and_update(
+        doc! { "_id": id, "recovery_codes": code_hash },
+        doc! { "$pull": { "recovery_codes": code_hash } },
+      )
+      .await
+  }
+
+  /// Moves an account to another organization. Tokens naming the previous
+  /// one stop working.
+  pub async fn set_organization(
+    &self,
+    id: &ObjectId,
+    organization: Option<ObjectId>,
+  ) -> Result<Option<Account>, Error> {
+    self
+      .update_and_revoke_tokens(id, doc! { "organization": organization })
+      .await
+  }
+
+  pub async fn set_roles(&self, id: &ObjectId, roles: Vec<Role>) -> Result<Option<Account>, Error> {
+    let roles = bson::to_bson(&roles)?;
+    self
+      .update_and_revoke_tokens(id, doc! { "roles": roles })
+      .await
+  }
 }
 
 impl ModelExt for Model {
@@ -43,9 +232,33 @@ pub struct Account {
   pub email: String,
   pub password: String,
   pub roles: Vec<Role>,
+  // Tenant the account's data belongs to.
+  #[serde(default)]
+  pub organization: Option<ObjectId>,
   pub updated_at: Date,
   pub created_at: Date,
   pub locked_at: Option<Date>,
+  // Bumped whenever existing tokens must stop working (lock, password or
+  // role change). Tokens carry the version they were issued with.
+  #[serde(default)]
+  pub token_version: i32,
+  // Failed logins since the last successful one.
+  #[serde(default)]
+  pub failed_logins: i32,
+  #[serde(default)]
+  pub lockout_until: Option<Date>,
+  // Base32 TOTP secret, kept in the clear since codes are computed from it.
+  #[serde(default)]
+  pub totp_secret: Option<String>,
+  // Set once a first code was verified; until then enrollment is pending.
+  #[serde(default)]
+  pub totp_enabled_at: Option<Date>,
+  // Last accepted time step, so codes can't be replayed.
+  #[serde(default)]
+  pub totp_last_step: Option<i64>,
+  // Hashes of the unused recovery codes.
+  #[serde(default)]
+  pub recovery_codes: Vec<String>,
 }
 
 impl Account {
@@ -57,15 +270,114 @@ impl Account {
       email,
       password: password_hash,
       roles,
+      organization: None,
       updated_at: now,
       created_at: now,
       locked_at: None,
+      token_version: 0,
+      failed_logins: 0,
+      lockout_until: None,
+      totp_secret: None,
+      totp_enabled_at: None,
+      totp_last_step: None,
+      recovery_codes: vec![],
     }
   }
 
-  pub fn is_password_match(&self, password: &str) -> bool {
-    bcrypt::verify(password, self.password.as_ref()).unwrap_or(false)
+  /// Checks `password` against the stored hash, which is either an Argon2
+  /// PHC string or a bcrypt hash from before the switch to Argon2id.
+  pub async fn is_password_match<P>(&self, password: P) -> Result<bool, Error>
+  where
+    P: AsRef<str> + Send + 'static,
+  {
+    verify_password(password, self.password.clone()).await
   }
+
+  /// Whether the stored hash is bcrypt or Argon2 with other than the
+  /// configured parameters, and should be replaced after the next login.
+  pub fn needs_rehash(&self, params: &settings::Argon2) -> bool {
+    let hash = match PasswordHash::new(&self.password) {
+      Ok(hash) => hash,
+      Err(_) => return true,
+    };
+    if hash.algorithm != argon2::ARGON2ID_IDENT || hash.version != Some(Version::V0x13.into()) {
+      return true;
+    }
+
+    match Params::try_from(&hash) {
+      Ok(current) => {
+        current.m_cost() != params.memory_kib
+          || current.t_cost() != params.iterations
+          || current.p_cost() != params.parallelism
+      }
+      Err(_) => true,
+    }
+  }
+
+  pub fn is_locked_out(&self) -> bool {
+    self.lockout_until.map_or(false, |until| until > date::now())
+  }
+
+  pub fn is_mfa_enabled(&self) -> bool {
+    self.totp_enabled_at.is_some()
+  }
+
+  /// Whether one of the account's roles is listed in `required_roles`.
+  pub fn is_mfa_required(&self, required_roles: &[String]) -> bool {
+    self.roles.iter().any(|role| required_roles.contains(&role.name))
+  }
+}
+
+/// Verifies `password` against a throwaway hash, so that looking up an
+/// unknown email costs as much as checking a wrong password.
+pub async fn verify_dummy_password<P>(password: P, params: &settings::Argon2) -> Result<(), Error>
+where
+  P: AsRef<str> + Send + 'static,
+{
+  setup_dummy_password(params).await?;
+  let hash = DUMMY_HASH.get().cloned().unwrap_or_default();
+  verify_password(password, hash).await?;
+
+  Ok(())
+}
+
+/// Hashes the throwaway password of `verify_dummy_password`, which fails
+/// when the configured Argon2 parameters are invalid. Run at startup so the
+/// first login with an unknown email doesn't pay for it.
+pub async fn setup_dummy_password(params: &settings::Argon2) -> Result<(), Error> {
+  if DUMMY_HASH.get().is_none() {
+    let hash = hash_password("dummy password", params).await?;
+    // Another caller may have set it meanwhile, with an equally good hash.
+    let _ = DUMMY_HASH.set(hash);
+  }
+
+  Ok(())
+}
+
+static DUMMY_HASH: OnceCell<String> = OnceCell::new();
+
+/// Argon2 and bcrypt checks take tens of milliseconds by design, so they run
+/// on the blocking pool rather than on a runtime worker.
+async fn verify_password<P>(password: P, hash: String) -> Result<bool, Error>
+where
+  P: AsRef<str> + Send + 'static,
+{
+  task::spawn_blocking(move || {
+    let password = password.as_ref();
+    if !is_argon2_hash(&hash) {
+      return bcrypt::verify(password, &hash).unwrap_or(false);
+    }
+
+    PasswordHash::new(&hash)
+      .and_then(|hash| Argon2::default().verify_password(password.as_bytes(), &hash))
+      .is_ok()
+  })
+  .await
+  .map_err(Error::RunSyncTask)
+}
+
+fn is_argon2_hash(hash: &str) -> bool {
+  hash.starts_with("$argon2")
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -75,6 +387,10 @@ pub struct PublicAccount {
   pub name: String,
   pub email: String,
   pub roles: Vec<Role>,
+  #[serde(default, serialize_with = "object_id::serialize_optional_hex")]
+  pub organization: Option<ObjectId>,
+  #[serde(default)]
+  pub mfa_enabled: bool,
   #[serde(with = "bson_datetime_as_rfc3339_string")]
   pub updated_at: Date,
   #[serde(with = "bson_datetime_as_rfc3339_string")]
@@ -87,19 +403,36 @@ impl From<Account> for PublicAccount {
       id: account.id.unwrap(),
       name: account.name.clone(),
       email: account.email.clone(),
-      roles: account.roles,
+      roles: account.roles.clone(),
+      organization: account.organization,
+      mfa_enabled: account.is_mfa_enabled(),
       updated_at: account.updated_at,
       created_at: account.created_at,
     }
   }
 }
 
-pub async fn hash_password<P>(password: P) -> Result<String, Error>
+/// Hashes `password` with Argon2id into a PHC string, which records the
+/// salt and parameters alongside the hash.
+pub async fn hash_password<P>(password: P, params: &settings::Argon2) -> Result<String, Error>
 where
   P: AsRef<str> + Send + 'static,
 {
-  task::spawn_blocking(move || bcrypt::hash(password.as_ref(), bcrypt::DEFAULT_COST))
+  let params = params.clone();
+  task::spawn_blocking(move || hash_password_sync(password.as_ref(), &params))
     .await
     .map_err(Error::RunSyncTask)?
     .map_err(Error::HashPassword)
 }
+
+fn hash_password_sync(
+  password: &str,
+  params: &settings::Argon2,
+) -> Result<String, argon2::password_hash::Error> {
+  let params = Params::new(params.memory_kib, params.iterations, params.parallelism, None)?;
+  let salt = SaltString::generate(&mut OsRng);
+  let hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+    .hash_password(password.as_bytes(), &salt)?;
+
+  Ok(hash.to_string())
+}
@@ -0,0 +1,173 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId};
+use wither::mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
+use wither::Model as WitherModel;
+
+use crate::common::date;
+use crate::common::date::Date;
+use crate::common::models::ModelExt;
+use crate::database::Database;
+use crate::errors::{Conflict, Error};
+use crate::models::geodata::Geodata;
+
+#[derive(Clone)]
+pub struct Model {
+  pub db: Database,
+}
+
+impl Model {
+  pub fn new(db: Database) -> Self {
+    Self { db }
+  }
+
+  /// Claims the request waiting to be anchored that failed the fewest times,
+  /// oldest first, among those due for an attempt. Claims older than `lease`
+  /// seconds are taken over, as their worker likely stopped.
+  pub async fn claim_next(&self, lease: i64) -> Result<Option<AnchorRequest>, Error> {
+    let now = date::now();
+    let expired = date::after(-lease);
+    let query = doc! {
+      "anchored_at": null,
+      "failed_at": null,
+      "$and": [
+        { "$or": [{ "claimed_at": null }, { "claimed_at": { "$lt": expired } }] },
+        { "$or": [{ "next_attempt_at": null }, { "next_attempt_at": { "$lte": now } }] },
+      ],
+    };
+    let update = doc! { "$set": { "claimed_at": now } };
+    let options = FindOneAndUpdateOptions::builder()
+      .sort(doc! { "attempts": 1, "created_at": 1 })
+      .return_document(ReturnDocument::After)
+      .build();
+
+    AnchorRequest::find_one_and_update(&self.db.conn, query, update, options)
+      .await
+      .map_err(Error::Wither)
+  }
+
+  /// Queues a revision before it's written, as a record of the intent. The
+  /// worker takes it at `ready_at`, or once marked ready, and completes or
+  /// drops a revision that was interrupted. A revision of the same version
+  /// already in flight makes it a conflict.
+  pub async fn enqueue_revision(
+    &self,
+    revision: &Geodata,
+    hash: String,
+    ready_at: Date,
+  ) -> Result<AnchorRequest, Error> {
+    let mut request = AnchorRequest::new(revision, hash);
+    request.next_attempt_at = Some(ready_at);
+    self.create(request).await.map_err(|err| match err.is_duplicate_key() {
+      true => Error::Conflict(Conflict::new(String::from("Geodata is being revised"))),
+      false => err,
+    })
+  }
+
+  pub async fn mark_ready(&self, id: &ObjectId) -> Result<(), Error> {
+    let update = doc! { "$set": { "next_attempt_at": null } };
+    self.update_one(doc! { "_id": id }, update, None).await?;
+    Ok(())
+  }
+
+  pub async fn mark_anchored(&self, id: &ObjectId) -> Result<(), Error> {
+    let update = doc! { "$set": { "anchored_at": date::now() } };
+    self.update_one(doc! { "_id": id }, update, None).await?;
+    Ok(())
+  }
+
+  /// Releases a claim that failed, keeping the error for operators. The
+  /// request is attempted again at `retry_at`, or never again without one.
+  pub async fn release(
+    &self,
+    id: &ObjectId,
+    error: String,
+    retry_at: Option<Date>,
+  ) -> Result<(), Error> {
+    let mut set = doc! { "claimed_at": null, "last_error": error };
+    match retry_at {
+      Some(retry_at) => set.insert("next_attempt_at", retry_at),
+      None => set.insert("failed_at", date::now()),
+    };
+    let update = doc! { "$set": set, "$inc": { "attempts": 1 } };
+    self.update_one(doc! { "_id": id }, update, None).await?;
+    Ok(())
+  }
+}
+
+impl ModelExt for Model {
+  type T = AnchorRequest;
+  fn get_database(&self) -> &Database {
+    &self.db
+  }
+}
+
+/// Geodata waiting to be anchored on the blockchain. Bulk imports queue
+/// their records here rather than anchoring each one within the request,
+/// as do revisions, and the anchor worker works through the queue.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(
+  index(
+    keys = r#"doc!{ "geodata": 1, "version": 1 }"#,
+    options = r#"doc!{ "unique": true }"#
+  ),
+  index(keys = r#"doc!{ "anchored_at": 1, "failed_at": 1, "attempts": 1, "created_at": 1 }"#),
+)]
+pub struct AnchorRequest {
+  #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+  pub id: Option<ObjectId>,
+  pub geodata: ObjectId,
+  pub account: ObjectId,
+  pub hash: String,
+  // Creation date of the version, which is anchored with the hash.
+  pub created: Date,
+  #[serde(default = "first_version")]
+  pub version: i32,
+  // Hash of the version a revision replaced, anchored along with its own.
+  #[serde(default)]
+  pub previous_hash: Option<String>,
+  pub created_at: Date,
+  #[serde(default)]
+  pub claimed_at: Option<Date>,
+  #[serde(default)]
+  pub anchored_at: Option<Date>,
+  #[serde(default)]
+  pub attempts: i32,
+  #[serde(default)]
+  pub last_error: Option<String>,
+  // When a failed request is due for its next attempt.
+  #[serde(default)]
+  pub next_attempt_at: Option<Date>,
+  // Set once a request failed too many times to be attempted again.
+  #[serde(default)]
+  pub failed_at: Option<Date>,
+}
+
+impl AnchorRequest {
+  pub fn new(geodata: &Geodata, hash: String) -> Self {
+    Self {
+      id: None,
+      geodata: geodata.id.unwrap(),
+      account: geodata.updated_by.unwrap_or(geodata.account),
+      hash,
+      created: geodata.updated.unwrap_or(geodata.created),
+      version: geodata.version,
+      previous_hash: geodata.previous_hash.clone(),
+      created_at: date::now(),
+      claimed_at: None,
+      anchored_at: None,
+      attempts: 0,
+      last_error: None,
+      next_attempt_at: None,
+      failed_at: None,
+    }
+  }
+
+  pub fn is_revision(&self) -> bool {
+    self.previous_hash.is_some()
+  }
+}
+
+fn first_version() -> i32 {
+  1
+}
@@ -0,0 +1,190 @@
+use bson::serde_helpers::bson_datetime_as_rfc3339_string;
+use bson::serde_helpers::serialize_object_id_as_hex_string;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId};
+use wither::Model as WitherModel;
+
+use crate::common::date;
+use crate::common::date::Date;
+use crate::common::hasher;
+use crate::common::models::ModelExt;
+use crate::common::secret;
+use crate::database::Database;
+
+/// Every API key starts with this marker, which tells them apart from JWTs.
+pub const KEY_MARKER: &str = "gdk_";
+
+#[derive(Clone)]
+pub struct Model {
+  pub db: Database,
+}
+
+impl Model {
+  pub fn new(db: Database) -> Self {
+    Self { db }
+  }
+}
+
+impl ModelExt for Model {
+  type T = ApiKey;
+  fn get_database(&self) -> &Database {
+    &self.db
+  }
+}
+
+/// A long-lived credential bound to an account, for machine clients such as
+/// sensors and ETL jobs. Keys look like `gdk_<prefix>_<secret>`; the prefix
+/// identifies the key and only the hash of the whole key is stored.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(
+  index(keys = r#"doc!{ "prefix": 1 }"#, options = r#"doc!{ "unique": true }"#),
+  index(keys = r#"doc!{ "account": 1 }"#),
+)]
+pub struct ApiKey {
+  #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+  pub id: Option<ObjectId>,
+  pub account: ObjectId,
+  #[validate(length(min = 1))]
+  pub name: String,
+  pub prefix: String,
+  pub key_hash: String,
+  // Role names the key may act with, limited to the roles of its account.
+  pub scopes: Vec<String>,
+  // IP addresses or CIDR ranges allowed to use the key, any when empty.
+  pub allowed_ips: Vec<String>,
+  pub expires_at: Option<Date>,
+  pub last_used_at: Option<Date>,
+  pub revoked_at: Option<Date>,
+  pub created_by: ObjectId,
+  pub created_at: Date,
+}
+
+impl ApiKey {
+  /// Creates a key and returns it along with its plaintext, which is only
+  /// available at this point.
+  pub fn generate(
+    account: ObjectId,
+    name: String,
+    scopes: Vec<String>,
+    allowed_ips: Vec<String>,
+    expires_at: Option<Date>,
+    created_by: ObjectId,
+  ) -> (Self, String) {
+    // 64 bits, so prefixes don't collide on their unique index.
+    let prefix = secret::generate(8);
+    let key = format!("{}{}_{}", KEY_MARKER, prefix, secret::generate(24));
+    let api_key = Self {
+      id: None,
+      account,
+      name,
+      prefix,
+      key_hash: hasher::hash(&key),
+      scopes,
+      allowed_ips,
+      expires_at,
+      last_used_at: None,
+      revoked_at: None,
+      created_by,
+      created_at: date::now(),
+    };
+
+    (api_key, key)
+  }
+
+  /// Returns the prefix of a plaintext key, if it is shaped like one.
+  pub fn parse_prefix(key: &str) -> Option<&str> {
+    let (prefix, _) = key.strip_prefix(KEY_MARKER)?.split_once('_')?;
+    Some(prefix)
+  }
+
+  pub fn is_match(&self, key: &str) -> bool {
+    hasher::hash(key) == self.key_hash
+  }
+
+  pub fn is_active(&self) -> bool {
+    self.revoked_at.is_none() && self.expires_at.map_or(true, |expires_at| expires_at > date::now())
+  }
+
+  pub fn allows_ip(&self, ip: IpAddr) -> bool {
+    self.allowed_ips.is_empty() || self.allowed_ips.iter().any(|allowed| ip_matches(allowed, ip))
+  }
+}
+
+/// Checks that `allowed` is an IP address or a CIDR range.
+pub fn is_valid_ip_range(allowed: &str) -> bool {
+  parse_ip_range(allowed).is_some()
+}
+
+fn ip_matches(allowed: &str, ip: IpAddr) -> bool {
+  match parse_ip_range(allowed) {
+    Some((IpAddr::V4(network), bits)) => match ip {
+      IpAddr::V4(ip) => {
+        mask(u128::from(u32::from(network)), bits, 32) == mask(u128::from(u32::from(ip)), bits, 32)
+      }
+      IpAddr::V6(_) => false,
+    },
+    Some((IpAddr::V6(network), bits)) => match ip {
+      IpAddr::V6(ip) => mask(u128::from(network), bits, 128) == mask(u128::from(ip), bits, 128),
+      IpAddr::V4(_) => false,
+    },
+    None => false,
+  }
+}
+
+fn parse_ip_range(range: &str) -> Option<(IpAddr, u32)> {
+  let (ip, bits) = match range.split_once('/') {
+    Some((ip, bits)) => (ip.parse::<IpAddr>().ok()?, Some(bits.parse::<u32>().ok()?)),
+    None => (range.parse::<IpAddr>().ok()?, None),
+  };
+  let width = if ip.is_ipv4() { 32 } else { 128 };
+  let bits = bits.unwrap_or(width);
+
+  (bits <= width).then_some((ip, bits))
+}
+
+// Keeps the `bits` most significant bits of a `width` bit address.
+fn mask(address: u128, bits: u32, width: u32) -> u128 {
+  if bits == 0 {
+    return 0;
+  }
+  address >> (width - bits)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublicApiKey {
+  #[serde(alias = "_id", serialize_with = "serialize_object_id_as_hex_string")]
+  pub id: ObjectId,
+  #[serde(serialize_with = "serialize_object_id_as_hex_string")]
+  pub account: ObjectId,
+  pub name: String,
+  pub prefix: String,
+  pub scopes: Vec<String>,
+  pub allowed_ips: Vec<String>,
+  #[serde(with = "date::optional_rfc3339")]
+  pub expires_at: Option<Date>,
+  #[serde(with = "date::optional_rfc3339")]
+  pub last_used_at: Option<Date>,
+  #[serde(with = "date::optional_rfc3339")]
+  pub revoked_at: Option<Date>,
+  #[serde(with = "bson_datetime_as_rfc3339_string")]
+  pub created_at: Date,
+}
+
+impl From<ApiKey> for PublicApiKey {
+  fn from(api_key: ApiKey) -> Self {
+    Self {
+      id: api_key.id.unwrap(),
+      account: api_key.account,
+      name: api_key.name,
+      prefix: api_key.prefix,
+      scopes: api_key.scopes,
+      allowed_ips: api_key.allowed_ips,
+      expires_at: api_key.expires_at,
+      last_used_at: api_key.last_used_at,
+      revoked_at: api_key.revoked_at,
+      created_at: api_key.created_at,
+    }
+  }
+}
@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId, Document};
+use wither::Model as WitherModel;
+
+use crate::common::date;
+use crate::common::date::Date;
+use crate::common::models::ModelExt;
+use crate::database::Database;
+
+// Kinds of recorded events.
+pub const ACCOUNT_LOCKOUT: &str = "account.lockout";
+pub const PASSWORD_CHANGE: &str = "account.password_change";
+pub const PASSWORD_RESET: &str = "account.password_reset";
+pub const MFA_DISABLE: &str = "account.mfa_disable";
+
+#[derive(Clone)]
+pub struct Model {
+  pub db: Database,
+}
+
+impl Model {
+  pub fn new(db: Database) -> Self {
+    Self { db }
+  }
+}
+
+impl ModelExt for Model {
+  type T = AuditEvent;
+  fn get_database(&self) -> &Database {
+    &self.db
+  }
+}
+
+/// A security relevant event, kept for later review.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(
+  index(keys = r#"doc!{ "account": 1, "created": -1 }"#),
+  index(keys = r#"doc!{ "kind": 1, "created": -1 }"#),
+)]
+pub struct AuditEvent {
+  #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+  pub id: Option<ObjectId>,
+  pub account: Option<ObjectId>,
+  pub kind: String,
+  pub detail: Document,
+  pub created: Date,
+}
+
+impl AuditEvent {
+  pub fn new(account: Option<ObjectId>, kind: &str, detail: Document) -> Self {
+    Self {
+      id: None,
+      account,
+      kind: kind.to_owned(),
+      detail,
+      created: date::now(),
+    }
+  }
+}
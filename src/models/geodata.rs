@@ -1,16 +1,27 @@
 use bson::serde_helpers::bson_datetime_as_rfc3339_string;
 use bson::serde_helpers::serialize_object_id_as_hex_string;
+use futures::TryStreamExt;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 use tokio::task;
-use wither::bson::{doc, oid::ObjectId};
+use wither::bson::{self, doc, oid::ObjectId, Bson, Document};
+use wither::mongodb::options::{FindOneOptions, FindOptions};
 use wither::Model as WitherModel;
+use wither::ModelCursor;
 
 use crate::database::Database;
 use crate::common::hasher;
-use crate::errors::Error;
-use crate::common::date::{self, Date};
+use crate::errors::{BadRequest, Error};
+use crate::common::date::{self, Date, Interval};
+use crate::common::geohash;
+use crate::common::geojson::Feature;
+pub use crate::common::geojson::Geometry;
 use crate::common::models::ModelExt;
+use crate::common::object_id;
+use crate::common::pagination::{Page, Pagination};
+use crate::common::tenant::Tenant;
+use crate::models::geodata_version::GeodataVersion;
 
 #[derive(Clone)]
 pub struct Model {
@@ -21,6 +32,325 @@ impl Model {
   pub fn new(db: Database) -> Self {
     Self { db }
   }
+
+  /// Like `find`, limited to the geodata `tenant` may read. Routes go
+  /// through the scoped queries so no tenant sees another's data. Deleted
+  /// geodata is left out.
+  pub async fn find_scoped<O>(
+    &self,
+    tenant: &Tenant,
+    query: Document,
+    options: O,
+  ) -> Result<Vec<Geodata>, Error>
+  where
+    O: Into<Option<FindOptions>> + Send,
+  {
+    self.find(tenant.scope(live(query)), options).await
+  }
+
+  pub async fn find_one_scoped<O>(
+    &self,
+    tenant: &Tenant,
+    query: Document,
+    options: O,
+  ) -> Result<Option<Geodata>, Error>
+  where
+    O: Into<Option<FindOneOptions>> + Send,
+  {
+    self.find_one(tenant.scope(live(query)), options).await
+  }
+
+  /// Like `cursor`, limited to the geodata `tenant` may read.
+  pub async fn cursor_scoped<O>(
+    &self,
+    tenant: &Tenant,
+    query: Document,
+    options: O,
+  ) -> Result<ModelCursor<Geodata>, Error>
+  where
+    O: Into<Option<FindOptions>> + Send,
+  {
+    self.cursor(tenant.scope(live(query)), options).await
+  }
+
+  /// Like `find_page`, limited to the geodata `tenant` may read.
+  pub async fn find_page_scoped(
+    &self,
+    tenant: &Tenant,
+    query: Document,
+    pagination: &Pagination,
+  ) -> Result<Page<Geodata>, Error> {
+    self.find_page(tenant.scope(live(query)), pagination).await
+  }
+
+  /// Runs `stages` over the geodata `tenant` may read matching `query`.
+  pub async fn aggregate_scoped<A>(
+    &self,
+    tenant: &Tenant,
+    query: Document,
+    stages: Vec<Document>,
+  ) -> Result<Vec<A>, Error>
+  where
+    A: Serialize + DeserializeOwned,
+  {
+    let mut pipeline = vec![doc! { "$match": tenant.scope(live(query)) }];
+    pipeline.extend(stages);
+    self.aggregate(pipeline).await
+  }
+
+  /// Counts the geodata `tenant` may read matching `query` between `min`
+  /// and `max` meters of a point. `$near` queries can't be counted,
+  /// `$geoNear` ones can.
+  pub async fn count_near(
+    &self,
+    tenant: &Tenant,
+    query: Document,
+    point: [f64; 2],
+    min: f64,
+    max: f64,
+  ) -> Result<u64, Error> {
+    let pipeline = vec![
+      doc! { "$geoNear": {
+        "near": { "type": "Point", "coordinates": point.to_vec() },
+        "distanceField": "distance",
+        "minDistance": min,
+        "maxDistance": max,
+        "query": tenant.scope(live(query)),
+        "spherical": true,
+      } },
+      doc! { "$count": "total" },
+    ];
+    let count = self.aggregate::<Count>(pipeline).await?;
+
+    Ok(count.first().map_or(0, |count| count.total))
+  }
+
+  /// Replaces the content of `geodata` with `revision`, provided nobody
+  /// revised or deleted it since it was read. Returns the new version.
+  pub async fn revise(
+    &self,
+    geodata: &Geodata,
+    revision: &Geodata,
+  ) -> Result<Option<Geodata>, Error> {
+    let update = doc! { "$set": {
+      "location": bson::to_bson(&revision.location)?,
+      "geotype": &revision.geotype,
+      "value": revision.value,
+      "source": &revision.source,
+      "quality": revision.quality,
+      "observed_at": bson::to_bson(&revision.observed_at)?,
+      "geohash": geohash::of(&revision.location),
+      "version": revision.version,
+      "previous_hash": revision.previous_hash.clone(),
+      "updated": revision.updated,
+      "updated_by": revision.updated_by,
+    } };
+
+    // Records from before versioning have no version field.
+    let version = match geodata.version {
+      1 => doc! { "$in": [1, null] },
+      version => doc! { "$eq": version },
+    };
+
+    self
+      .find_one_and_update(
+        doc! { "_id": geodata.id, "version": version, "deleted_at": null },
+        update,
+      )
+      .await
+  }
+
+  /// Stores the geohash of up to `batch_size` geodata from before geohashes
+  /// were kept, in one bulk update. Geodata without positions gets a null
+  /// geohash, so it isn't visited again. Returns how many were updated, none
+  /// once no geodata is left without.
+  pub async fn backfill_geohashes(&self, batch_size: i64) -> Result<u64, Error> {
+    let collection = Geodata::collection(&self.db.conn);
+    let missing = doc! { "geohash": { "$exists": false } };
+    let options = FindOptions::builder()
+      .projection(doc! { "location": 1 })
+      .limit(batch_size)
+      .build();
+    let documents = collection
+      .find(missing.clone(), options)
+      .await
+      .map_err(Error::Mongo)?
+      .try_collect::<Vec<Document>>()
+      .await
+      .map_err(Error::Mongo)?;
+    if documents.is_empty() {
+      return Ok(0);
+    }
+
+    let updates = documents
+      .into_iter()
+      .map(|document| {
+        let geohash = document
+          .get("location")
+          .cloned()
+          .and_then(|location| bson::from_bson::<Location>(location).ok())
+          .and_then(|location| geohash::of(&location));
+        let mut query = missing.clone();
+        query.insert("_id", document.get("_id").cloned());
+        doc! { "q": query, "u": { "$set": { "geohash": geohash } } }
+      })
+      .collect::<Vec<Document>>();
+    let command = doc! {
+      "update": collection.name(),
+      "updates": updates,
+      "ordered": false,
+    };
+    let result = self.db.conn.run_command(command, None).await.map_err(Error::Mongo)?;
+
+    Ok(result.get_i32("nModified").unwrap_or(0) as u64)
+  }
+
+  /// Marks geodata as deleted. The record stays as a tombstone, so its
+  /// history can still be validated.
+  pub async fn delete_soft(
+    &self,
+    id: &ObjectId,
+    account: &ObjectId,
+  ) -> Result<Option<Geodata>, Error> {
+    self
+      .find_one_and_update(
+        doc! { "_id": id, "deleted_at": null },
+        doc! { "$set": { "deleted_at": date::now(), "deleted_by": account } },
+      )
+      .await
+  }
+}
+
+/// Attribute filters of geodata queries. `geotype` takes a comma separated
+/// list, dates are RFC 3339 and ranges include their bounds, except
+/// `created_before` and `observed_before`. Observation filters match the
+/// geodata observed at any time within their range, and leave out geodata
+/// without an observation time.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct GeodataFilter {
+  pub geotype: Option<String>,
+  pub source: Option<String>,
+  pub min_quality: Option<i32>,
+  pub max_quality: Option<i32>,
+  pub min_value: Option<f64>,
+  pub max_value: Option<f64>,
+  pub created_after: Option<String>,
+  pub created_before: Option<String>,
+  pub observed_after: Option<String>,
+  pub observed_before: Option<String>,
+}
+
+impl GeodataFilter {
+  /// The Mongo filter matching these attributes.
+  pub fn to_query(&self) -> Result<Document, Error> {
+    let mut query = doc! {};
+
+    if let Some(geotype) = &self.geotype {
+      let geotypes = geotype
+        .split(',')
+        .map(str::trim)
+        .filter(|geotype| !geotype.is_empty())
+        .collect::<Vec<&str>>();
+      match geotypes.as_slice() {
+        [] => return Err(invalid("geotype", "Geotype must not be empty")),
+        [geotype] => query.insert("geotype", *geotype),
+        geotypes => query.insert("geotype", doc! { "$in": geotypes.to_vec() }),
+      };
+    }
+    if let Some(source) = &self.source {
+      query.insert("source", source);
+    }
+
+    if let (Some(min), Some(max)) = (self.min_quality, self.max_quality) {
+      if min > max {
+        return Err(invalid("min_quality", "Minimum quality exceeds the maximum"));
+      }
+    }
+    let quality = range(self.min_quality, self.max_quality, "$lte");
+    if !quality.is_empty() {
+      query.insert("quality", quality);
+    }
+
+    for (field, value) in [("min_value", self.min_value), ("max_value", self.max_value)] {
+      if value.map_or(false, |value| !value.is_finite()) {
+        return Err(invalid(field, "Value must be a finite number"));
+      }
+    }
+    if let (Some(min), Some(max)) = (self.min_value, self.max_value) {
+      if min > max {
+        return Err(invalid("min_value", "Minimum value exceeds the maximum"));
+      }
+    }
+    let value = range(self.min_value, self.max_value, "$lte");
+    if !value.is_empty() {
+      query.insert("value", value);
+    }
+
+    let after = parse_date("created_after", &self.created_after)?;
+    let before = parse_date("created_before", &self.created_before)?;
+    if let (Some(after), Some(before)) = (after, before) {
+      if after >= before {
+        return Err(invalid("created_after", "Range ends before it starts"));
+      }
+    }
+    let created = range(after, before, "$lt");
+    if !created.is_empty() {
+      query.insert("created", created);
+    }
+
+    let after = parse_date("observed_after", &self.observed_after)?;
+    let before = parse_date("observed_before", &self.observed_before)?;
+    if let (Some(after), Some(before)) = (after, before) {
+      if after >= before {
+        return Err(invalid("observed_after", "Range ends before it starts"));
+      }
+    }
+    // Intervals overlapping the range, instants within it.
+    if let Some(after) = after {
+      query.insert("observed_at.end", doc! { "$gte": after });
+    }
+    if let Some(before) = before {
+      query.insert("observed_at.start", doc! { "$lt": before });
+    }
+
+    Ok(query)
+  }
+}
+
+fn range<T: Into<Bson>>(min: Option<T>, max: Option<T>, max_operator: &str) -> Document {
+  let mut range = doc! {};
+  if let Some(min) = min {
+    range.insert("$gte", min);
+  }
+  if let Some(max) = max {
+    range.insert(max_operator, max);
+  }
+  range
+}
+
+fn parse_date(field: &str, date: &Option<String>) -> Result<Option<Date>, Error> {
+  date
+    .as_deref()
+    .map(Date::parse_rfc3339_str)
+    .transpose()
+    .map_err(|_| invalid(field, "Date must be RFC 3339, e.g. 2022-06-01T00:00:00Z"))
+}
+
+fn invalid(field: &str, message: &str) -> Error {
+  Error::BadRequest(BadRequest::new(field.to_owned(), message.to_owned()))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Count {
+  total: u64,
+}
+
+// Leaves out deleted geodata, unless the query asks about deletion itself.
+fn live(mut query: Document) -> Document {
+  if !query.contains_key("deleted_at") {
+    query.insert("deleted_at", Bson::Null);
+  }
+  query
 }
 
 impl ModelExt for Model {
@@ -30,38 +360,69 @@ impl ModelExt for Model {
   }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Geometry {
-  pub r#type: String,
-  pub coordinates: Vec<f64>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Location {
-  pub r#type: String,
-  pub geometries: Vec<Geometry>,
-}
+/// Where geodata was observed, as a GeoJSON geometry. Usually a
+/// GeometryCollection of Points.
+pub type Location = Geometry;
 
 #[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
 #[model(
   index(keys=r#"doc!{ "account": 1 }"#),
+  index(keys=r#"doc!{ "organization": 1, "geotype": 1, "created": 1 }"#),
+  index(keys=r#"doc!{ "organization": 1, "created": 1 }"#),
+  index(keys=r#"doc!{ "geotype": 1, "value": 1 }"#),
+  index(keys=r#"doc!{ "geotype": 1, "quality": 1 }"#),
+  index(keys=r#"doc!{ "source": 1, "created": 1 }"#),
+  index(keys=r#"doc!{ "observed_at.start": 1, "observed_at.end": 1 }"#),
+  index(keys=r#"doc!{ "organization": 1, "geotype": 1, "observed_at.start": 1 }"#),
+  index(keys=r#"doc!{ "geohash": 1 }"#),
   index(keys=r#"doc!{ "location" : "2dsphere" }"#),
 )]
 pub struct Geodata {
   #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
   pub id: Option<ObjectId>,
   pub account: ObjectId,
+  // Owning tenant, that of the creating account.
+  #[serde(default)]
+  pub organization: Option<ObjectId>,
   pub location: Location,
   pub geotype: String,
   pub value: f64,
   pub source: String,
   pub quality: i32,
+  // When the value was observed, as opposed to `created`, when it was
+  // ingested. Absent when the source didn't tell.
+  #[serde(default)]
+  pub observed_at: Option<Interval>,
+  // Full precision geohash of the location, kept with it so grids of any
+  // precision group geodata by a prefix of it.
+  #[serde(default)]
+  pub geohash: Option<String>,
   pub created: Date,
+  // Starts at 1 and grows with each revision. Earlier versions are kept in
+  // `geodata_versions`.
+  #[serde(default = "first_version")]
+  pub version: i32,
+  // Hash of the previous version, which links the versions into a chain.
+  #[serde(default)]
+  pub previous_hash: Option<String>,
+  #[serde(default)]
+  pub updated: Option<Date>,
+  #[serde(default)]
+  pub updated_by: Option<ObjectId>,
+  #[serde(default)]
+  pub deleted_at: Option<Date>,
+  #[serde(default)]
+  pub deleted_by: Option<ObjectId>,
+}
+
+fn first_version() -> i32 {
+  1
 }
 
 impl Geodata {
   pub fn new(
     account: ObjectId,
+    organization: Option<ObjectId>,
     location: Location,
     geotype: String,
     value: f64,
@@ -71,14 +432,32 @@ impl Geodata {
     Self {
       id: None,
       account,
+      organization,
+      geohash: geohash::of(&location),
       location,
       geotype,
       value,
       source,
       quality,
+      observed_at: None,
       created: date::now(),
+      version: 1,
+      previous_hash: None,
+      updated: None,
+      updated_by: None,
+      deleted_at: None,
+      deleted_by: None,
     }
   }
+
+  pub fn with_observed_at(mut self, observed_at: Option<Interval>) -> Self {
+    self.observed_at = observed_at;
+    self
+  }
+
+  pub fn is_deleted(&self) -> bool {
+    self.deleted_at.is_some()
+  }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -87,13 +466,21 @@ pub struct PublicGeodata {
   pub id: ObjectId,
   #[serde(serialize_with = "serialize_object_id_as_hex_string")]
   pub account: ObjectId,
+  #[serde(default, serialize_with = "object_id::serialize_optional_hex")]
+  pub organization: Option<ObjectId>,
   pub location: Location,
   pub geotype: String,
   pub value: f64,
   pub source: String,
   pub quality: i32,
+  #[serde(default, with = "date::optional_interval")]
+  pub observed_at: Option<Interval>,
   #[serde(with = "bson_datetime_as_rfc3339_string")]
   pub created: Date,
+  #[serde(default = "first_version")]
+  pub version: i32,
+  #[serde(default, with = "date::optional_rfc3339")]
+  pub updated: Option<Date>,
 }
 
 impl From<Geodata> for PublicGeodata {
@@ -101,16 +488,34 @@ impl From<Geodata> for PublicGeodata {
     Self {
       id: geodata.id.unwrap(),
       account: geodata.account,
+      organization: geodata.organization,
       location: geodata.location.clone(),
       geotype: geodata.geotype.clone(),
       value: geodata.value.clone(),
       source: geodata.source.clone(),
       quality: geodata.quality.clone(),
+      observed_at: geodata.observed_at,
       created: geodata.created,
+      version: geodata.version,
+      updated: geodata.updated,
     }
   }
 }
 
+/// A GeoJSON Feature with the location as geometry and the other fields as
+/// properties.
+impl From<PublicGeodata> for Feature {
+  fn from(geodata: PublicGeodata) -> Self {
+    let mut properties = match serde_json::to_value(&geodata) {
+      Ok(serde_json::Value::Object(properties)) => properties,
+      _ => serde_json::Map::new(),
+    };
+    properties.remove("location");
+
+    Feature::new(Some(geodata.id.to_hex()), geodata.location, properties)
+  }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HashableGeodata {
   pub location: Location,
@@ -118,6 +523,13 @@ pub struct HashableGeodata {
   pub value: f64,
   pub source: String,
   pub quality: i32,
+  // Like the previous hash, left out when absent so geodata from before
+  // observation times hashes as it always did.
+  #[serde(with = "date::optional_interval", skip_serializing_if = "Option::is_none")]
+  pub observed_at: Option<Interval>,
+  // Only revisions have one, so first versions hash as they always did.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub previous_hash: Option<String>,
 }
 
 impl From<Geodata> for HashableGeodata {
@@ -128,10 +540,32 @@ impl From<Geodata> for HashableGeodata {
       value: geodata.value.clone(),
       source: geodata.source.clone(),
       quality: geodata.quality.clone(),
+      observed_at: geodata.observed_at,
+      previous_hash: geodata.previous_hash,
+    }
+  }
+}
+
+impl From<GeodataVersion> for HashableGeodata {
+  fn from(version: GeodataVersion) -> Self {
+    Self {
+      location: version.location,
+      geotype: version.geotype,
+      value: version.value,
+      source: version.source,
+      quality: version.quality,
+      observed_at: version.observed_at,
+      previous_hash: version.previous_hash,
     }
   }
 }
 
+/// Hashes the content of geodata, as anchored and validated.
+pub async fn hash_geodata<H: Into<HashableGeodata>>(geodata: H) -> Result<String, Error> {
+  let hashable = serde_json::to_string(&geodata.into()).unwrap();
+  hash_data(hashable).await
+}
+
 pub async fn hash_data<P>(data: P) -> Result<String, Error>
 where
   P: AsRef<str> + Send + 'static,
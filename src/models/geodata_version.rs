@@ -0,0 +1,165 @@
+use bson::serde_helpers::bson_datetime_as_rfc3339_string;
+use bson::serde_helpers::serialize_object_id_as_hex_string;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId};
+use wither::mongodb::options::{FindOptions, UpdateOptions};
+use wither::Model as WitherModel;
+
+use crate::common::date;
+use crate::common::date::{Date, Interval};
+use crate::common::models::ModelExt;
+use crate::database::Database;
+use crate::errors::Error;
+use crate::models::geodata::{Geodata, Location};
+
+#[derive(Clone)]
+pub struct Model {
+  pub db: Database,
+}
+
+impl Model {
+  pub fn new(db: Database) -> Self {
+    Self { db }
+  }
+
+  /// Versions of a geodata record before `version`, oldest first.
+  pub async fn find_for_geodata(
+    &self,
+    geodata: &ObjectId,
+    version: i32,
+  ) -> Result<Vec<GeodataVersion>, Error> {
+    let options = FindOptions::builder().sort(doc! { "version": 1 }).build();
+    let query = doc! { "geodata": geodata, "version": { "$lt": version } };
+    self.find(query, options).await
+  }
+
+  /// Keeps a snapshot unless one of its version was kept already, as a
+  /// revision snapshots the version it replaces before replacing it, and
+  /// may be retried or completed by the anchor worker.
+  pub async fn snapshot(&self, version: GeodataVersion) -> Result<(), Error> {
+    let query = doc! { "geodata": version.geodata, "version": version.version };
+    let update = doc! { "$setOnInsert": bson::to_document(&version)? };
+    let options = UpdateOptions::builder().upsert(true).build();
+    match self.update_one(query, update, options).await {
+      Err(err) if !err.is_duplicate_key() => Err(err),
+      _ => Ok(()),
+    }
+  }
+
+  /// Records the revision that replaced `version` once it's written.
+  pub async fn supersede(
+    &self,
+    geodata: &ObjectId,
+    version: i32,
+    superseded_by: &ObjectId,
+    superseded_at: Date,
+  ) -> Result<(), Error> {
+    let query = doc! { "geodata": geodata, "version": version };
+    let update = doc! { "$set": {
+      "superseded_by": superseded_by,
+      "superseded_at": superseded_at,
+    } };
+    self.update_one(query, update, None).await?;
+    Ok(())
+  }
+}
+
+impl ModelExt for Model {
+  type T = GeodataVersion;
+  fn get_database(&self) -> &Database {
+    &self.db
+  }
+}
+
+/// An immutable snapshot of a geodata record, taken when a revision
+/// replaced it. A snapshot is only part of the history once the current
+/// version is past it, as a revision that failed may leave one behind. Each snapshot keeps the hash of its content, and the hash of
+/// the version before it, so the versions of a record form a hash chain.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(
+  index(keys = r#"doc!{ "geodata": 1, "version": 1 }"#, options = r#"doc!{ "unique": true }"#),
+)]
+pub struct GeodataVersion {
+  #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+  pub id: Option<ObjectId>,
+  pub geodata: ObjectId,
+  pub version: i32,
+  // Author of this version.
+  pub account: ObjectId,
+  pub organization: Option<ObjectId>,
+  pub location: Location,
+  pub geotype: String,
+  pub value: f64,
+  pub source: String,
+  pub quality: i32,
+  #[serde(default)]
+  pub observed_at: Option<Interval>,
+  pub previous_hash: Option<String>,
+  pub hash: String,
+  // When this version was written.
+  pub created: Date,
+  pub superseded_at: Date,
+  pub superseded_by: ObjectId,
+}
+
+impl GeodataVersion {
+  /// Snapshots `geodata` as it was before being revised by `superseded_by`.
+  pub fn new(geodata: Geodata, hash: String, superseded_by: ObjectId) -> Self {
+    Self {
+      id: None,
+      geodata: geodata.id.unwrap(),
+      version: geodata.version,
+      account: geodata.updated_by.unwrap_or(geodata.account),
+      organization: geodata.organization,
+      location: geodata.location,
+      geotype: geodata.geotype,
+      value: geodata.value,
+      source: geodata.source,
+      quality: geodata.quality,
+      observed_at: geodata.observed_at,
+      previous_hash: geodata.previous_hash,
+      hash,
+      created: geodata.updated.unwrap_or(geodata.created),
+      superseded_at: date::now(),
+      superseded_by,
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublicGeodataVersion {
+  pub version: i32,
+  #[serde(serialize_with = "serialize_object_id_as_hex_string")]
+  pub account: ObjectId,
+  pub location: Location,
+  pub geotype: String,
+  pub value: f64,
+  pub source: String,
+  pub quality: i32,
+  #[serde(default, with = "date::optional_interval")]
+  pub observed_at: Option<Interval>,
+  pub hash: String,
+  #[serde(with = "bson_datetime_as_rfc3339_string")]
+  pub created: Date,
+  #[serde(with = "bson_datetime_as_rfc3339_string")]
+  pub superseded_at: Date,
+}
+
+impl From<GeodataVersion> for PublicGeodataVersion {
+  fn from(version: GeodataVersion) -> Self {
+    Self {
+      version: version.version,
+      account: version.account,
+      location: version.location,
+      geotype: version.geotype,
+      value: version.value,
+      source: version.source,
+      quality: version.quality,
+      observed_at: version.observed_at,
+      hash: version.hash,
+      created: version.created,
+      superseded_at: version.superseded_at,
+    }
+  }
+}
@@ -0,0 +1,323 @@
+use bson::serde_helpers::bson_datetime_as_rfc3339_string;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId};
+use wither::mongodb::options::UpdateOptions;
+use wither::Model as WitherModel;
+
+use crate::common::date;
+use crate::common::date::Date;
+use crate::common::geojson::{Geometry, GEOMETRY_TYPES};
+use crate::common::models::ModelExt;
+use crate::database::Database;
+use crate::errors::{Error, FieldError, InvalidFields};
+use crate::models::geodata;
+
+#[derive(Clone)]
+pub struct Model {
+  pub db: Database,
+}
+
+impl Model {
+  pub fn new(db: Database) -> Self {
+    Self { db }
+  }
+
+  pub async fn find_by_name(&self, name: &str) -> Result<Option<Geotype>, Error> {
+    self.find_one(doc! { "name": name }, None).await
+  }
+
+  /// Every registered geotype by name, for checking many records at once.
+  pub async fn registry(&self) -> Result<HashMap<String, Geotype>, Error> {
+    let geotypes = self.find(doc! {}, None).await?;
+    Ok(
+      geotypes
+        .into_iter()
+        .map(|geotype| (geotype.name.clone(), geotype))
+        .collect(),
+    )
+  }
+
+  /// Registers the geotypes geodata uses without them being registered, as
+  /// geodata from before the registry does. Their definitions allow every
+  /// value, and the qualities and geometry types of that geodata, for admins
+  /// to narrow down. Returns how many were registered.
+  pub async fn register_in_use(&self, geodata: &geodata::Model) -> Result<u64, Error> {
+    let pipeline = vec![doc! { "$group": {
+      "_id": "$geotype",
+      "min_quality": { "$min": "$quality" },
+      "max_quality": { "$max": "$quality" },
+      "geometry_types": { "$addToSet": "$location.type" },
+    } }];
+    let in_use = geodata.aggregate::<GeotypeInUse>(pipeline).await?;
+
+    let mut count = 0;
+    for geotype in in_use {
+      let mut geometry_types = geotype.geometry_types;
+      geometry_types.sort();
+      let geotype = Geotype::new(GeotypeDefinition {
+        name: geotype.name,
+        unit: String::from("1"),
+        description: Some(String::from("Registered from the geodata using it")),
+        min_value: None,
+        max_value: None,
+        geometry_types,
+        min_quality: geotype.min_quality,
+        max_quality: geotype.max_quality,
+      });
+      // Names differing only in case are one geotype, registered once.
+      let query = doc! { "key": &geotype.key };
+      let update = doc! { "$setOnInsert": bson::to_document(&geotype)? };
+      let options = UpdateOptions::builder().upsert(true).build();
+      match self.update_one(query, update, options).await {
+        Ok(result) if result.upserted_id.is_some() => count += 1,
+        Err(err) if !err.is_duplicate_key() => return Err(err),
+        _ => {}
+      }
+    }
+
+    Ok(count)
+  }
+
+  /// Checks geodata against the definition of its geotype.
+  pub async fn validate_geodata(
+    &self,
+    geotype: &str,
+    location: &Geometry,
+    value: f64,
+    quality: i32,
+  ) -> Result<(), Error> {
+    let errors = match self.find_by_name(geotype).await? {
+      Some(geotype) => geotype.errors(location, value, quality),
+      None => vec![unknown(geotype)],
+    };
+    if errors.is_empty() {
+      return Ok(());
+    }
+
+    Err(Error::InvalidFields(InvalidFields::new(errors)))
+  }
+}
+
+impl ModelExt for Model {
+  type T = Geotype;
+  fn get_database(&self) -> &Database {
+    &self.db
+  }
+}
+
+/// The definition of a kind of geodata, such as `Wind`. Geodata may only use
+/// registered geotypes, and its value, location and quality must fit their
+/// definition. `key` is the lowercase name, so names differing only in case
+/// can't be registered side by side.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(
+  index(keys = r#"doc!{ "name": 1 }"#, options = r#"doc!{ "unique": true }"#),
+  index(keys = r#"doc!{ "key": 1 }"#, options = r#"doc!{ "unique": true }"#),
+)]
+pub struct Geotype {
+  #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+  pub id: Option<ObjectId>,
+  #[validate(length(min = 1))]
+  pub name: String,
+  pub key: String,
+  // UCUM case sensitive code of the unit of values, e.g. `m/s` or `Cel`.
+  #[validate(length(min = 1))]
+  pub unit: String,
+  pub description: Option<String>,
+  pub min_value: Option<f64>,
+  pub max_value: Option<f64>,
+  // Allowed types of the location. A GeometryCollection is allowed when all
+  // its members are.
+  pub geometry_types: Vec<String>,
+  pub min_quality: i32,
+  pub max_quality: i32,
+  pub created_at: Date,
+  pub updated_at: Date,
+}
+
+impl Geotype {
+  pub fn new(definition: GeotypeDefinition) -> Self {
+    let now = date::now();
+    Self {
+      id: None,
+      key: definition.name.to_lowercase(),
+      name: definition.name,
+      unit: definition.unit,
+      description: definition.description,
+      min_value: definition.min_value,
+      max_value: definition.max_value,
+      geometry_types: definition.geometry_types,
+      min_quality: definition.min_quality,
+      max_quality: definition.max_quality,
+      created_at: now,
+      updated_at: now,
+    }
+  }
+
+  /// Every way geodata with this geotype breaks its definition.
+  pub fn errors(&self, location: &Geometry, value: f64, quality: i32) -> Vec<FieldError> {
+    let mut errors = vec![];
+    let below = matches!(self.min_value, Some(min) if value < min);
+    let above = matches!(self.max_value, Some(max) if value > max);
+    if below || above {
+      let message = format!(
+        "{} values range from {} to {} {}",
+        self.name,
+        bound(self.min_value),
+        bound(self.max_value),
+        self.unit
+      );
+      errors.push(field_error("value", message));
+    }
+    if !self.allows(location) {
+      let message = format!(
+        "{} locations are {}",
+        self.name,
+        self.geometry_types.join(", ")
+      );
+      errors.push(field_error("location", message));
+    }
+    if quality < self.min_quality || quality > self.max_quality {
+      let message = format!(
+        "{} quality ranges from {} to {}",
+        self.name, self.min_quality, self.max_quality
+      );
+      errors.push(field_error("quality", message));
+    }
+
+    errors
+  }
+
+  fn allows(&self, location: &Geometry) -> bool {
+    let allowed = |geometry: &Geometry| {
+      self
+        .geometry_types
+        .iter()
+        .any(|name| name == geometry.type_name())
+    };
+    match location {
+      Geometry::GeometryCollection { geometries } if !allowed(location) => {
+        geometries.iter().all(allowed)
+      }
+      _ => allowed(location),
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct GeotypeInUse {
+  #[serde(rename = "_id")]
+  name: String,
+  min_quality: i32,
+  max_quality: i32,
+  geometry_types: Vec<String>,
+}
+
+/// A geotype as created or replaced by admins.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeotypeDefinition {
+  pub name: String,
+  pub unit: String,
+  pub description: Option<String>,
+  pub min_value: Option<f64>,
+  pub max_value: Option<f64>,
+  pub geometry_types: Vec<String>,
+  pub min_quality: i32,
+  pub max_quality: i32,
+}
+
+impl GeotypeDefinition {
+  /// Every problem of the definition itself.
+  pub fn errors(&self) -> Vec<FieldError> {
+    let mut errors = vec![];
+    if self.name.is_empty() || self.name.trim() != self.name {
+      errors.push(field_error(
+        "name",
+        "Name must be non-empty, without surrounding whitespace".to_owned(),
+      ));
+    }
+    // UCUM codes are printable ASCII without spaces.
+    if self.unit.is_empty() || !self.unit.chars().all(|c| c.is_ascii_graphic()) {
+      errors.push(field_error("unit", "Unit must be a UCUM code".to_owned()));
+    }
+    let infinite = |bound: Option<f64>| matches!(bound, Some(bound) if !bound.is_finite());
+    if infinite(self.min_value) || infinite(self.max_value) {
+      errors.push(field_error("min_value", "Value bounds must be finite".to_owned()));
+    } else if let (Some(min), Some(max)) = (self.min_value, self.max_value) {
+      if min > max {
+        errors.push(field_error("min_value", "min_value exceeds max_value".to_owned()));
+      }
+    }
+    if self.geometry_types.is_empty() {
+      errors.push(field_error(
+        "geometry_types",
+        "Allow one geometry type or more".to_owned(),
+      ));
+    }
+    for (i, name) in self.geometry_types.iter().enumerate() {
+      if !GEOMETRY_TYPES.contains(&name.as_str()) {
+        let message = format!("Unknown geometry type {}", name);
+        errors.push(field_error(&format!("geometry_types[{}]", i), message));
+      }
+    }
+    if self.min_quality > self.max_quality {
+      errors.push(field_error(
+        "min_quality",
+        "min_quality exceeds max_quality".to_owned(),
+      ));
+    }
+
+    errors
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublicGeotype {
+  pub name: String,
+  pub unit: String,
+  pub description: Option<String>,
+  pub min_value: Option<f64>,
+  pub max_value: Option<f64>,
+  pub geometry_types: Vec<String>,
+  pub min_quality: i32,
+  pub max_quality: i32,
+  #[serde(with = "bson_datetime_as_rfc3339_string")]
+  pub created_at: Date,
+  #[serde(with = "bson_datetime_as_rfc3339_string")]
+  pub updated_at: Date,
+}
+
+impl From<Geotype> for PublicGeotype {
+  fn from(geotype: Geotype) -> Self {
+    Self {
+      name: geotype.name,
+      unit: geotype.unit,
+      description: geotype.description,
+      min_value: geotype.min_value,
+      max_value: geotype.max_value,
+      geometry_types: geotype.geometry_types,
+      min_quality: geotype.min_quality,
+      max_quality: geotype.max_quality,
+      created_at: geotype.created_at,
+      updated_at: geotype.updated_at,
+    }
+  }
+}
+
+/// The error for geodata of a geotype that isn't registered.
+pub fn unknown(geotype: &str) -> FieldError {
+  field_error("geotype", format!("Unknown geotype {}", geotype))
+}
+
+fn bound(bound: Option<f64>) -> String {
+  bound.map_or_else(|| "any".to_owned(), |bound| bound.to_string())
+}
+
+fn field_error(field: &str, message: String) -> FieldError {
+  FieldError {
+    field: field.to_owned(),
+    message,
+  }
+}
@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId};
+use wither::mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
+use wither::mongodb::results::DeleteResult;
+use wither::Model as WitherModel;
+
+use crate::common::date;
+use crate::common::date::Date;
+use crate::common::hasher;
+use crate::common::models::ModelExt;
+use crate::database::Database;
+use crate::errors::Error;
+
+#[derive(Clone)]
+pub struct Model {
+  pub db: Database,
+}
+
+impl Model {
+  pub fn new(db: Database) -> Self {
+    Self { db }
+  }
+
+  /// Counts a failed login with `email` and returns the failures in a row,
+  /// which are forgotten `ttl` seconds after the last one.
+  pub async fn record_failure(&self, email: &str, ttl: i64) -> Result<i32, Error> {
+    let options = FindOneAndUpdateOptions::builder()
+      .upsert(true)
+      .return_document(ReturnDocument::After)
+      .build();
+    let attempt = LoginAttempt::find_one_and_update(
+      &self.db.conn,
+      doc! { "email_hash": email_hash(email) },
+      doc! {
+        "$inc": { "failures": 1 },
+        "$set": { "expires_at": date::after(ttl) },
+      },
+      options,
+    )
+    .await
+    .map_err(Error::Wither)?;
+
+    Ok(attempt.map_or(1, |attempt| attempt.failures))
+  }
+
+  pub async fn clear(&self, email: &str) -> Result<DeleteResult, Error> {
+    self
+      .delete_one(doc! { "email_hash": email_hash(email) })
+      .await
+  }
+}
+
+impl ModelExt for Model {
+  type T = LoginAttempt;
+  fn get_database(&self) -> &Database {
+    &self.db
+  }
+}
+
+/// Failed logins with an email, whether or not an account has it, so login
+/// delays can't tell registered emails apart. Only the hash of the email is
+/// stored.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(
+  index(keys = r#"doc!{ "email_hash": 1 }"#, options = r#"doc!{ "unique": true }"#),
+  index(keys = r#"doc!{ "expires_at": 1 }"#, options = r#"doc!{ "expireAfterSeconds": 0 }"#),
+)]
+pub struct LoginAttempt {
+  #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+  pub id: Option<ObjectId>,
+  pub email_hash: String,
+  pub failures: i32,
+  pub expires_at: Date,
+}
+
+fn email_hash(email: &str) -> String {
+  hasher::hash(&email.trim().to_lowercase())
+}
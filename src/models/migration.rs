@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId};
+use wither::Model as WitherModel;
+
+use crate::common::date;
+use crate::common::date::Date;
+use crate::common::models::ModelExt;
+use crate::database::Database;
+use crate::errors::Error;
+
+#[derive(Clone)]
+pub struct Model {
+  pub db: Database,
+}
+
+impl Model {
+  pub fn new(db: Database) -> Self {
+    Self { db }
+  }
+
+  pub async fn is_applied(&self, name: &str) -> Result<bool, Error> {
+    self.exists(doc! { "name": name }).await
+  }
+
+  /// Records that a migration was applied. Replicas starting together may
+  /// both apply it, so a migration recorded already is fine.
+  pub async fn mark_applied(&self, name: &str) -> Result<(), Error> {
+    let migration = Migration {
+      id: None,
+      name: name.to_owned(),
+      applied_at: date::now(),
+    };
+    match self.create(migration).await {
+      Err(err) if !err.is_duplicate_key() => Err(err),
+      _ => Ok(()),
+    }
+  }
+}
+
+impl ModelExt for Model {
+  type T = Migration;
+  fn get_database(&self) -> &Database {
+    &self.db
+  }
+}
+
+/// A one-off data migration applied to the database, by name.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(index(keys = r#"doc!{ "name": 1 }"#, options = r#"doc!{ "unique": true }"#))]
+pub struct Migration {
+  #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+  pub id: Option<ObjectId>,
+  pub name: String,
+  pub applied_at: Date,
+}
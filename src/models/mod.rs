@@ -1,7 +1,19 @@
 pub mod role;
 pub mod account;
 pub mod geodata;
+pub mod geodata_version;
 pub mod validation;
+pub mod session;
+pub mod revoked_token;
+pub mod api_key;
+pub mod audit_event;
+pub mod password_reset;
+pub mod login_attempt;
+pub mod organization;
+pub mod sharing_grant;
+pub mod anchor_request;
+pub mod geotype;
+pub mod migration;
 use crate::common::models::ModelExt;
 use crate::database::Database;
 use crate::errors::Error;
@@ -11,7 +23,19 @@ pub struct Models {
   pub role: role::Model,
   pub account: account::Model,
   pub geodata: geodata::Model,
+  pub geodata_version: geodata_version::Model,
   pub validation: validation::Model,
+  pub session: session::Model,
+  pub revoked_token: revoked_token::Model,
+  pub api_key: api_key::Model,
+  pub audit_event: audit_event::Model,
+  pub password_reset: password_reset::Model,
+  pub login_attempt: login_attempt::Model,
+  pub organization: organization::Model,
+  pub sharing_grant: sharing_grant::Model,
+  pub anchor_request: anchor_request::Model,
+  pub geotype: geotype::Model,
+  pub migration: migration::Model,
 }
 
 impl Models {
@@ -19,8 +43,37 @@ impl Models {
     let role = role::Model::new(db.clone());
     let account = account::Model::new(db.clone());
     let geodata = geodata::Model::new(db.clone());
+    let geodata_version = geodata_version::Model::new(db.clone());
     let validation = validation::Model::new(db.clone());
-    let this = Self { role, account, geodata, validation };
+    let session = session::Model::new(db.clone());
+    let revoked_token = revoked_token::Model::new(db.clone());
+    let api_key = api_key::Model::new(db.clone());
+    let audit_event = audit_event::Model::new(db.clone());
+    let password_reset = password_reset::Model::new(db.clone());
+    let login_attempt = login_attempt::Model::new(db.clone());
+    let organization = organization::Model::new(db.clone());
+    let sharing_grant = sharing_grant::Model::new(db.clone());
+    let anchor_request = anchor_request::Model::new(db.clone());
+    let geotype = geotype::Model::new(db.clone());
+    let migration = migration::Model::new(db.clone());
+    let this = Self {
+      role,
+      account,
+      geodata,
+      geodata_version,
+      validation,
+      session,
+      revoked_token,
+      api_key,
+      audit_event,
+      password_reset,
+      login_attempt,
+      organization,
+      sharing_grant,
+      anchor_request,
+      geotype,
+      migration,
+    };
 
     this.sync_indexes().await?;
     Ok(this)
@@ -30,7 +83,19 @@ impl Models {
     self.role.sync_indexes().await?;
     self.account.sync_indexes().await?;
     self.geodata.sync_indexes().await?;
+    self.geodata_version.sync_indexes().await?;
     self.validation.sync_indexes().await?;
+    self.session.sync_indexes().await?;
+    self.revoked_token.sync_indexes().await?;
+    self.api_key.sync_indexes().await?;
+    self.audit_event.sync_indexes().await?;
+    self.password_reset.sync_indexes().await?;
+    self.login_attempt.sync_indexes().await?;
+    self.organization.sync_indexes().await?;
+    self.sharing_grant.sync_indexes().await?;
+    self.anchor_request.sync_indexes().await?;
+    self.geotype.sync_indexes().await?;
+    self.migration.sync_indexes().await?;
 
     Ok(())
   }
@@ -0,0 +1,73 @@
+use bson::serde_helpers::bson_datetime_as_rfc3339_string;
+use bson::serde_helpers::serialize_object_id_as_hex_string;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId};
+use wither::Model as WitherModel;
+
+use crate::common::date;
+use crate::common::date::Date;
+use crate::common::models::ModelExt;
+use crate::database::Database;
+
+#[derive(Clone)]
+pub struct Model {
+  pub db: Database,
+}
+
+impl Model {
+  pub fn new(db: Database) -> Self {
+    Self { db }
+  }
+}
+
+impl ModelExt for Model {
+  type T = Organization;
+  fn get_database(&self) -> &Database {
+    &self.db
+  }
+}
+
+/// A tenant. Accounts belong to at most one organization and only see the
+/// geodata of their organization, plus what other organizations share with
+/// it.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(
+  index(keys = r#"doc!{ "name": 1 }"#, options = r#"doc!{ "unique": true }"#),
+)]
+pub struct Organization {
+  #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+  pub id: Option<ObjectId>,
+  #[validate(length(min = 1))]
+  pub name: String,
+  pub created_at: Date,
+}
+
+impl Organization {
+  pub fn new(name: String) -> Self {
+    Self {
+      id: None,
+      name,
+      created_at: date::now(),
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublicOrganization {
+  #[serde(alias = "_id", serialize_with = "serialize_object_id_as_hex_string")]
+  pub id: ObjectId,
+  pub name: String,
+  #[serde(with = "bson_datetime_as_rfc3339_string")]
+  pub created_at: Date,
+}
+
+impl From<Organization> for PublicOrganization {
+  fn from(organization: Organization) -> Self {
+    Self {
+      id: organization.id.unwrap(),
+      name: organization.name,
+      created_at: organization.created_at,
+    }
+  }
+}
@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId, Document};
+use wither::mongodb::results::DeleteResult;
+use wither::Model as WitherModel;
+
+use crate::common::date;
+use crate::common::date::Date;
+use crate::common::hasher;
+use crate::common::models::ModelExt;
+use crate::database::Database;
+use crate::errors::Error;
+
+#[derive(Clone)]
+pub struct Model {
+  pub db: Database,
+}
+
+impl Model {
+  pub fn new(db: Database) -> Self {
+    Self { db }
+  }
+
+  /// Returns the reset matching `token`, unless it is unknown, expired or
+  /// already used, without using it.
+  pub async fn find_pending(&self, token: &str) -> Result<Option<PasswordReset>, Error> {
+    self.find_one(pending(token), None).await
+  }
+
+  /// Marks the reset matching `token` as used and returns it, unless it is
+  /// unknown, expired or already used.
+  pub async fn redeem(&self, token: &str) -> Result<Option<PasswordReset>, Error> {
+    self
+      .find_one_and_update(pending(token), doc! { "$set": { "used_at": date::now() } })
+      .await
+  }
+
+  /// Drops the pending resets of an account, so only the latest one works.
+  pub async fn delete_pending(&self, account: &ObjectId) -> Result<DeleteResult, Error> {
+    self
+      .delete_many(doc! { "account": account, "used_at": null })
+      .await
+  }
+}
+
+fn pending(token: &str) -> Document {
+  doc! {
+    "token_hash": hasher::hash(token),
+    "used_at": null,
+    "expires_at": { "$gt": date::now() },
+  }
+}
+
+impl ModelExt for Model {
+  type T = PasswordReset;
+  fn get_database(&self) -> &Database {
+    &self.db
+  }
+}
+
+/// A single-use token that lets the holder of an account set a new password.
+/// Only the hash of the token is stored.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(
+  index(keys = r#"doc!{ "token_hash": 1 }"#, options = r#"doc!{ "unique": true }"#),
+  index(keys = r#"doc!{ "account": 1 }"#),
+  index(keys = r#"doc!{ "expires_at": 1 }"#, options = r#"doc!{ "expireAfterSeconds": 0 }"#),
+)]
+pub struct PasswordReset {
+  #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+  pub id: Option<ObjectId>,
+  pub account: ObjectId,
+  pub token_hash: String,
+  pub expires_at: Date,
+  pub used_at: Option<Date>,
+  pub created_at: Date,
+}
+
+impl PasswordReset {
+  pub fn new(account: ObjectId, token_hash: String, expires_at: Date) -> Self {
+    Self {
+      id: None,
+      account,
+      token_hash,
+      expires_at,
+      used_at: None,
+      created_at: date::now(),
+    }
+  }
+}
@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId};
+use wither::Model as WitherModel;
+
+use crate::common::date;
+use crate::common::date::Date;
+use crate::common::models::ModelExt;
+use crate::database::Database;
+
+#[derive(Clone)]
+pub struct Model {
+  pub db: Database,
+}
+
+impl Model {
+  pub fn new(db: Database) -> Self {
+    Self { db }
+  }
+}
+
+impl ModelExt for Model {
+  type T = RevokedToken;
+  fn get_database(&self) -> &Database {
+    &self.db
+  }
+}
+
+/// An access token revoked before its expiry. Entries are dropped by the TTL
+/// index once the token would have expired anyway.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(
+  index(keys = r#"doc!{ "jti": 1 }"#, options = r#"doc!{ "unique": true }"#),
+  index(keys = r#"doc!{ "expires_at": 1 }"#, options = r#"doc!{ "expireAfterSeconds": 0 }"#),
+)]
+pub struct RevokedToken {
+  #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+  pub id: Option<ObjectId>,
+  pub jti: String,
+  pub account: ObjectId,
+  pub expires_at: Date,
+  pub created_at: Date,
+}
+
+impl RevokedToken {
+  pub fn new(jti: String, account: ObjectId, expires_at: Date) -> Self {
+    Self {
+      id: None,
+      jti,
+      account,
+      expires_at,
+      created_at: date::now(),
+    }
+  }
+}
@@ -8,6 +8,11 @@ use crate::common::date::Date;
 use crate::common::models::ModelExt;
 use crate::database::Database;
 
+/// Names of the roles that route guards check for.
+pub const ADMIN: &str = "admin";
+pub const USER: &str = "user";
+pub const VALIDATOR: &str = "validator";
+
 #[derive(Clone)]
 pub struct Model {
   pub db: Database,
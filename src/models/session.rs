@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId};
+use wither::mongodb::results::UpdateResult;
+use wither::Model as WitherModel;
+
+use crate::common::date;
+use crate::common::date::Date;
+use crate::common::models::ModelExt;
+use crate::database::Database;
+use crate::errors::Error;
+
+#[derive(Clone)]
+pub struct Model {
+  pub db: Database,
+}
+
+impl Model {
+  pub fn new(db: Database) -> Self {
+    Self { db }
+  }
+
+  /// Revokes every session descending from the same authentication.
+  pub async fn revoke_family(&self, family: &ObjectId) -> Result<UpdateResult, Error> {
+    self
+      .update_many(
+        doc! { "family": family, "revoked_at": null },
+        doc! { "$set": { "revoked_at": date::now() } },
+        None,
+      )
+      .await
+  }
+
+  /// Revokes every session of an account, e.g. after a password change.
+  pub async fn revoke_account(&self, account: &ObjectId) -> Result<UpdateResult, Error> {
+    self
+      .update_many(
+        doc! { "account": account, "revoked_at": null },
+        doc! { "$set": { "revoked_at": date::now() } },
+        None,
+      )
+      .await
+  }
+}
+
+impl ModelExt for Model {
+  type T = Session;
+  fn get_database(&self) -> &Database {
+    &self.db
+  }
+}
+
+/// A refresh token issued to an account. Only the hash of the token is
+/// stored. Each refresh rotates the token into a new session of the same
+/// family, so presenting a rotated token again reveals a replay.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(
+  index(keys = r#"doc!{ "token_hash": 1 }"#, options = r#"doc!{ "unique": true }"#),
+  index(keys = r#"doc!{ "family": 1 }"#),
+  index(keys = r#"doc!{ "account": 1 }"#),
+  index(keys = r#"doc!{ "expires_at": 1 }"#, options = r#"doc!{ "expireAfterSeconds": 0 }"#),
+)]
+pub struct Session {
+  #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+  pub id: Option<ObjectId>,
+  pub account: ObjectId,
+  pub family: ObjectId,
+  pub token_hash: String,
+  pub expires_at: Date,
+  pub rotated_at: Option<Date>,
+  pub revoked_at: Option<Date>,
+  pub created_at: Date,
+}
+
+impl Session {
+  pub fn new(account: ObjectId, family: ObjectId, token_hash: String, expires_at: Date) -> Self {
+    Self {
+      id: None,
+      account,
+      family,
+      token_hash,
+      expires_at,
+      rotated_at: None,
+      revoked_at: None,
+      created_at: date::now(),
+    }
+  }
+
+  pub fn is_active(&self) -> bool {
+    self.rotated_at.is_none() && self.revoked_at.is_none() && self.expires_at > date::now()
+  }
+}
@@ -0,0 +1,98 @@
+use bson::serde_helpers::bson_datetime_as_rfc3339_string;
+use bson::serde_helpers::serialize_object_id_as_hex_string;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId};
+use wither::Model as WitherModel;
+
+use crate::common::date;
+use crate::common::date::Date;
+use crate::common::models::ModelExt;
+use crate::database::Database;
+use crate::errors::Error;
+
+#[derive(Clone)]
+pub struct Model {
+  pub db: Database,
+}
+
+impl Model {
+  pub fn new(db: Database) -> Self {
+    Self { db }
+  }
+
+  /// Grants shared with `organization`.
+  pub async fn find_for_grantee(
+    &self,
+    organization: &ObjectId,
+  ) -> Result<Vec<SharingGrant>, Error> {
+    self.find(doc! { "grantee": organization }, None).await
+  }
+}
+
+impl ModelExt for Model {
+  type T = SharingGrant;
+  fn get_database(&self) -> &Database {
+    &self.db
+  }
+}
+
+/// Lets the accounts of the `grantee` organization read the geodata of the
+/// `owner` organization, limited to `geotypes` unless it is empty.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(
+  index(keys = r#"doc!{ "grantee": 1 }"#),
+  index(keys = r#"doc!{ "owner": 1, "grantee": 1 }"#, options = r#"doc!{ "unique": true }"#),
+)]
+pub struct SharingGrant {
+  #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+  pub id: Option<ObjectId>,
+  pub owner: ObjectId,
+  pub grantee: ObjectId,
+  pub geotypes: Vec<String>,
+  pub created_by: ObjectId,
+  pub created_at: Date,
+}
+
+impl SharingGrant {
+  pub fn new(
+    owner: ObjectId,
+    grantee: ObjectId,
+    geotypes: Vec<String>,
+    created_by: ObjectId,
+  ) -> Self {
+    Self {
+      id: None,
+      owner,
+      grantee,
+      geotypes,
+      created_by,
+      created_at: date::now(),
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublicSharingGrant {
+  #[serde(alias = "_id", serialize_with = "serialize_object_id_as_hex_string")]
+  pub id: ObjectId,
+  #[serde(serialize_with = "serialize_object_id_as_hex_string")]
+  pub owner: ObjectId,
+  #[serde(serialize_with = "serialize_object_id_as_hex_string")]
+  pub grantee: ObjectId,
+  pub geotypes: Vec<String>,
+  #[serde(with = "bson_datetime_as_rfc3339_string")]
+  pub created_at: Date,
+}
+
+impl From<SharingGrant> for PublicSharingGrant {
+  fn from(grant: SharingGrant) -> Self {
+    Self {
+      id: grant.id.unwrap(),
+      owner: grant.owner,
+      grantee: grant.grantee,
+      geotypes: grant.geotypes,
+      created_at: grant.created_at,
+    }
+  }
+}
@@ -2,13 +2,16 @@ use bson::serde_helpers::bson_datetime_as_rfc3339_string;
 use bson::serde_helpers::serialize_object_id_as_hex_string;
 use serde::{Deserialize, Serialize};
 use validator::Validate;
-use wither::bson::{doc, oid::ObjectId};
+use wither::bson::{self, doc, oid::ObjectId, Document};
 use wither::Model as WitherModel;
 
 use crate::database::Database;
 use crate::common::date;
 use crate::common::date::Date;
 use crate::common::models::ModelExt;
+use crate::common::pagination::{Page, Pagination};
+use crate::common::tenant::Tenant;
+use crate::errors::Error;
 
 #[derive(Clone)]
 pub struct Model {
@@ -19,6 +22,37 @@ impl Model {
   pub fn new(db: Database) -> Self {
     Self { db }
   }
+
+  /// Like `find_page`, limited to the validations of geodata `tenant` may
+  /// read.
+  pub async fn find_page_scoped(
+    &self,
+    tenant: &Tenant,
+    query: Document,
+    pagination: &Pagination,
+  ) -> Result<Page<Validation>, Error> {
+    self.find_page(tenant.scope(query), pagination).await
+  }
+
+  /// Records a revision of the validated geodata. Its hash becomes the one
+  /// later validations compare against.
+  pub async fn authorize_revision(
+    &self,
+    geodata: &ObjectId,
+    validity: Validity,
+  ) -> Result<Option<Validation>, Error> {
+    let hash = validity.hash.clone();
+    // A revision is authorized once, even when the anchor worker completes it.
+    self
+      .find_one_and_update(
+        doc! { "geodata": geodata, "authorized_hash": { "$ne": &hash } },
+        doc! {
+          "$push": { "validities": bson::to_bson(&validity)? },
+          "$set": { "authorized_hash": hash },
+        },
+      )
+      .await
+  }
 }
 
 impl ModelExt for Model {
@@ -49,29 +83,51 @@ impl Validity {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
-#[model(index(keys = r#"doc!{ "account": 1 }"#))]
+#[model(
+  index(keys = r#"doc!{ "account": 1 }"#),
+  index(keys = r#"doc!{ "geodata": 1 }"#),
+  index(keys = r#"doc!{ "organization": 1 }"#),
+)]
 pub struct Validation {
   #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
   pub id: Option<ObjectId>,
   #[serde(serialize_with = "serialize_object_id_as_hex_string")]
   pub account: ObjectId,
+  // Owning tenant of the validated geodata.
+  #[serde(default)]
+  pub organization: Option<ObjectId>,
   #[serde(serialize_with = "serialize_object_id_as_hex_string")]
   pub geodata: ObjectId,
   pub validities: Vec<Validity>,
+  // Hash of the latest authorized revision. Without revisions, the first
+  // validity holds the authorized hash.
+  #[serde(default)]
+  pub authorized_hash: Option<String>,
   pub created: Date,
 }
 
 impl Validation {
+  /// The hash the current content of the geodata must have.
+  pub fn expected_hash(&self) -> &str {
+    self
+      .authorized_hash
+      .as_deref()
+      .unwrap_or(&self.validities[0].hash)
+  }
+
   pub fn new(
     account: ObjectId,
+    organization: Option<ObjectId>,
     geodata: ObjectId,
     validities: Vec<Validity>,
   ) -> Self {
     Self {
       id: None,
       account,
+      organization,
       geodata,
       validities,
+      authorized_hash: None,
       created: date::now(),
     }
   }
@@ -83,6 +139,9 @@ pub struct ValidationResult {
   pub account: ObjectId,
   #[serde(serialize_with = "serialize_object_id_as_hex_string")]
   pub geodata: ObjectId,
+  // Version of the geodata that was validated.
+  #[serde(default)]
+  pub version: i32,
   pub validated: bool,
   #[serde(with = "bson_datetime_as_rfc3339_string")]
   pub created: Date,
@@ -91,11 +150,13 @@ impl ValidationResult {
   pub fn new(
     account: ObjectId,
     geodata: ObjectId,
+    version: i32,
     validated: bool,
   ) -> Self {
     Self {
       account,
       geodata,
+      version,
       validated,
       created: date::now(),
     }
@@ -105,6 +166,10 @@ impl ValidationResult {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ValidationResults {
   pub results: Vec<ValidationResult>,
+  // Pagination of the validations, as in `Page`.
+  pub next_cursor: Option<String>,
+  #[serde(default)]
+  pub total: u64,
   #[serde(with = "bson_datetime_as_rfc3339_string")]
   pub created: Date,
 }
@@ -115,6 +180,8 @@ impl ValidationResults {
   ) -> Self {
     Self {
       results,
+      next_cursor: None,
+      total: 0,
       created: date::now(),
     }
   }
@@ -1,19 +1,56 @@
-use axum::{extract::Extension, routing::post, Json, Router};
+use axum::{
+  extract::{Extension, Path},
+  http::StatusCode,
+  routing::{delete, post, put},
+  Json, Router,
+};
 use bson::doc;
+use bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
-use tracing::debug;
+use std::time::Duration;
+use tracing::{debug, error, warn};
 
 use crate::context::Context;
 use crate::errors::BadRequest;
 use crate::errors::NotFound;
 use crate::errors::{AuthenticateError, Error};
+use crate::common::date;
+use crate::common::hasher;
+use crate::common::secret;
 use crate::common::token;
-use crate::models::account::PublicAccount;
+use crate::common::token::Claims;
+use crate::common::totp;
+use crate::common::authenticate_request::AdminAccount;
+use crate::models::account::{self, Account, PublicAccount};
+use crate::models::audit_event::{self, AuditEvent};
+use crate::models::password_reset::PasswordReset;
+use crate::models::revoked_token::RevokedToken;
+use crate::models::session::Session;
 use crate::common::models::ModelExt;
+use crate::routes::ADMIN_SCOPE;
+
+// Recovery codes handed out with each enrollment.
+const RECOVERY_CODES: usize = 10;
 
 pub fn create_route() -> Router {
+  let admin = Router::new()
+    .route("/accounts/:id/lock", post(lock_account))
+    .route("/accounts/:id/unlock", post(unlock_account))
+    .route("/accounts/:id/roles", put(set_account_roles))
+    .route("/accounts/:id/mfa", delete(reset_account_mfa));
+
   Router::new()
     .route("/accounts/authenticate", post(authenticate_account))
+    .route("/accounts/authenticate/mfa", post(authenticate_mfa))
+    .route("/accounts/refresh", post(refresh_session))
+    .route("/accounts/logout", post(logout))
+    .route("/accounts/password", post(change_password))
+    .route("/accounts/password/reset", post(request_password_reset))
+    .route("/accounts/password/reset/confirm", post(confirm_password_reset))
+    .route("/accounts/mfa/enroll", post(enroll_mfa))
+    .route("/accounts/mfa/confirm", post(confirm_mfa))
+    .route("/accounts/mfa/disable", post(disable_mfa))
+    .nest(ADMIN_SCOPE, admin)
 }
 
 async fn authenticate_account(
@@ -44,45 +81,801 @@ async fn authenticate_account(
     .account
     .find_one(doc! { "email": email }, None)
     .await?;
-    
-  let account = match account {
-    Some(account) => account,
+
+  // Unknown emails, locked accounts, temporary lockouts and wrong passwords
+  // all get the same response after the same work: a password hash check
+  // and a delay growing with the failed logins of the submitted email, so
+  // neither can be used to find out which accounts exist.
+  let auth = &context.settings.auth;
+  let password_matches = match &account {
+    Some(account) => account.is_password_match(password.clone()).await?,
     None => {
-      debug!("Account not found, returning 401");
-      return Err(Error::NotFound(NotFound::new(String::from("account"))));
+      account::verify_dummy_password(password.clone(), &auth.argon2).await?;
+      false
     }
   };
+  let usable = |account: &Account| account.locked_at.is_none() && !account.is_locked_out();
+  let account = match account {
+    Some(account) if password_matches && usable(&account) => account,
+    account => {
+      debug!("Wrong credentials, unknown or locked account, returning 401");
+      if let Some(account) = account.filter(|account| !password_matches && usable(account)) {
+        record_failed_login(&context, &account).await?;
+      }
+      let failed_logins = context
+        .models
+        .login_attempt
+        .record_failure(email, auth.lockout.duration)
+        .await?;
+      delay_failed_login(&context, failed_logins).await;
+      return Err(Error::Authenticate(AuthenticateError::WrongCredentials));
+    }
+  };
+
+  context.models.login_attempt.clear(email).await?;
+  if account.failed_logins > 0 || account.lockout_until.is_some() {
+    context
+      .models
+      .account
+      .reset_failed_logins(account.id.as_ref().unwrap())
+      .await?;
+  }
+
+  if account.needs_rehash(&context.settings.auth.argon2) {
+    rehash_password(&context, &account, password.clone()).await;
+  }
+
+  // The password checks out, but accounts with a second factor (or roles
+  // requiring one) only get a token to complete the login with it.
+  let mfa = &context.settings.auth.mfa;
+  if account.is_mfa_enabled() || account.is_mfa_required(&mfa.required_roles) {
+    debug!("Second factor needed, returning MFA token");
+    let mfa_token = token::create_mfa(&account, &context.keys, mfa.token_ttl)
+      .map_err(|_| Error::Authenticate(AuthenticateError::TokenCreation))?;
+    return Ok(Json(AuthenticateResponse::MfaRequired(MfaChallenge {
+      mfa_token,
+      expires_in: mfa.token_ttl,
+      enrollment_required: !account.is_mfa_enabled(),
+    })));
+  }
+
+  let res = issue_tokens(&context, account, ObjectId::new()).await?;
+  Ok(Json(AuthenticateResponse::Tokens(res)))
+}
+
+/// Completes a login with a TOTP code or a recovery code. A correct code
+/// for a pending enrollment also enables two-factor authentication.
+async fn authenticate_mfa(
+  Extension(context): Extension<Context>,
+  Json(body): Json<MfaBody>,
+) -> Result<Json<TokenResponse>, Error> {
+  let account = account_from_mfa_token(&context, &body.mfa_token).await?;
+
+  if account.is_locked_out() {
+    debug!("Account is temporarily locked out, returning 401");
+    delay_failed_login(&context, context.settings.auth.lockout.max_attempts).await;
+    return Err(Error::Authenticate(AuthenticateError::WrongCredentials));
+  }
+
+  let verified = match (&body.code, &body.recovery_code) {
+    (Some(code), _) => verify_totp(&context, &account, code).await?,
+    (None, Some(recovery_code)) if account.is_mfa_enabled() => {
+      let id = account.id.unwrap();
+      let used = context
+        .models
+        .account
+        .use_recovery_code(&id, &hasher::hash(recovery_code.trim()))
+        .await?;
+      if used.is_some() {
+        warn!("Recovery code used by account {}", id);
+      }
+      used.is_some()
+    }
+    (None, Some(_)) => false,
+    (None, None) => {
+      return Err(Error::BadRequest(BadRequest::new(
+        "code".to_owned(),
+        "Missing code or recovery_code attribute".to_owned(),
+      )));
+    }
+  };
+
+  if !verified {
+    debug!("Second factor is incorrect, returning 401 status code");
+    let failed_logins = record_failed_login(&context, &account).await?;
+    delay_failed_login(&context, failed_logins).await;
+    return Err(Error::Authenticate(AuthenticateError::WrongCredentials));
+  }
+
+  let id = account.id.unwrap();
+  if !account.is_mfa_enabled() {
+    context.models.account.enable_mfa(&id).await?;
+    debug!("Two-factor authentication enabled for account {}", id);
+  }
+
+  if account.failed_logins > 0 || account.lockout_until.is_some() {
+    context.models.account.reset_failed_logins(&id).await?;
+  }
+
+  let res = issue_tokens(&context, account, ObjectId::new()).await?;
+  Ok(Json(res))
+}
+
+/// Starts TOTP enrollment and returns the secret and the recovery codes,
+/// which are only shown here. Authenticate with an access token, or with the
+/// MFA token of a login that requires enrollment.
+async fn enroll_mfa(
+  claims: Option<Claims>,
+  Extension(context): Extension<Context>,
+  body: Option<Json<MfaEnrollBody>>,
+) -> Result<Json<MfaEnrollment>, Error> {
+  let mfa_token = body.and_then(|Json(body)| body.mfa_token);
+  let account = match (claims, mfa_token) {
+    (Some(claims), _) => context
+      .models
+      .account
+      .find_by_id(&claims.account.id)
+      .await?
+      .ok_or(Error::Authenticate(AuthenticateError::InvalidToken))?,
+    (None, Some(mfa_token)) => account_from_mfa_token(&context, &mfa_token).await?,
+    (None, None) => return Err(Error::Authenticate(AuthenticateError::InvalidToken)),
+  };
+
+  if account.is_mfa_enabled() {
+    return Err(Error::BadRequest(BadRequest::new(
+      "mfa".to_owned(),
+      "Two-factor authentication is already enabled".to_owned(),
+    )));
+  }
+
+  let secret = totp::generate_secret();
+  let recovery_codes = (0..RECOVERY_CODES)
+    .map(|_| secret::generate(5))
+    .collect::<Vec<String>>();
+  let recovery_code_hashes = recovery_codes.iter().map(|code| hasher::hash(code)).collect();
+
+  let id = account.id.unwrap();
+  context
+    .models
+    .account
+    .start_mfa_enrollment(&id, secret.clone(), recovery_code_hashes)
+    .await?
+    .ok_or_else(|| NotFound::new(String::from("account")))?;
+
+  debug!("Two-factor enrollment started for account {}", id);
+  Ok(Json(MfaEnrollment {
+    otpauth_uri: totp::otpauth_uri(&context.settings.auth.mfa.issuer, &account.email, &secret),
+    secret,
+    recovery_codes,
+  }))
+}
 
-  if !account.is_password_match(password) {
-    debug!("Account password is incorrect, returning 401 status code");
+/// Enables two-factor authentication for a signed in account with a first
+/// code from the enrolled secret.
+async fn confirm_mfa(
+  claims: Claims,
+  Extension(context): Extension<Context>,
+  Json(body): Json<MfaCodeBody>,
+) -> Result<StatusCode, Error> {
+  let account = context
+    .models
+    .account
+    .find_by_id(&claims.account.id)
+    .await?
+    .ok_or(Error::Authenticate(AuthenticateError::InvalidToken))?;
+
+  if account.is_mfa_enabled() || account.totp_secret.is_none() {
+    return Err(Error::BadRequest(BadRequest::new(
+      "mfa".to_owned(),
+      "No pending two-factor enrollment".to_owned(),
+    )));
+  }
+
+  if !verify_totp(&context, &account, &body.code).await? {
+    debug!("TOTP code is incorrect, returning 401 status code");
     return Err(Error::Authenticate(AuthenticateError::WrongCredentials));
   }
 
+  context.models.account.enable_mfa(&claims.account.id).await?;
+  debug!("Two-factor authentication enabled for account {}", claims.account.id);
+  Ok(StatusCode::NO_CONTENT)
+}
+
+/// Turns two-factor authentication off, unless a role of the account
+/// requires it.
+async fn disable_mfa(
+  claims: Claims,
+  Extension(context): Extension<Context>,
+  Json(body): Json<MfaCodeBody>,
+) -> Result<StatusCode, Error> {
+  let account = context
+    .models
+    .account
+    .find_by_id(&claims.account.id)
+    .await?
+    .ok_or(Error::Authenticate(AuthenticateError::InvalidToken))?;
+
+  if account.is_mfa_required(&context.settings.auth.mfa.required_roles) {
+    debug!("Two-factor authentication is required for the account's roles");
+    return Err(Error::Authenticate(AuthenticateError::Forbidden));
+  }
+
+  if !account.is_mfa_enabled() || !verify_totp(&context, &account, &body.code).await? {
+    debug!("TOTP code is incorrect, returning 401 status code");
+    return Err(Error::Authenticate(AuthenticateError::WrongCredentials));
+  }
+
+  let id = account.id.unwrap();
+  context.models.account.disable_mfa(&id).await?;
+
+  let event = AuditEvent::new(Some(id), audit_event::MFA_DISABLE, doc! {});
+  context.models.audit_event.create(event).await?;
+
+  debug!("Two-factor authentication disabled for account {}", id);
+  Ok(StatusCode::NO_CONTENT)
+}
+
+/// Looks up the account an MFA token was issued to, provided the token is
+/// still valid for it.
+async fn account_from_mfa_token(context: &Context, mfa_token: &str) -> Result<Account, Error> {
+  let claims = token::decode_mfa(mfa_token, &context.keys)
+    .map_err(|_| Error::Authenticate(AuthenticateError::InvalidToken))?;
+
+  let account = context
+    .models
+    .account
+    .find_by_id(&claims.sub)
+    .await?
+    .ok_or(Error::Authenticate(AuthenticateError::InvalidToken))?;
+
+  if account.token_version != claims.ver {
+    debug!("MFA token predates a token revocation, returning 401");
+    return Err(Error::Authenticate(AuthenticateError::InvalidToken));
+  }
+
   if account.locked_at.is_some() {
-    debug!("Account is locked, returning 401");
+    debug!("Account is locked, returning 423");
     return Err(Error::Authenticate(AuthenticateError::Locked));
   }
 
-  let secret = context.settings.auth.secret.as_str();
-  let token = token::create(account.clone(), secret)
-    .map_err(|_| Error::Authenticate(AuthenticateError::TokenCreation))?;
+  Ok(account)
+}
 
-  let res = AuthenticateResponse {
-    access_token: token,
-    account: PublicAccount::from(account),
+/// Checks a TOTP code against the account's secret and consumes its time
+/// step.
+async fn verify_totp(context: &Context, account: &Account, code: &str) -> Result<bool, Error> {
+  let secret = match &account.totp_secret {
+    Some(secret) => secret,
+    None => return Ok(false),
+  };
+
+  let now = chrono::Utc::now().timestamp();
+  let step = match totp::verify(secret, code, now, context.settings.auth.mfa.skew) {
+    Some(step) => step,
+    None => return Ok(false),
   };
 
+  let used = context
+    .models
+    .account
+    .use_totp_step(account.id.as_ref().unwrap(), step)
+    .await?;
+
+  Ok(used.is_some())
+}
+
+/// Upgrades the hash of a verified password to Argon2id with the configured
+/// parameters. Failing to do so doesn't fail the login.
+async fn rehash_password(context: &Context, account: &Account, password: String) {
+  let id = account.id.unwrap();
+  let result = match account::hash_password(password, &context.settings.auth.argon2).await {
+    Ok(password_hash) => context.models.account.rehash_password(&id, password_hash).await,
+    Err(err) => Err(err),
+  };
+
+  match result {
+    Ok(_) => debug!("Password hash of account {} upgraded", id),
+    Err(err) => warn!("Failed to upgrade password hash of account {}: {}", id, err),
+  }
+}
+
+/// Counts a failed login and locks the account out once it reaches the
+/// configured number of attempts. Returns the number of failed attempts.
+async fn record_failed_login(context: &Context, account: &Account) -> Result<i32, Error> {
+  let lockout = &context.settings.auth.lockout;
+  let id = account.id.unwrap();
+  let failed_logins = context
+    .models
+    .account
+    .record_failed_login(&id)
+    .await?
+    .map_or(account.failed_logins + 1, |account| account.failed_logins);
+
+  if failed_logins >= lockout.max_attempts {
+    let until = date::after(lockout.duration);
+    context.models.account.lock_out(&id, until).await?;
+
+    warn!("Account {} locked out after {} failed logins", id, failed_logins);
+    let event = AuditEvent::new(
+      Some(id),
+      audit_event::ACCOUNT_LOCKOUT,
+      doc! { "failed_logins": failed_logins, "until": until },
+    );
+    context.models.audit_event.create(event).await?;
+  }
+
+  Ok(failed_logins)
+}
+
+/// Waits longer after each failed login in a row, to slow down guessing.
+async fn delay_failed_login(context: &Context, failed_logins: i32) {
+  let lockout = &context.settings.auth.lockout;
+  let exponent = (failed_logins.max(1) - 1).min(16) as u32;
+  let delay = lockout
+    .base_delay_ms
+    .saturating_mul(2u64.pow(exponent))
+    .min(lockout.max_delay_ms);
+
+  tokio::time::sleep(Duration::from_millis(delay)).await;
+}
+
+/// Exchanges a refresh token for a new access token and a new refresh token.
+/// The presented token is rotated out; presenting it again revokes every
+/// session of its family.
+async fn refresh_session(
+  Extension(context): Extension<Context>,
+  Json(body): Json<RefreshBody>,
+) -> Result<Json<TokenResponse>, Error> {
+  let token_hash = hasher::hash(&body.refresh_token);
+  let session = context
+    .models
+    .session
+    .find_one(doc! { "token_hash": &token_hash }, None)
+    .await?
+    .ok_or(Error::Authenticate(AuthenticateError::InvalidToken))?;
+
+  if session.rotated_at.is_some() && session.revoked_at.is_none() {
+    warn!(
+      "Refresh token reuse detected for account {}, revoking session family {}",
+      session.account, session.family
+    );
+    context.models.session.revoke_family(&session.family).await?;
+    return Err(Error::Authenticate(AuthenticateError::InvalidToken));
+  }
+
+  if !session.is_active() {
+    debug!("Refresh token is expired or revoked, returning 401");
+    return Err(Error::Authenticate(AuthenticateError::InvalidToken));
+  }
+
+  // Rotate atomically so two concurrent refreshes can't both succeed.
+  let rotated = context
+    .models
+    .session
+    .find_one_and_update(
+      doc! { "_id": session.id, "rotated_at": null, "revoked_at": null },
+      doc! { "$set": { "rotated_at": date::now() } },
+    )
+    .await?;
+
+  if rotated.is_none() {
+    warn!("Concurrent refresh of session family {}, revoking", session.family);
+    context.models.session.revoke_family(&session.family).await?;
+    return Err(Error::Authenticate(AuthenticateError::InvalidToken));
+  }
+
+  let account = context
+    .models
+    .account
+    .find_by_id(&session.account)
+    .await?
+    .ok_or(Error::Authenticate(AuthenticateError::InvalidToken))?;
+
+  if account.locked_at.is_some() {
+    debug!("Account is locked, revoking session family");
+    context.models.session.revoke_family(&session.family).await?;
+    return Err(Error::Authenticate(AuthenticateError::Locked));
+  }
+
+  let res = issue_tokens(&context, account, session.family).await?;
   Ok(Json(res))
 }
 
+/// Revokes the session family of the presented refresh token, and the access
+/// token when one is sent along. Unknown tokens are ignored so logging out
+/// twice is harmless.
+async fn logout(
+  claims: Option<Claims>,
+  Extension(context): Extension<Context>,
+  Json(body): Json<RefreshBody>,
+) -> Result<StatusCode, Error> {
+  if let Some(claims) = claims {
+    let expires_at = bson::DateTime::from_millis(claims.exp as i64 * 1000);
+    let revoked = RevokedToken::new(claims.jti.clone(), claims.account.id, expires_at);
+    context.models.revoked_token.create(revoked).await?;
+    context.cache.insert_revoked(claims.jti, true);
+  }
+
+  let token_hash = hasher::hash(&body.refresh_token);
+  let session = context
+    .models
+    .session
+    .find_one(doc! { "token_hash": &token_hash }, None)
+    .await?;
+
+  if let Some(session) = session {
+    context.models.session.revoke_family(&session.family).await?;
+  }
+
+  Ok(StatusCode::NO_CONTENT)
+}
+
+/// Changes the password of the authenticated account, which must present its
+/// current password again. Every session and token of the account stops
+/// working, including the one used for this call.
+async fn change_password(
+  claims: Claims,
+  Extension(context): Extension<Context>,
+  Json(body): Json<ChangePasswordBody>,
+) -> Result<StatusCode, Error> {
+  validate_new_password(&body.new_password)?;
+
+  let account = context
+    .models
+    .account
+    .find_by_id(&claims.account.id)
+    .await?
+    .ok_or(Error::Authenticate(AuthenticateError::InvalidToken))?;
+
+  if account.is_locked_out() {
+    debug!("Account is temporarily locked out, returning 401");
+    delay_failed_login(&context, context.settings.auth.lockout.max_attempts).await;
+    return Err(Error::Authenticate(AuthenticateError::WrongCredentials));
+  }
+
+  if !account.is_password_match(body.current_password.clone()).await? {
+    debug!("Current password is incorrect, returning 401 status code");
+    let failed_logins = record_failed_login(&context, &account).await?;
+    delay_failed_login(&context, failed_logins).await;
+    return Err(Error::Authenticate(AuthenticateError::WrongCredentials));
+  }
+
+  let id = account.id.unwrap();
+  set_password(&context, &id, body.new_password, audit_event::PASSWORD_CHANGE).await?;
+
+  debug!("Password of account {} changed", id);
+  Ok(StatusCode::NO_CONTENT)
+}
+
+/// Sends a password reset token to the account with the given email. The
+/// reset is issued off the response path, so the response is the same, and
+/// as fast, whether or not the account exists or the notifier fails.
+async fn request_password_reset(
+  Extension(context): Extension<Context>,
+  Json(body): Json<PasswordResetBody>,
+) -> StatusCode {
+  tokio::spawn(async move {
+    if let Err(err) = issue_password_reset(&context, &body.email).await {
+      error!("Failed to issue a password reset: {}", err);
+    }
+  });
+
+  StatusCode::ACCEPTED
+}
+
+async fn issue_password_reset(context: &Context, email: &str) -> Result<(), Error> {
+  let account = context
+    .models
+    .account
+    .find_one(doc! { "email": email }, None)
+    .await?;
+
+  let account = match account {
+    Some(account) if account.locked_at.is_none() => account,
+    _ => {
+      debug!("No unlocked account for password reset, ignoring");
+      return Ok(());
+    }
+  };
+
+  let id = account.id.unwrap();
+  context.models.password_reset.delete_pending(&id).await?;
+
+  let token = secret::generate(32);
+  let reset = PasswordReset::new(
+    id,
+    hasher::hash(&token),
+    date::after(context.settings.auth.password_reset_ttl),
+  );
+  context.models.password_reset.create(reset).await?;
+  context.notifier.send_password_reset(&account, &token).await?;
+
+  debug!("Password reset issued for account {}", id);
+  Ok(())
+}
+
+/// Sets a new password with a reset token. Each token works once, and isn't
+/// used up while the account is locked, so it can be retried once unlocked.
+async fn confirm_password_reset(
+  Extension(context): Extension<Context>,
+  Json(body): Json<ConfirmPasswordResetBody>,
+) -> Result<StatusCode, Error> {
+  validate_new_password(&body.new_password)?;
+
+  let pending = context
+    .models
+    .password_reset
+    .find_pending(&body.token)
+    .await?
+    .ok_or(Error::Authenticate(AuthenticateError::InvalidToken))?;
+
+  let account = context
+    .models
+    .account
+    .find_by_id(&pending.account)
+    .await?
+    .ok_or(Error::Authenticate(AuthenticateError::InvalidToken))?;
+
+  if account.locked_at.is_some() {
+    debug!("Account is locked, returning 423");
+    return Err(Error::Authenticate(AuthenticateError::Locked));
+  }
+
+  // Redeeming is what makes the token single-use, should it race another.
+  let reset = context
+    .models
+    .password_reset
+    .redeem(&body.token)
+    .await?
+    .ok_or(Error::Authenticate(AuthenticateError::InvalidToken))?;
+
+  set_password(&context, &reset.account, body.new_password, audit_event::PASSWORD_RESET).await?;
+
+  debug!("Password of account {} reset", reset.account);
+  Ok(StatusCode::NO_CONTENT)
+}
+
+fn validate_new_password(password: &str) -> Result<(), Error> {
+  if password.chars().count() < account::MIN_PASSWORD_LENGTH {
+    debug!("New password too short, returning 400 status code");
+    return Err(Error::BadRequest(BadRequest::new(
+      "new_password".to_owned(),
+      format!("Password must have at least {} characters", account::MIN_PASSWORD_LENGTH),
+    )));
+  }
+
+  Ok(())
+}
+
+/// Stores a new password and ends every session and token of the account.
+async fn set_password(
+  context: &Context,
+  id: &ObjectId,
+  password: String,
+  kind: &str,
+) -> Result<(), Error> {
+  let password_hash = account::hash_password(password, &context.settings.auth.argon2).await?;
+  context
+    .models
+    .account
+    .set_password(id, password_hash)
+    .await?
+    .ok_or_else(|| NotFound::new(String::from("account")))?;
+
+  context.models.session.revoke_account(id).await?;
+  context.cache.invalidate_account(id);
+
+  let event = AuditEvent::new(Some(*id), kind, doc! {});
+  context.models.audit_event.create(event).await?;
+
+  Ok(())
+}
+
+async fn lock_account(
+  AdminAccount(admin): AdminAccount,
+  Extension(context): Extension<Context>,
+  Path(id): Path<String>,
+) -> Result<Json<PublicAccount>, Error> {
+  let id = ObjectId::parse_str(&id)?;
+  let account = context
+    .models
+    .account
+    .lock(&id)
+    .await?
+    .ok_or_else(|| NotFound::new(String::from("account")))?;
+
+  context.models.session.revoke_account(&id).await?;
+  context.cache.invalidate_account(&id);
+
+  debug!("Account {} locked by {}", id, admin.id);
+  Ok(Json(PublicAccount::from(account)))
+}
+
+async fn unlock_account(
+  AdminAccount(admin): AdminAccount,
+  Extension(context): Extension<Context>,
+  Path(id): Path<String>,
+) -> Result<Json<PublicAccount>, Error> {
+  let id = ObjectId::parse_str(&id)?;
+  let account = context
+    .models
+    .account
+    .unlock(&id)
+    .await?
+    .ok_or_else(|| NotFound::new(String::from("account")))?;
+
+  context.cache.invalidate_account(&id);
+
+  debug!("Account {} unlocked by {}", id, admin.id);
+  Ok(Json(PublicAccount::from(account)))
+}
+
+/// Replaces the roles of an account. Tokens embedding the previous roles
+/// stop working.
+async fn set_account_roles(
+  AdminAccount(admin): AdminAccount,
+  Extension(context): Extension<Context>,
+  Path(id): Path<String>,
+  Json(body): Json<RolesBody>,
+) -> Result<Json<PublicAccount>, Error> {
+  let id = ObjectId::parse_str(&id)?;
+  let roles = context
+    .models
+    .role
+    .find(doc! { "name": { "$in": body.roles.clone() } }, None)
+    .await?;
+
+  if roles.len() != body.roles.len() {
+    debug!("Unknown role in {:?}, returning 400 status code", body.roles);
+    return Err(Error::BadRequest(BadRequest::new(
+      "roles".to_owned(),
+      "Unknown or duplicated role".to_owned(),
+    )));
+  }
+
+  let account = context
+    .models
+    .account
+    .set_roles(&id, roles)
+    .await?
+    .ok_or_else(|| NotFound::new(String::from("account")))?;
+
+  context.cache.invalidate_account(&id);
+
+  debug!("Account {} roles set to {:?} by {}", id, body.roles, admin.id);
+  Ok(Json(PublicAccount::from(account)))
+}
+
+/// Turns off two-factor authentication of an account that lost its
+/// authenticator and recovery codes. It must enroll again on the next login
+/// if its roles require it.
+async fn reset_account_mfa(
+  AdminAccount(admin): AdminAccount,
+  Extension(context): Extension<Context>,
+  Path(id): Path<String>,
+) -> Result<Json<PublicAccount>, Error> {
+  let id = ObjectId::parse_str(&id)?;
+  let account = context
+    .models
+    .account
+    .disable_mfa(&id)
+    .await?
+    .ok_or_else(|| NotFound::new(String::from("account")))?;
+
+  let event = AuditEvent::new(Some(id), audit_event::MFA_DISABLE, doc! { "by": admin.id });
+  context.models.audit_event.create(event).await?;
+
+  debug!("Two-factor authentication of account {} reset by {}", id, admin.id);
+  Ok(Json(PublicAccount::from(account)))
+}
+
+/// Creates an access token and a refresh token session within `family`.
+async fn issue_tokens(
+  context: &Context,
+  account: Account,
+  family: ObjectId,
+) -> Result<TokenResponse, Error> {
+  let auth = &context.settings.auth;
+  let access_token = token::create(account.clone(), &context.keys, auth.access_token_ttl)
+    .map_err(|_| Error::Authenticate(AuthenticateError::TokenCreation))?;
+
+  let refresh_token = secret::generate(32);
+  let session = Session::new(
+    account.id.unwrap(),
+    family,
+    hasher::hash(&refresh_token),
+    date::after(auth.refresh_token_ttl),
+  );
+  context.models.session.create(session).await?;
+
+  Ok(TokenResponse {
+    access_token,
+    refresh_token,
+    expires_in: auth.access_token_ttl,
+    account: PublicAccount::from(account),
+  })
+}
+
 #[derive(Debug, Deserialize)]
 struct AuthorizeBody {
   email: String,
   password: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct RefreshBody {
+  refresh_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChangePasswordBody {
+  current_password: String,
+  new_password: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PasswordResetBody {
+  email: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ConfirmPasswordResetBody {
+  token: String,
+  new_password: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RolesBody {
+  roles: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MfaBody {
+  mfa_token: String,
+  code: Option<String>,
+  recovery_code: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MfaEnrollBody {
+  mfa_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MfaCodeBody {
+  code: String,
+}
+
+#[derive(Debug, Serialize)]
+struct MfaEnrollment {
+  secret: String,
+  otpauth_uri: String,
+  recovery_codes: Vec<String>,
+}
+
+/// Either the tokens, or a challenge for the second factor.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum AuthenticateResponse {
+  Tokens(TokenResponse),
+  MfaRequired(MfaChallenge),
+}
+
+#[derive(Debug, Serialize)]
+struct MfaChallenge {
+  mfa_token: String,
+  // Seconds left to complete the login.
+  expires_in: i64,
+  // The account has to enroll (`/accounts/mfa/enroll`) before completing
+  // the login.
+  enrollment_required: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
-struct AuthenticateResponse {
+struct TokenResponse {
   access_token: String,
+  refresh_token: String,
+  // Seconds until the access token expires.
+  expires_in: i64,
   account: PublicAccount,
 }
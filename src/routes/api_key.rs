@@ -0,0 +1,162 @@
+use axum::{
+  extract::{Extension, Path, Query},
+  routing::{delete, post},
+  Json, Router,
+};
+use bson::doc;
+use bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::common::authenticate_request::AdminAccount;
+use crate::common::date::{self, Date};
+use crate::common::models::ModelExt;
+use crate::common::pagination::{Page, PageQuery, Pagination};
+use crate::context::Context;
+use crate::errors::{BadRequest, Error, NotFound};
+use crate::models::api_key::{self, ApiKey, PublicApiKey};
+use crate::routes::ADMIN_SCOPE;
+
+pub fn create_route() -> Router {
+  let admin = Router::new()
+    .route("/api-keys", post(create_api_key).get(query_api_keys))
+    .route("/api-keys/:id", delete(revoke_api_key));
+
+  Router::new().nest(ADMIN_SCOPE, admin)
+}
+
+/// Issues an API key. The plaintext key is only returned by this call.
+async fn create_api_key(
+  AdminAccount(admin): AdminAccount,
+  Extension(context): Extension<Context>,
+  Json(body): Json<CreateApiKey>,
+) -> Result<Json<CreatedApiKey>, Error> {
+  let account_id = ObjectId::parse_str(&body.account)?;
+  let account = context
+    .models
+    .account
+    .find_by_id(&account_id)
+    .await?
+    .ok_or_else(|| NotFound::new(String::from("account")))?;
+
+  if let Some(scope) = body
+    .scopes
+    .iter()
+    .find(|scope| !account.roles.iter().any(|role| &role.name == *scope))
+  {
+    debug!("Account lacks role {}, returning 400 status code", scope);
+    return Err(Error::BadRequest(BadRequest::new(
+      "scopes".to_owned(),
+      format!("Account does not hold role {}", scope),
+    )));
+  }
+
+  if let Some(ip) = body
+    .allowed_ips
+    .iter()
+    .find(|ip| !api_key::is_valid_ip_range(ip))
+  {
+    return Err(Error::BadRequest(BadRequest::new(
+      "allowed_ips".to_owned(),
+      format!("{} is not an IP address or CIDR range", ip),
+    )));
+  }
+
+  let expires_at = match &body.expires_at {
+    Some(expires_at) => {
+      let expires_at = Date::parse_rfc3339_str(expires_at).map_err(|_| {
+        BadRequest::new(
+          "expires_at".to_owned(),
+          "Expected an RFC 3339 date".to_owned(),
+        )
+      })?;
+      if expires_at <= date::now() {
+        return Err(Error::BadRequest(BadRequest::new(
+          "expires_at".to_owned(),
+          "Expiry must be in the future".to_owned(),
+        )));
+      }
+      Some(expires_at)
+    }
+    None => None,
+  };
+
+  let (api_key, key) = ApiKey::generate(
+    account_id,
+    body.name,
+    body.scopes,
+    body.allowed_ips,
+    expires_at,
+    admin.id,
+  );
+  let api_key = context.models.api_key.create(api_key).await?;
+
+  debug!("API key {} issued for {} by {}", api_key.prefix, account_id, admin.id);
+  Ok(Json(CreatedApiKey {
+    key,
+    api_key: PublicApiKey::from(api_key),
+  }))
+}
+
+async fn query_api_keys(
+  _admin: AdminAccount,
+  Extension(context): Extension<Context>,
+  Query(params): Query<ApiKeyQueryParams>,
+  Query(page): Query<PageQuery>,
+) -> Result<Json<Page<PublicApiKey>>, Error> {
+  let pagination = Pagination::new(&page, &context.settings.pagination, "created_at")?;
+  let query = match &params.account {
+    Some(account) => doc! { "account": ObjectId::parse_str(account)? },
+    None => doc! {},
+  };
+
+  let api_keys = context
+    .models
+    .api_key
+    .find_page(query, &pagination)
+    .await?
+    .map(PublicApiKey::from);
+
+  Ok(Json(api_keys))
+}
+
+async fn revoke_api_key(
+  AdminAccount(admin): AdminAccount,
+  Extension(context): Extension<Context>,
+  Path(id): Path<String>,
+) -> Result<Json<PublicApiKey>, Error> {
+  let id = ObjectId::parse_str(&id)?;
+  let api_key = context
+    .models
+    .api_key
+    .find_one_and_update(
+      doc! { "_id": id },
+      doc! { "$set": { "revoked_at": date::now() } },
+    )
+    .await?
+    .ok_or_else(|| NotFound::new(String::from("api key")))?;
+
+  debug!("API key {} revoked by {}", api_key.prefix, admin.id);
+  Ok(Json(PublicApiKey::from(api_key)))
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateApiKey {
+  account: String,
+  name: String,
+  scopes: Vec<String>,
+  #[serde(default)]
+  allowed_ips: Vec<String>,
+  expires_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiKeyQueryParams {
+  account: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct CreatedApiKey {
+  key: String,
+  api_key: PublicApiKey,
+}
@@ -1,24 +1,39 @@
 use crate::common::anchor;
+use crate::common::anchor_queue;
+use crate::common::authenticate_request::{AdminAccount, UserAccount};
+use crate::common::date::{self, Interval};
+use crate::common::export;
+use crate::common::geojson::{
+  self, AcceptGeoJson, BoundingBox, Feature, FeatureCollection, Geometry, Link,
+};
 use crate::common::models::ModelExt;
+use crate::common::pagination::{self, Page, PageQuery, Pagination};
+use crate::common::tenant::Tenant;
 use crate::common::token::TokenAccount;
 use crate::context::Context;
-use crate::errors::Error;
+use crate::errors::{AuthenticateError, BadRequest, Conflict, Error, NotFound};
 use crate::models::geodata;
-use crate::models::geodata::{Geodata, HashableGeodata, Location, PublicGeodata};
+use crate::models::geodata::{Geodata, GeodataFilter, HashableGeodata, Location, PublicGeodata};
+use crate::models::geodata_version::{GeodataVersion, PublicGeodataVersion};
 use crate::models::validation::{Validation, Validity};
 use axum::{
-  extract::{Extension, Query},
-  routing::{get, post},
+  body::{Bytes, StreamBody},
+  extract::{Extension, OriginalUri, Path, Query},
+  http::{header, HeaderValue, StatusCode, Uri},
+  response::{IntoResponse, Response},
+  routing::{get, patch, post},
   Json, Router,
 };
 use axum_macros::debug_handler;
-use bson::doc;
+use bson::{doc, Document};
+use bson::oid::ObjectId;
+use futures::stream::{self, StreamExt};
 use serde::{Deserialize, Serialize};
 use tracing::debug;
-
-use crate::common::token::{ADMIN_PATH, USER_PATH};
 use wither::mongodb::options::FindOptions;
 
+use crate::routes::{self, ADMIN_SCOPE, LEGACY_ADMIN_PATH, LEGACY_USER_PATH, USER_SCOPE};
+
 #[derive(Serialize, Deserialize, Debug)]
 struct NearQueryParams {
   lon: f32,
@@ -27,31 +42,62 @@ struct NearQueryParams {
   max: i32,
 }
 
+#[derive(Deserialize, Debug)]
+struct ExportQueryParams {
+  format: Option<export::Format>,
+  bbox: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct WithinQueryParams {
+  bbox: Option<String>,
+  lon: Option<f64>,
+  lat: Option<f64>,
+  radius: Option<f64>,
+}
+
 pub fn create_route() -> Router {
-  let create_geodata_path = format!("{}{}", ADMIN_PATH, "/geodata");
-  let get_geodata_near_path = format!("{}{}", USER_PATH, "/geodata/near");
-  let query_geodata_path = format!("{}{}", USER_PATH, "/geodata");
+  let admin = Router::new()
+    .route("/geodata", post(create_geodata))
+    .route("/geodata/:id", patch(update_geodata).delete(delete_geodata));
+  let user = Router::new()
+    .route("/geodata", get(query_geodata))
+    .route("/geodata/near", get(get_geodata_near))
+    .route("/geodata/within", get(get_geodata_within).post(post_geodata_within))
+    .route("/geodata/intersects", post(post_geodata_intersects))
+    .route("/geodata/export", get(export_geodata))
+    .route("/geodata/:id", get(get_geodata))
+    .route("/geodata/:id/versions", get(query_geodata_versions));
+
   Router::new()
-    .route(&create_geodata_path, post(create_geodata))
-    .route(&query_geodata_path, get(query_geodata))
-    .route(&get_geodata_near_path, get(get_geodata_near))
+    .merge(routes::scope(ADMIN_SCOPE, LEGACY_ADMIN_PATH, admin))
+    .merge(routes::scope(USER_SCOPE, LEGACY_USER_PATH, user))
 }
 
 #[debug_handler]
 async fn create_geodata(
-  account: TokenAccount,
+  AdminAccount(account): AdminAccount,
   Extension(context): Extension<Context>,
   Json(body): Json<CreateGeodata>,
 ) -> Result<Json<PublicGeodata>, Error> {
+  body.location.validate_field("location")?;
+  context
+    .models
+    .geotype
+    .validate_geodata(&body.geotype, &body.location, body.value, body.quality)
+    .await?;
+
   // create geodata doc
   let geodata = Geodata::new(
     account.id,
+    account.organization,
     body.location,
     body.geotype,
     body.value,
     body.source,
     body.quality,
-  );
+  )
+  .with_observed_at(body.observed_at);
 
   let geodata = context.models.geodata.create(geodata).await?;
   let geodata_id = &geodata.id.unwrap().to_hex();
@@ -76,59 +122,430 @@ async fn create_geodata(
 
   // create top level Validation doc for this geodata, and supply initial validity
   let validity = Validity::new(account.id, hash);
-  let validation = Validation::new(account.id, geodata.id.unwrap(), vec![validity]);
+  let validation = Validation::new(
+    account.id,
+    geodata.organization,
+    geodata.id.unwrap(),
+    vec![validity],
+  );
   context.models.validation.create(validation).await?;
   let res = PublicGeodata::from(geodata);
   Ok(Json(res))
 }
 
 async fn query_geodata(
-  _account: TokenAccount,
+  UserAccount(account): UserAccount,
   Extension(context): Extension<Context>,
-) -> Result<Json<Vec<PublicGeodata>>, Error> {
-  let limit = FindOptions::builder().limit(10).build();
+  AcceptGeoJson(accept_geojson): AcceptGeoJson,
+  OriginalUri(uri): OriginalUri,
+  Query(page): Query<PageQuery>,
+  Query(filter): Query<GeodataFilter>,
+) -> Result<Response, Error> {
+  let geodata = find_geodata_page(&context, &account, &page, &filter, doc! {}).await?;
+  Ok(respond_page(accept_geojson, &uri, geodata))
+}
+
+/// Geodata within `min` and `max` meters of a point, nearest first. Pages
+/// keep the distance order.
+async fn get_geodata_near(
+  UserAccount(account): UserAccount,
+  Extension(context): Extension<Context>,
+  AcceptGeoJson(accept_geojson): AcceptGeoJson,
+  OriginalUri(uri): OriginalUri,
+  params: Query<NearQueryParams>,
+  Query(page): Query<PageQuery>,
+  Query(filter): Query<GeodataFilter>,
+) -> Result<Response, Error> {
+  debug!("params: {:?}", &params);
+  let pagination = Pagination::natural(&page, &context.settings.pagination)?;
+  let query = filter.to_query()?;
+  let tenant = Tenant::load(&context, &account).await?;
+  let total = context
+    .models
+    .geodata
+    .count_near(
+      &tenant,
+      query.clone(),
+      [params.lon.into(), params.lat.into()],
+      params.min.into(),
+      params.max.into(),
+    )
+    .await?;
+  let mut near = doc! { "location": {
+        "$near": {
+           "$geometry": { "type": "Point", "coordinates": [
+                      params.lon,
+                      params.lat
+                  ]
+            }, "$minDistance": params.min, "$maxDistance": params.max
+        }
+      }
+  };
+  near.extend(query);
   let geodata = context
     .models
     .geodata
-    .find(doc! {}, limit)
-    .await?
-    .into_iter()
-    .map(Into::into)
-    .collect::<Vec<PublicGeodata>>();
+    .find_scoped(&tenant, near, pagination.options())
+    .await?;
+  let geodata = pagination.page(geodata, total)?.map(PublicGeodata::from);
 
   debug!("Returning geodata");
-  Ok(Json(geodata))
+  Ok(respond_page(accept_geojson, &uri, geodata))
 }
 
-async fn get_geodata_near(
-  _account: TokenAccount,
+/// Geodata within a bounding box (`bbox=west,south,east,north`) or a circle
+/// (`lon`, `lat` and `radius` in meters).
+async fn get_geodata_within(
+  UserAccount(account): UserAccount,
   Extension(context): Extension<Context>,
-  params: Query<NearQueryParams>,
-) -> Result<Json<Vec<PublicGeodata>>, Error> {
-  debug!("params: {:?}", &params);
-  let geodata = context
+  AcceptGeoJson(accept_geojson): AcceptGeoJson,
+  OriginalUri(uri): OriginalUri,
+  Query(params): Query<WithinQueryParams>,
+  Query(page): Query<PageQuery>,
+  Query(filter): Query<GeodataFilter>,
+) -> Result<Response, Error> {
+  let within = match (&params.bbox, params.lon, params.lat, params.radius) {
+    (Some(bbox), None, None, None) => Geometry::covering(&BoundingBox::parse(bbox)?).to_query()?,
+    (None, Some(lon), Some(lat), Some(radius)) => geojson::circle(lon, lat, radius)?,
+    _ => {
+      return Err(Error::BadRequest(BadRequest::new(
+        "bbox".to_owned(),
+        "Query either a bbox, or lon, lat and radius".to_owned(),
+      )))
+    }
+  };
+
+  let query = doc! { "location": { "$geoWithin": within } };
+  let geodata = find_geodata_page(&context, &account, &page, &filter, query).await?;
+  Ok(respond_page(accept_geojson, &uri, geodata))
+}
+
+/// Geodata within a GeoJSON Polygon or MultiPolygon.
+async fn post_geodata_within(
+  UserAccount(account): UserAccount,
+  Extension(context): Extension<Context>,
+  AcceptGeoJson(accept_geojson): AcceptGeoJson,
+  OriginalUri(uri): OriginalUri,
+  Query(page): Query<PageQuery>,
+  Query(filter): Query<GeodataFilter>,
+  Json(geometry): Json<Geometry>,
+) -> Result<Response, Error> {
+  if !geometry.is_areal() {
+    return Err(Error::BadRequest(BadRequest::new(
+      "type".to_owned(),
+      "Geodata can only be within a Polygon or MultiPolygon".to_owned(),
+    )));
+  }
+  geometry.validate()?;
+
+  let query = doc! { "location": { "$geoWithin": geometry.to_query()? } };
+  let geodata = find_geodata_page(&context, &account, &page, &filter, query).await?;
+  Ok(respond_page(accept_geojson, &uri, geodata))
+}
+
+/// Geodata intersecting a GeoJSON geometry.
+async fn post_geodata_intersects(
+  UserAccount(account): UserAccount,
+  Extension(context): Extension<Context>,
+  AcceptGeoJson(accept_geojson): AcceptGeoJson,
+  OriginalUri(uri): OriginalUri,
+  Query(page): Query<PageQuery>,
+  Query(filter): Query<GeodataFilter>,
+  Json(geometry): Json<Geometry>,
+) -> Result<Response, Error> {
+  geometry.validate()?;
+
+  let query = doc! { "location": { "$geoIntersects": geometry.to_query()? } };
+  let geodata = find_geodata_page(&context, &account, &page, &filter, query).await?;
+  Ok(respond_page(accept_geojson, &uri, geodata))
+}
+
+/// Streams every geodata matching the attribute filters and an optional
+/// `bbox`, as NDJSON (the default), a GeoJSON FeatureCollection or CSV. The
+/// records are written as the cursor yields them, so memory use stays the
+/// same however large the export.
+async fn export_geodata(
+  UserAccount(account): UserAccount,
+  Extension(context): Extension<Context>,
+  Query(params): Query<ExportQueryParams>,
+  Query(filter): Query<GeodataFilter>,
+) -> Result<Response, Error> {
+  let mut query = filter.to_query()?;
+  if let Some(bbox) = &params.bbox {
+    let within = Geometry::covering(&BoundingBox::parse(bbox)?).to_query()?;
+    query.insert("location", doc! { "$geoWithin": within });
+  }
+  // Exports may outlast the idle timeout of the cursor while the client
+  // reads slowly. The cursor is closed when the response is dropped.
+  let options = FindOptions::builder()
+    .sort(doc! { "_id": 1 })
+    .no_cursor_timeout(true)
+    .build();
+  let tenant = Tenant::load(&context, &account).await?;
+  let cursor = context
     .models
     .geodata
-    .find(
-      doc! { "location": {
-            "$near": {
-               "$geometry": { "type": "Point", "coordinates": [
-                          params.lon,
-                          params.lat
-                      ]
-                }, "$minDistance": params.min, "$maxDistance": params.max
-            }
-          }
-      },
-      None,
+    .cursor_scoped(&tenant, query, options)
+    .await?;
+
+  let format = params.format.unwrap_or(export::Format::NdJson);
+  let records = cursor.enumerate().map(move |(index, geodata)| {
+    let geodata = PublicGeodata::from(geodata.map_err(Error::Wither)?);
+    Ok::<Bytes, Error>(Bytes::from(format.record(index, geodata)))
+  });
+  let body = stream::once(async move { Ok(Bytes::from(format.header())) })
+    .chain(records)
+    .chain(stream::once(async move { Ok(Bytes::from(format.footer())) }));
+  let disposition = format!("attachment; filename=\"geodata.{}\"", format.extension());
+
+  debug!("Exporting geodata as {:?}", format);
+  Ok(
+    (
+      [
+        (header::CONTENT_TYPE, format.media_type().to_owned()),
+        (header::CONTENT_DISPOSITION, disposition),
+      ],
+      StreamBody::new(body),
     )
+      .into_response(),
+  )
+}
+
+/// A page of the geodata matching `query` and the attribute filters.
+async fn find_geodata_page(
+  context: &Context,
+  account: &TokenAccount,
+  page: &PageQuery,
+  filter: &GeodataFilter,
+  query: Document,
+) -> Result<Page<PublicGeodata>, Error> {
+  let pagination = Pagination::new(page, &context.settings.pagination, "created")?;
+  let mut filter = filter.to_query()?;
+  filter.extend(query);
+  let tenant = Tenant::load(context, account).await?;
+  let geodata = context
+    .models
+    .geodata
+    .find_page_scoped(&tenant, filter, &pagination)
     .await?
-    .into_iter()
-    .map(Into::into)
-    .collect::<Vec<PublicGeodata>>();
+    .map(PublicGeodata::from);
 
   debug!("Returning geodata");
-  Ok(Json(geodata))
+  Ok(geodata)
+}
+
+/// Answers with a page of geodata, as a GeoJSON FeatureCollection when the
+/// client accepts `application/geo+json`, as JSON otherwise.
+fn respond_page(accept_geojson: bool, uri: &Uri, page: Page<PublicGeodata>) -> Response {
+  if !accept_geojson {
+    return vary_accept(Json(page).into_response());
+  }
+
+  let mut links = vec![Link::new(uri.to_string(), "self", geojson::MEDIA_TYPE)];
+  if let Some(cursor) = &page.next_cursor {
+    let next = pagination::next_page_uri(uri, cursor);
+    links.push(Link::new(next, "next", geojson::MEDIA_TYPE));
+  }
+  let features = page.items.into_iter().map(Feature::from).collect();
+
+  vary_accept(geojson_response(FeatureCollection::new(features, links, page.total)))
+}
+
+fn geojson_response<T: Serialize>(body: T) -> Response {
+  ([(header::CONTENT_TYPE, geojson::MEDIA_TYPE)], Json(body)).into_response()
+}
+
+/// Marks a response as negotiated on `Accept`, so caches keep the JSON and
+/// GeoJSON responses of a URI apart.
+fn vary_accept(mut response: Response) -> Response {
+  let headers = response.headers_mut();
+  headers.insert(header::VARY, HeaderValue::from_static("accept"));
+  response
+}
+
+async fn get_geodata(
+  UserAccount(account): UserAccount,
+  Extension(context): Extension<Context>,
+  AcceptGeoJson(accept_geojson): AcceptGeoJson,
+  Path(id): Path<String>,
+) -> Result<Response, Error> {
+  let id = ObjectId::parse_str(&id)?;
+  let tenant = Tenant::load(&context, &account).await?;
+  let geodata = context
+    .models
+    .geodata
+    .find_one_scoped(&tenant, doc! { "_id": id }, None)
+    .await?
+    .ok_or_else(|| NotFound::new(String::from("geodata")))?;
+
+  let geodata = PublicGeodata::from(geodata);
+  if accept_geojson {
+    return Ok(vary_accept(geojson_response(Feature::from(geodata))));
+  }
+  Ok(vary_accept(Json(geodata).into_response()))
+}
+
+/// Earlier versions of a geodata record, oldest first.
+async fn query_geodata_versions(
+  UserAccount(account): UserAccount,
+  Extension(context): Extension<Context>,
+  Path(id): Path<String>,
+  Query(page): Query<PageQuery>,
+) -> Result<Json<Page<PublicGeodataVersion>>, Error> {
+  let pagination = Pagination::new(&page, &context.settings.pagination, "created")?;
+  let id = ObjectId::parse_str(&id)?;
+  let tenant = Tenant::load(&context, &account).await?;
+  let geodata = context
+    .models
+    .geodata
+    .find_one_scoped(&tenant, doc! { "_id": id }, None)
+    .await?
+    .ok_or_else(|| NotFound::new(String::from("geodata")))?;
+
+  let query = doc! { "geodata": id, "version": { "$lt": geodata.version } };
+  let versions = context
+    .models
+    .geodata_version
+    .find_page(query, &pagination)
+    .await?
+    .map(PublicGeodataVersion::from);
+
+  Ok(Json(versions))
+}
+
+/// Revises geodata. The replaced version is kept in `geodata_versions` with
+/// its hash, the new version's hash becomes the one validations expect, and
+/// is queued to be anchored along with the hash it replaced.
+async fn update_geodata(
+  AdminAccount(account): AdminAccount,
+  Extension(context): Extension<Context>,
+  Path(id): Path<String>,
+  Json(body): Json<UpdateGeodata>,
+) -> Result<Json<PublicGeodata>, Error> {
+  let id = ObjectId::parse_str(&id)?;
+  let geodata = find_owned_geodata(&context, &account, &id).await?;
+
+  if body.is_empty() {
+    debug!("Empty geodata update, returning 400 status code");
+    return Err(Error::BadRequest(BadRequest::new(
+      "body".to_owned(),
+      "Nothing to update".to_owned(),
+    )));
+  }
+
+  if let Some(location) = &body.location {
+    location.validate_field("location")?;
+  }
+
+  let hash = geodata::hash_geodata(geodata.clone()).await?;
+  let mut revision = geodata.clone();
+  if let Some(location) = body.location {
+    revision.location = location;
+  }
+  if let Some(geotype) = body.geotype {
+    revision.geotype = geotype;
+  }
+  if let Some(value) = body.value {
+    revision.value = value;
+  }
+  if let Some(source) = body.source {
+    revision.source = source;
+  }
+  if let Some(quality) = body.quality {
+    revision.quality = quality;
+  }
+  if let Some(observed_at) = body.observed_at {
+    revision.observed_at = Some(observed_at);
+  }
+  context
+    .models
+    .geotype
+    .validate_geodata(&revision.geotype, &revision.location, revision.value, revision.quality)
+    .await?;
+  revision.version = geodata.version + 1;
+  revision.previous_hash = Some(hash.clone());
+  revision.updated = Some(date::now());
+  revision.updated_by = Some(account.id);
+  let revised_hash = geodata::hash_geodata(revision.clone()).await?;
+
+  // The snapshot and the anchor request are written first, so the anchor
+  // worker can complete a revision interrupted after this point, or drop it
+  // if it never got written.
+  let models = &context.models;
+  let snapshot = GeodataVersion::new(geodata.clone(), hash, account.id);
+  models.geodata_version.snapshot(snapshot).await?;
+  let ready_at = date::after(anchor_queue::REVISION_GRACE);
+  let anchor_request = models
+    .anchor_request
+    .enqueue_revision(&revision, revised_hash.clone(), ready_at)
+    .await?;
+  let anchor_request_id = anchor_request.id.unwrap();
+
+  let revised = match models.geodata.revise(&geodata, &revision).await? {
+    Some(revised) => revised,
+    None => {
+      models.anchor_request.delete_one(doc! { "_id": anchor_request_id }).await?;
+      return Err(Error::Conflict(Conflict::new(String::from(
+        "Geodata was revised or deleted meanwhile",
+      ))));
+    }
+  };
+  let superseded_at = revised.updated.unwrap();
+  models
+    .geodata_version
+    .supersede(&id, geodata.version, &account.id, superseded_at)
+    .await?;
+  models
+    .validation
+    .authorize_revision(&id, Validity::new(account.id, revised_hash))
+    .await?;
+  models.anchor_request.mark_ready(&anchor_request_id).await?;
+
+  debug!("Geodata {} revised to version {} by {}", id, revised.version, account.id);
+  Ok(Json(PublicGeodata::from(revised)))
+}
+
+/// Deletes geodata, leaving a tombstone that keeps its history verifiable.
+async fn delete_geodata(
+  AdminAccount(account): AdminAccount,
+  Extension(context): Extension<Context>,
+  Path(id): Path<String>,
+) -> Result<StatusCode, Error> {
+  let id = ObjectId::parse_str(&id)?;
+  find_owned_geodata(&context, &account, &id).await?;
+
+  context
+    .models
+    .geodata
+    .delete_soft(&id, &account.id)
+    .await?
+    .ok_or_else(|| NotFound::new(String::from("geodata")))?;
+
+  debug!("Geodata {} deleted by {}", id, account.id);
+  Ok(StatusCode::NO_CONTENT)
+}
+
+/// Finds geodata of the account's own organization. Geodata shared by other
+/// organizations can be read but not changed.
+async fn find_owned_geodata(
+  context: &Context,
+  account: &TokenAccount,
+  id: &ObjectId,
+) -> Result<Geodata, Error> {
+  let tenant = Tenant::load(context, account).await?;
+  let geodata = context
+    .models
+    .geodata
+    .find_one_scoped(&tenant, doc! { "_id": id }, None)
+    .await?
+    .ok_or_else(|| NotFound::new(String::from("geodata")))?;
+
+  if !tenant.owns(geodata.organization) {
+    debug!("Geodata {} is shared with, not owned by the account", id);
+    return Err(Error::Authenticate(AuthenticateError::Forbidden));
+  }
+
+  Ok(geodata)
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -138,4 +555,28 @@ struct CreateGeodata {
   value: f64,
   source: String,
   quality: i32,
+  #[serde(default, with = "date::optional_interval")]
+  observed_at: Option<Interval>,
+}
+
+#[derive(Deserialize, Debug)]
+struct UpdateGeodata {
+  location: Option<Location>,
+  geotype: Option<String>,
+  value: Option<f64>,
+  source: Option<String>,
+  quality: Option<i32>,
+  #[serde(default, with = "date::optional_interval")]
+  observed_at: Option<Interval>,
+}
+
+impl UpdateGeodata {
+  fn is_empty(&self) -> bool {
+    self.location.is_none()
+      && self.geotype.is_none()
+      && self.value.is_none()
+      && self.source.is_none()
+      && self.quality.is_none()
+      && self.observed_at.is_none()
+  }
 }
@@ -0,0 +1,83 @@
+use axum::{
+  extract::{Extension, OriginalUri, Query},
+  http::header,
+  response::{IntoResponse, Response},
+  routing::get,
+  Json, Router,
+};
+use bson::doc;
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::common::authenticate_request::UserAccount;
+use crate::common::geohash;
+use crate::common::geojson::{self, BoundingBox, Feature, FeatureCollection, Geometry, Link};
+use crate::common::grid::{self, GridCell};
+use crate::common::tenant::Tenant;
+use crate::context::Context;
+use crate::errors::{BadRequest, Error};
+use crate::models::geodata::GeodataFilter;
+use crate::routes::USER_SCOPE;
+
+#[derive(Deserialize, Debug)]
+struct GridQueryParams {
+  geotype: String,
+  bbox: String,
+  precision: usize,
+}
+
+pub fn create_route() -> Router {
+  let user = Router::new().route("/geodata/grid", get(get_geodata_grid));
+
+  Router::new().nest(USER_SCOPE, user)
+}
+
+/// Summarizes the values of a geotype within a `bbox` per geohash cell of
+/// `precision` characters, as a FeatureCollection of cell Polygons. Geodata
+/// falls in the cell of the center of its location. Grids of more than
+/// `grid.max_cells` cells are refused.
+async fn get_geodata_grid(
+  UserAccount(account): UserAccount,
+  Extension(context): Extension<Context>,
+  OriginalUri(uri): OriginalUri,
+  Query(params): Query<GridQueryParams>,
+  Query(filter): Query<GeodataFilter>,
+) -> Result<Response, Error> {
+  if params.geotype.contains(',') || params.geotype.trim().is_empty() {
+    return Err(invalid("geotype", "A grid is of a single geotype"));
+  }
+  if !(1..=geohash::PRECISION).contains(&params.precision) {
+    let message = format!("Precision ranges from 1 to {}", geohash::PRECISION);
+    return Err(invalid("precision", &message));
+  }
+  let within = Geometry::covering(&BoundingBox::parse(&params.bbox)?).to_query()?;
+
+  // The filter reads `geotype` from the query string too.
+  let mut query = filter.to_query()?;
+  query.insert("location", doc! { "$geoWithin": within });
+  let max_cells = context.settings.grid.max_cells;
+  let tenant = Tenant::load(&context, &account).await?;
+  let cells: Vec<GridCell> = context
+    .models
+    .geodata
+    .aggregate_scoped(&tenant, query, grid::cells(params.precision, max_cells + 1))
+    .await?;
+  if cells.len() as i64 > max_cells {
+    let message = format!(
+      "The grid exceeds {} cells, lower the precision or narrow the bbox",
+      max_cells
+    );
+    return Err(invalid("precision", &message));
+  }
+
+  debug!("Returning a grid of {} cells", cells.len());
+  let number_matched = cells.len() as u64;
+  let features = cells.into_iter().map(Feature::from).collect();
+  let links = vec![Link::new(uri.to_string(), "self", geojson::MEDIA_TYPE)];
+  let collection = FeatureCollection::new(features, links, number_matched);
+  Ok(([(header::CONTENT_TYPE, geojson::MEDIA_TYPE)], Json(collection)).into_response())
+}
+
+fn invalid(field: &str, message: &str) -> Error {
+  Error::BadRequest(BadRequest::new(field.to_owned(), message.to_owned()))
+}
@@ -0,0 +1,354 @@
+use axum::{
+  extract::{BodyStream, Extension, TypedHeader},
+  headers::ContentType,
+  response::{IntoResponse, Response},
+  routing::post,
+  Json, Router,
+};
+use bson::{doc, oid::ObjectId};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::{debug, error, warn};
+
+use crate::common::authenticate_request::AdminAccount;
+use crate::common::import::{self, CsvHeader, Format, ParsedRow};
+use crate::common::models::ModelExt;
+use crate::common::token::TokenAccount;
+use crate::context::Context;
+use crate::errors::{BadRequest, Error, FieldError};
+use crate::models::anchor_request::AnchorRequest;
+use crate::models::geodata::{self, Geodata};
+use crate::models::geotype::{self, Geotype};
+use crate::models::validation::{Validation, Validity};
+use crate::routes::ADMIN_SCOPE;
+
+pub fn create_route() -> Router {
+  let admin = Router::new().route("/geodata/import", post(import_geodata));
+
+  Router::new().nest(ADMIN_SCOPE, admin)
+}
+
+/// The outcome of an import: counts of the accepted and rejected rows, and
+/// the first `import.max_reported_rejections` rejected rows with the
+/// reasons, `truncated` telling whether more were left out. Rows are lines
+/// for NDJSON and CSV, the header being line 1, and features for GeoJSON.
+/// An import that fails midway answers with the report so far and the
+/// `error`: rows up to `completed_through` were imported or rejected, later
+/// ones weren't imported, so retrying from the next row doesn't duplicate
+/// any.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ImportReport {
+  pub accepted: u64,
+  pub rejected: u64,
+  pub rows: Vec<ImportResult>,
+  #[serde(default)]
+  pub truncated: bool,
+  pub completed_through: u64,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportResult {
+  pub row: u64,
+  pub errors: Vec<FieldError>,
+}
+
+/// Imports geodata in bulk from NDJSON, a GeoJSON FeatureCollection or CSV
+/// with `lon` and `lat` columns, as told by the content type. Each row is
+/// validated on its own, accepted rows are inserted in batches, and their
+/// hashes are queued for anchoring rather than anchored one by one.
+async fn import_geodata(
+  AdminAccount(account): AdminAccount,
+  Extension(context): Extension<Context>,
+  TypedHeader(content_type): TypedHeader<ContentType>,
+  body: BodyStream,
+) -> Result<Response, Error> {
+  let format = Format::from_content_type(&content_type.to_string()).ok_or_else(|| {
+    invalid(
+      "content-type",
+      "Import application/x-ndjson, application/geo+json or text/csv",
+    )
+  })?;
+  let geotypes = context.models.geotype.registry().await?;
+  let mut importer = Importer::new(&context, &account, geotypes);
+
+  let read = match format {
+    Format::GeoJson => read_feature_collection(&context, body, &mut importer).await,
+    _ => read_lines(&context, format, body, &mut importer).await,
+  };
+  importer.finish(read).await
+}
+
+async fn read_feature_collection(
+  context: &Context,
+  mut body: BodyStream,
+  importer: &mut Importer<'_>,
+) -> Result<(), Error> {
+  let mut buffer = Vec::new();
+  while let Some(chunk) = body.next().await {
+    buffer.extend_from_slice(&chunk.map_err(read_error)?);
+    if buffer.len() > context.settings.import.max_feature_collection_bytes {
+      return Err(invalid("body", "FeatureCollection is too large, import NDJSON instead"));
+    }
+  }
+  for (i, row) in import::parse_feature_collection(&buffer)?.into_iter().enumerate() {
+    importer.push(i as u64 + 1, row).await?;
+  }
+
+  Ok(())
+}
+
+// NDJSON and CSV are read a line at a time, so only the pending batch, a
+// line of at most `import.max_line_bytes` and the reported rejections are
+// held in memory whatever the size of the import.
+async fn read_lines(
+  context: &Context,
+  format: Format,
+  mut body: BodyStream,
+  importer: &mut Importer<'_>,
+) -> Result<(), Error> {
+  let max_line_bytes = context.settings.import.max_line_bytes;
+  let too_long = |line: u64| {
+    let message = format!("Line {} exceeds {} bytes", line, max_line_bytes);
+    invalid("body", &message)
+  };
+  let mut lines = LineParser::new(format);
+  let mut buffer = Vec::new();
+  while let Some(chunk) = body.next().await {
+    buffer.extend_from_slice(&chunk.map_err(read_error)?);
+    while let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+      if end > max_line_bytes {
+        return Err(too_long(lines.line + 1));
+      }
+      let line = buffer.drain(..=end).collect::<Vec<u8>>();
+      if let Some((row, parsed)) = lines.parse(&line)? {
+        importer.push(row, parsed).await?;
+      }
+    }
+    if buffer.len() > max_line_bytes {
+      return Err(too_long(lines.line + 1));
+    }
+  }
+  if !buffer.is_empty() {
+    if let Some((row, parsed)) = lines.parse(&buffer)? {
+      importer.push(row, parsed).await?;
+    }
+  }
+  if format == Format::Csv && lines.header.is_none() {
+    return Err(invalid("header", "CSV imports start with a header row"));
+  }
+
+  Ok(())
+}
+
+/// Numbers and parses the lines of an NDJSON or CSV import.
+struct LineParser {
+  format: Format,
+  line: u64,
+  header: Option<CsvHeader>,
+}
+
+impl LineParser {
+  fn new(format: Format) -> Self {
+    Self {
+      format,
+      line: 0,
+      header: None,
+    }
+  }
+
+  /// The row held by `line`, if any. Blank lines are skipped but counted.
+  fn parse(&mut self, line: &[u8]) -> Result<Option<(u64, ParsedRow)>, Error> {
+    self.line += 1;
+    let line = match std::str::from_utf8(line) {
+      Ok(line) => line.trim_end_matches(|c| c == '\n' || c == '\r'),
+      Err(_) => {
+        let error = FieldError {
+          field: "row".to_owned(),
+          message: "Row isn't valid UTF-8".to_owned(),
+        };
+        return Ok(Some((self.line, Err(vec![error]))));
+      }
+    };
+    if line.trim().is_empty() {
+      return Ok(None);
+    }
+
+    let parsed = match (self.format, &self.header) {
+      (Format::Csv, Some(header)) => header.parse_row(line),
+      (Format::Csv, None) => {
+        self.header = Some(CsvHeader::parse(line)?);
+        return Ok(None);
+      }
+      _ => import::parse_ndjson_line(line),
+    };
+
+    Ok(Some((self.line, parsed)))
+  }
+}
+
+/// Checks rows against the geotype registry, collects accepted rows into
+/// batches and reports on the rejected ones.
+struct Importer<'a> {
+  context: &'a Context,
+  account: &'a TokenAccount,
+  geotypes: HashMap<String, Geotype>,
+  batch: Vec<(u64, Geodata)>,
+  last_row: u64,
+  report: ImportReport,
+}
+
+impl<'a> Importer<'a> {
+  fn new(
+    context: &'a Context,
+    account: &'a TokenAccount,
+    geotypes: HashMap<String, Geotype>,
+  ) -> Self {
+    Self {
+      context,
+      account,
+      geotypes,
+      batch: Vec::new(),
+      last_row: 0,
+      report: ImportReport::default(),
+    }
+  }
+
+  async fn push(&mut self, row: u64, parsed: ParsedRow) -> Result<(), Error> {
+    self.last_row = row;
+    let parsed = parsed.and_then(|record| {
+      let errors = match self.geotypes.get(&record.geotype) {
+        Some(geotype) => geotype.errors(&record.location, record.value, record.quality),
+        None => vec![geotype::unknown(&record.geotype)],
+      };
+      if errors.is_empty() {
+        return Ok(record);
+      }
+      Err(errors)
+    });
+    let record = match parsed {
+      Ok(record) => record,
+      Err(errors) => {
+        self.report.rejected += 1;
+        if self.report.rows.len() < self.context.settings.import.max_reported_rejections {
+          self.report.rows.push(ImportResult { row, errors });
+        } else {
+          self.report.truncated = true;
+        }
+        return Ok(());
+      }
+    };
+
+    let mut geodata = Geodata::new(
+      self.account.id,
+      self.account.organization,
+      record.location,
+      record.geotype,
+      record.value,
+      record.source,
+      record.quality,
+    )
+    .with_observed_at(record.observed_at);
+    // Ids are known before inserting, so a failed batch can be undone.
+    geodata.id = Some(ObjectId::new());
+    self.batch.push((row, geodata));
+    if self.batch.len() >= self.context.settings.import.batch_size {
+      self.flush().await?;
+    }
+
+    Ok(())
+  }
+
+  /// Inserts the batch, along with the validation of each record and its
+  /// request to be anchored. A batch that fails is undone, so it's either
+  /// imported whole or not at all.
+  async fn flush(&mut self) -> Result<(), Error> {
+    let (rows, batch): (Vec<u64>, Vec<Geodata>) = self.batch.drain(..).unzip();
+    let last_row = match rows.last() {
+      Some(row) => *row,
+      None => return Ok(()),
+    };
+    let ids = batch.iter().filter_map(|geodata| geodata.id).collect::<Vec<ObjectId>>();
+    if let Err(err) = self.insert(batch).await {
+      if let Err(undo) = self.undo(&ids).await {
+        error!("Failed to undo the import of rows up to {}: {}", last_row, undo);
+      }
+      return Err(err);
+    }
+
+    self.report.accepted += ids.len() as u64;
+    self.report.completed_through = last_row;
+    Ok(())
+  }
+
+  async fn insert(&self, batch: Vec<Geodata>) -> Result<(), Error> {
+    let models = &self.context.models;
+    let batch = models.geodata.insert_many(batch).await?;
+
+    let mut validations = Vec::with_capacity(batch.len());
+    let mut anchor_requests = Vec::with_capacity(batch.len());
+    for geodata in batch {
+      let hash = geodata::hash_geodata(geodata.clone()).await?;
+      let validity = Validity::new(self.account.id, hash.clone());
+      validations.push(Validation::new(
+        self.account.id,
+        geodata.organization,
+        geodata.id.unwrap(),
+        vec![validity],
+      ));
+      anchor_requests.push(AnchorRequest::new(&geodata, hash));
+    }
+    models.validation.insert_many(validations).await?;
+    models.anchor_request.insert_many(anchor_requests).await?;
+
+    Ok(())
+  }
+
+  async fn undo(&self, ids: &[ObjectId]) -> Result<(), Error> {
+    let models = &self.context.models;
+    let ids = ids.to_vec();
+    models.anchor_request.delete_many(doc! { "geodata": { "$in": &ids } }).await?;
+    models.validation.delete_many(doc! { "geodata": { "$in": &ids } }).await?;
+    models.geodata.delete_many(doc! { "_id": { "$in": &ids } }).await?;
+
+    Ok(())
+  }
+
+  /// Answers with the report once the rows are read. An import failing
+  /// before any row was imported or rejected answers with the error alone.
+  async fn finish(mut self, read: Result<(), Error>) -> Result<Response, Error> {
+    let imported = match read {
+      Ok(()) => self.flush().await,
+      Err(err) => Err(err),
+    };
+    let err = match imported {
+      Ok(()) => {
+        self.report.completed_through = self.last_row;
+        debug!(
+          "Imported {} geodata for {}, rejected {}",
+          self.report.accepted, self.account.id, self.report.rejected
+        );
+        return Ok(Json(self.report).into_response());
+      }
+      Err(err) if self.report.accepted == 0 && self.report.rejected == 0 => return Err(err),
+      Err(err) => err,
+    };
+
+    warn!(
+      "Import by {} stopped after row {}: {}",
+      self.account.id, self.report.completed_through, err
+    );
+    self.report.error = Some(err.to_string());
+    Ok((err.status(), Json(self.report)).into_response())
+  }
+}
+
+fn read_error(err: axum::Error) -> Error {
+  invalid("body", &format!("Failed to read the body: {}", err))
+}
+
+fn invalid(field: &str, message: &str) -> Error {
+  Error::BadRequest(BadRequest::new(field.to_owned(), message.to_owned()))
+}
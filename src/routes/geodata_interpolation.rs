@@ -0,0 +1,178 @@
+use axum::{
+  extract::{Extension, Query},
+  http::header,
+  response::{IntoResponse, Response},
+  routing::get,
+  Json, Router,
+};
+use bson::doc;
+use serde::{Deserialize, Serialize};
+use tokio::task;
+use tracing::debug;
+use wither::mongodb::options::FindOptions;
+
+use crate::common::authenticate_request::UserAccount;
+use crate::common::geojson::{BoundingBox, Geometry, EARTH_RADIUS};
+use crate::common::interpolation::{self, Grid, Method, Parameters, Sample};
+use crate::common::tenant::Tenant;
+use crate::context::Context;
+use crate::errors::{BadRequest, Error};
+use crate::models::geodata::GeodataFilter;
+use crate::routes::USER_SCOPE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GridFormat {
+  Json,
+  Csv,
+  Asc,
+}
+
+#[derive(Deserialize, Debug)]
+struct InterpolateQueryParams {
+  geotype: String,
+  bbox: String,
+  resolution: f64,
+  method: Option<Method>,
+  power: Option<f64>,
+  radius: Option<f64>,
+  format: Option<GridFormat>,
+}
+
+/// An interpolated grid, with what it interpolates.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Surface {
+  pub geotype: String,
+  pub method: Method,
+  pub power: f64,
+  pub radius: Option<f64>,
+  pub points: usize,
+  #[serde(flatten)]
+  pub grid: Grid,
+}
+
+pub fn create_route() -> Router {
+  let user = Router::new().route("/geodata/interpolate", get(get_geodata_interpolation));
+
+  Router::new().nest(USER_SCOPE, user)
+}
+
+/// Interpolates the values of a geotype onto a grid of cells of
+/// `resolution` degrees over a `bbox`, by inverse distance weighting (the
+/// default, with `power` 2) or from the nearest point. Points take part
+/// when within `radius` meters of a cell center, or all of them without a
+/// radius. Geodata counts as a point at the center of its location. The
+/// grid comes as JSON, CSV or an ESRI ASCII grid.
+async fn get_geodata_interpolation(
+  UserAccount(account): UserAccount,
+  Extension(context): Extension<Context>,
+  Query(params): Query<InterpolateQueryParams>,
+  Query(filter): Query<GeodataFilter>,
+) -> Result<Response, Error> {
+  if params.geotype.contains(',') || params.geotype.trim().is_empty() {
+    return Err(invalid("geotype", "A surface is of a single geotype"));
+  }
+  let bbox = BoundingBox::parse(&params.bbox)?;
+  let positive = |value: f64| value.is_finite() && value > 0.0;
+  if !positive(params.resolution) {
+    return Err(invalid("resolution", "Resolution must be a positive number of degrees"));
+  }
+  let parameters = Parameters {
+    method: params.method.unwrap_or(Method::Idw),
+    power: params.power.unwrap_or(2.0),
+    radius: params.radius,
+  };
+  if !positive(parameters.power) {
+    return Err(invalid("power", "Power must be a positive number"));
+  }
+  if !parameters.radius.map_or(true, positive) {
+    return Err(invalid("radius", "Radius must be a positive number of meters"));
+  }
+  let settings = &context.settings.interpolation;
+  let (ncols, nrows) = Grid::size(&bbox, params.resolution);
+  if ncols.saturating_mul(nrows) > settings.max_cells {
+    let message = format!(
+      "The grid exceeds {} cells, lower the resolution or narrow the bbox",
+      settings.max_cells
+    );
+    return Err(invalid("resolution", &message));
+  }
+
+  // Points just outside the box are in reach of the cells along its edges.
+  let reach = parameters.radius.map_or(bbox, |radius| around(&bbox, radius));
+  let mut query = filter.to_query()?;
+  query.insert(
+    "location",
+    doc! { "$geoWithin": Geometry::covering(&reach).to_query()? },
+  );
+  let options = FindOptions::builder()
+    .limit(settings.max_points as i64 + 1)
+    .build();
+  let tenant = Tenant::load(&context, &account).await?;
+  let geodata = context
+    .models
+    .geodata
+    .find_scoped(&tenant, query, options)
+    .await?;
+  if geodata.len() > settings.max_points {
+    let message = format!(
+      "The surface exceeds {} points, narrow the bbox or filter the geodata",
+      settings.max_points
+    );
+    return Err(invalid("bbox", &message));
+  }
+  let samples = geodata
+    .iter()
+    .filter_map(|geodata| {
+      let (lon, lat) = BoundingBox::around(geodata.location.positions())?.center();
+      Some(Sample {
+        lon,
+        lat,
+        value: geodata.value,
+      })
+    })
+    .collect::<Vec<Sample>>();
+
+  let points = samples.len();
+  let resolution = params.resolution;
+  let grid = task::spawn_blocking(move || {
+    interpolation::interpolate(&samples, &bbox, resolution, &parameters)
+  })
+  .await
+  .map_err(Error::RunSyncTask)?;
+
+  debug!("Interpolated {} points onto {} by {} cells", points, ncols, nrows);
+  let (media_type, body) = match params.format.unwrap_or(GridFormat::Json) {
+    GridFormat::Csv => ("text/csv; charset=utf-8", grid.to_csv()),
+    GridFormat::Asc => ("text/plain; charset=utf-8", grid.to_asc()),
+    GridFormat::Json => {
+      let surface = Surface {
+        geotype: params.geotype.trim().to_owned(),
+        method: parameters.method,
+        power: parameters.power,
+        radius: parameters.radius,
+        points,
+        grid,
+      };
+      return Ok(Json(surface).into_response());
+    }
+  };
+  Ok(([(header::CONTENT_TYPE, media_type)], body).into_response())
+}
+
+/// The box holding every position within `radius` meters of `bbox`.
+fn around(bbox: &BoundingBox, radius: f64) -> BoundingBox {
+  let lat = (radius / EARTH_RADIUS).to_degrees();
+  let widest = bbox.north.abs().max(bbox.south.abs()) + lat;
+  let lon = lat / widest.min(89.0).to_radians().cos();
+  BoundingBox {
+    west: (bbox.west - lon).max(-180.0),
+    south: (bbox.south - lat).max(-89.0),
+    east: (bbox.east + lon).min(180.0),
+    north: (bbox.north + lat).min(89.0),
+  }
+}
+
+fn invalid(field: &str, message: &str) -> Error {
+  Error::BadRequest(BadRequest::new(field.to_owned(), message.to_owned()))
+}
@@ -0,0 +1,145 @@
+use axum::{
+  extract::{Extension, Query},
+  routing::get,
+  Json, Router,
+};
+use bson::{doc, Document};
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::common::authenticate_request::UserAccount;
+use crate::common::date::Date;
+use crate::common::geojson::{self, Geometry};
+use crate::common::series::{self, Bucket, TimeSeries};
+use crate::common::tenant::Tenant;
+use crate::common::token::TokenAccount;
+use crate::context::Context;
+use crate::errors::{BadRequest, Error};
+use crate::models::geodata::GeodataFilter;
+use crate::routes::USER_SCOPE;
+
+#[derive(Deserialize, Debug)]
+struct SeriesQueryParams {
+  geotype: String,
+  start: String,
+  end: String,
+  bucket: Option<Bucket>,
+  lon: Option<f64>,
+  lat: Option<f64>,
+  radius: Option<f64>,
+}
+
+pub fn create_route() -> Router {
+  let user = Router::new().route(
+    "/geodata/series",
+    get(get_geodata_series).post(post_geodata_series),
+  );
+
+  Router::new().nest(USER_SCOPE, user)
+}
+
+/// The time series of a geotype within `radius` meters of a point.
+async fn get_geodata_series(
+  UserAccount(account): UserAccount,
+  Extension(context): Extension<Context>,
+  Query(params): Query<SeriesQueryParams>,
+  Query(filter): Query<GeodataFilter>,
+) -> Result<Json<TimeSeries>, Error> {
+  let circle = match (params.lon, params.lat, params.radius) {
+    (Some(lon), Some(lat), Some(radius)) => geojson::circle(lon, lat, radius)?,
+    _ => return Err(invalid("lon", "Query lon, lat and radius, or post a geometry")),
+  };
+
+  let location = doc! { "location": { "$geoWithin": circle } };
+  let series = find_series(&context, &account, &params, &filter, location).await?;
+  Ok(Json(series))
+}
+
+/// The time series of a geotype at the geodata intersecting a GeoJSON
+/// geometry.
+async fn post_geodata_series(
+  UserAccount(account): UserAccount,
+  Extension(context): Extension<Context>,
+  Query(params): Query<SeriesQueryParams>,
+  Query(filter): Query<GeodataFilter>,
+  Json(geometry): Json<Geometry>,
+) -> Result<Json<TimeSeries>, Error> {
+  geometry.validate()?;
+
+  let location = doc! { "location": { "$geoIntersects": geometry.to_query()? } };
+  let series = find_series(&context, &account, &params, &filter, location).await?;
+  Ok(Json(series))
+}
+
+/// The values of a single geotype from `start` up to `end`, in time order,
+/// of the geodata matching `location` and the attribute filters. Series
+/// longer than `series.max_points` are refused rather than cut short, so
+/// analysts never mistake part of a series for all of it.
+async fn find_series(
+  context: &Context,
+  account: &TokenAccount,
+  params: &SeriesQueryParams,
+  filter: &GeodataFilter,
+  location: Document,
+) -> Result<TimeSeries, Error> {
+  if params.geotype.contains(',') || params.geotype.trim().is_empty() {
+    return Err(invalid("geotype", "A series is of a single geotype"));
+  }
+  let start = parse_date("start", &params.start)?;
+  let end = parse_date("end", &params.end)?;
+  if start >= end {
+    return Err(invalid("start", "Range ends before it starts"));
+  }
+
+  // The filter reads `geotype` from the query string too.
+  let mut query = filter.to_query()?;
+  query.extend(location);
+  let max_points = context.settings.series.max_points;
+  let tenant = Tenant::load(context, account).await?;
+  let geodata = &context.models.geodata;
+  let mut series = TimeSeries {
+    geotype: params.geotype.trim().to_owned(),
+    start,
+    end,
+    bucket: params.bucket,
+    points: None,
+    buckets: None,
+  };
+  let length = match params.bucket {
+    Some(bucket) => {
+      let stages = series::buckets(start, end, bucket, max_points + 1);
+      let buckets = geodata.aggregate_scoped(&tenant, query, stages).await?;
+      let length = buckets.len();
+      series.buckets = Some(buckets);
+      length
+    }
+    None => {
+      let stages = series::points(start, end, max_points + 1);
+      let points = geodata.aggregate_scoped(&tenant, query, stages).await?;
+      let length = points.len();
+      series.points = Some(points);
+      length
+    }
+  };
+  if length as i64 > max_points {
+    return Err(invalid(
+      "start",
+      &format!(
+        "The series exceeds {} points, narrow it or bucket it",
+        max_points
+      ),
+    ));
+  }
+
+  debug!("Returning a series of {} points", length);
+  Ok(series)
+}
+
+fn parse_date(field: &str, date: &str) -> Result<Date, Error> {
+  Date::parse_rfc3339_str(date)
+    .map_err(|_| invalid(field, "Date must be RFC 3339, e.g. 2022-06-01T00:00:00Z"))
+}
+
+fn invalid(field: &str, message: &str) -> Error {
+  Error::BadRequest(BadRequest::new(field.to_owned(), message.to_owned()))
+}
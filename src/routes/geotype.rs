@@ -0,0 +1,163 @@
+use axum::{
+  extract::{Extension, Path, Query},
+  http::StatusCode,
+  routing::{get, post, put},
+  Json, Router,
+};
+use bson::doc;
+use tracing::debug;
+
+use crate::common::authenticate_request::{AdminAccount, UserAccount};
+use crate::common::date;
+use crate::common::models::ModelExt;
+use crate::common::pagination::{Page, PageQuery, Pagination};
+use crate::context::Context;
+use crate::errors::{BadRequest, Conflict, Error, InvalidFields, NotFound};
+use crate::models::geotype::{Geotype, GeotypeDefinition, PublicGeotype};
+use crate::routes::{ADMIN_SCOPE, USER_SCOPE};
+
+pub fn create_route() -> Router {
+  let admin = Router::new()
+    .route("/geotypes", post(create_geotype))
+    .route("/geotypes/:name", put(update_geotype).delete(delete_geotype));
+  let user = Router::new()
+    .route("/geotypes", get(query_geotypes))
+    .route("/geotypes/:name", get(get_geotype));
+
+  Router::new()
+    .nest(ADMIN_SCOPE, admin)
+    .nest(USER_SCOPE, user)
+}
+
+async fn create_geotype(
+  AdminAccount(admin): AdminAccount,
+  Extension(context): Extension<Context>,
+  Json(body): Json<GeotypeDefinition>,
+) -> Result<Json<PublicGeotype>, Error> {
+  validate_definition(&body)?;
+  let key = body.name.to_lowercase();
+  if let Some(existing) = context.models.geotype.find_one(doc! { "key": &key }, None).await? {
+    return Err(Error::Conflict(Conflict::new(format!(
+      "Geotype {} already exists",
+      existing.name
+    ))));
+  }
+
+  // Another admin may register the same name meanwhile.
+  let name = body.name.clone();
+  let geotype = context
+    .models
+    .geotype
+    .create(Geotype::new(body))
+    .await
+    .map_err(|err| match err.is_duplicate_key() {
+      true => Error::Conflict(Conflict::new(format!("Geotype {} already exists", name))),
+      false => err,
+    })?;
+
+  debug!("Geotype {} registered by {}", geotype.name, admin.id);
+  Ok(Json(PublicGeotype::from(geotype)))
+}
+
+async fn query_geotypes(
+  _user: UserAccount,
+  Extension(context): Extension<Context>,
+  Query(page): Query<PageQuery>,
+) -> Result<Json<Page<PublicGeotype>>, Error> {
+  let pagination = Pagination::new(&page, &context.settings.pagination, "created_at")?;
+  let geotypes = context
+    .models
+    .geotype
+    .find_page(doc! {}, &pagination)
+    .await?
+    .map(PublicGeotype::from);
+
+  Ok(Json(geotypes))
+}
+
+async fn get_geotype(
+  _user: UserAccount,
+  Extension(context): Extension<Context>,
+  Path(name): Path<String>,
+) -> Result<Json<PublicGeotype>, Error> {
+  let geotype = context
+    .models
+    .geotype
+    .find_by_name(&name)
+    .await?
+    .ok_or_else(|| NotFound::new(String::from("geotype")))?;
+
+  Ok(Json(PublicGeotype::from(geotype)))
+}
+
+/// Replaces the definition of a geotype. Geodata refers to geotypes by name,
+/// so names can't change. Existing geodata isn't checked again.
+async fn update_geotype(
+  AdminAccount(admin): AdminAccount,
+  Extension(context): Extension<Context>,
+  Path(name): Path<String>,
+  Json(body): Json<GeotypeDefinition>,
+) -> Result<Json<PublicGeotype>, Error> {
+  if body.name != name {
+    return Err(Error::BadRequest(BadRequest::new(
+      "name".to_owned(),
+      "Geotypes can't be renamed".to_owned(),
+    )));
+  }
+  validate_definition(&body)?;
+
+  let update = doc! { "$set": {
+    "unit": &body.unit,
+    "description": &body.description,
+    "min_value": body.min_value,
+    "max_value": body.max_value,
+    "geometry_types": &body.geometry_types,
+    "min_quality": body.min_quality,
+    "max_quality": body.max_quality,
+    "updated_at": date::now(),
+  } };
+  let geotype = context
+    .models
+    .geotype
+    .find_one_and_update(doc! { "name": &name }, update)
+    .await?
+    .ok_or_else(|| NotFound::new(String::from("geotype")))?;
+
+  debug!("Geotype {} redefined by {}", name, admin.id);
+  Ok(Json(PublicGeotype::from(geotype)))
+}
+
+/// Removes a geotype no geodata uses.
+async fn delete_geotype(
+  AdminAccount(admin): AdminAccount,
+  Extension(context): Extension<Context>,
+  Path(name): Path<String>,
+) -> Result<StatusCode, Error> {
+  if context.models.geodata.exists(doc! { "geotype": &name }).await? {
+    return Err(Error::Conflict(Conflict::new(format!(
+      "Geotype {} is used by geodata",
+      name
+    ))));
+  }
+
+  let result = context
+    .models
+    .geotype
+    .delete_one(doc! { "name": &name })
+    .await?;
+  if result.deleted_count == 0 {
+    return Err(Error::NotFound(NotFound::new(String::from("geotype"))));
+  }
+
+  debug!("Geotype {} removed by {}", name, admin.id);
+  Ok(StatusCode::NO_CONTENT)
+}
+
+fn validate_definition(definition: &GeotypeDefinition) -> Result<(), Error> {
+  let errors = definition.errors();
+  if errors.is_empty() {
+    return Ok(());
+  }
+
+  Err(Error::InvalidFields(InvalidFields::new(errors)))
+}
@@ -1,3 +1,46 @@
 pub mod geodata;
+pub mod geodata_grid;
+pub mod geodata_import;
+pub mod geodata_interpolation;
+pub mod geodata_series;
+pub mod geotype;
 pub mod account;
-pub mod validation;
\ No newline at end of file
+pub mod validation;
+pub mod api_key;
+pub mod ogc;
+pub mod organization;
+pub mod tiles;
+pub mod well_known;
+
+use axum::http::{HeaderValue, Request};
+use axum::middleware::{self, Next};
+use axum::response::Response;
+use axum::Router;
+
+// Versioned route prefixes, one per role. Handlers mounted under a scope
+// still name the role they require through their account extractor.
+pub const ADMIN_SCOPE: &str = "/v1/admin";
+pub const USER_SCOPE: &str = "/v1/user";
+pub const VALIDATOR_SCOPE: &str = "/v1/validator";
+
+// Deprecated: obfuscated prefixes kept as aliases of the scopes above.
+pub const LEGACY_ADMIN_PATH: &str = "/6a2dda";
+pub const LEGACY_USER_PATH: &str = "/6b0866";
+pub const LEGACY_VALIDATOR_PATH: &str = "/5be0da";
+
+/// Mounts `router` under `scope` and under its deprecated `legacy` alias.
+pub fn scope(scope: &str, legacy: &str, router: Router) -> Router {
+  Router::new()
+    .nest(scope, router.clone())
+    .nest(legacy, router.layer(middleware::from_fn(deprecated)))
+}
+
+// Flags responses served through a legacy alias so clients can migrate.
+async fn deprecated<B>(req: Request<B>, next: Next<B>) -> Response {
+  let mut response = next.run(req).await;
+  response
+    .headers_mut()
+    .insert("deprecation", HeaderValue::from_static("true"));
+
+  response
+}
@@ -0,0 +1,169 @@
+use axum::{
+  extract::{Extension, OriginalUri, Path, Query},
+  http::header,
+  response::{IntoResponse, Response},
+  routing::get,
+  Json, Router,
+};
+use bson::doc;
+use bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use wither::mongodb::options::FindOptions;
+
+use crate::common::authenticate_request::UserAccount;
+use crate::common::geojson::{self, Feature, FeatureCollection, Geometry, Link};
+use crate::common::models::ModelExt;
+use crate::common::ogc::{self, Collection, Collections, Conformance, LandingPage};
+use crate::common::pagination::{self, PageQuery, Pagination};
+use crate::common::tenant::Tenant;
+use crate::context::Context;
+use crate::errors::{Error, NotFound};
+use crate::models::geodata::PublicGeodata;
+use crate::models::geotype::Geotype;
+
+#[derive(Deserialize, Debug)]
+struct ItemsQueryParams {
+  bbox: Option<String>,
+  datetime: Option<String>,
+}
+
+/// A feature of a collection, with links to itself and its collection.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Item {
+  #[serde(flatten)]
+  pub feature: Feature,
+  pub links: Vec<Link>,
+}
+
+// OGC API clients discover the API from its root, so it lives outside the
+// versioned scopes. The landing page and conformance declaration are
+// public, collections need a user.
+pub fn create_route() -> Router {
+  Router::new()
+    .route("/ogc", get(get_landing_page))
+    .route("/ogc/conformance", get(get_conformance))
+    .route("/ogc/collections", get(get_collections))
+    .route("/ogc/collections/:collection", get(get_collection))
+    .route("/ogc/collections/:collection/items", get(get_items))
+    .route("/ogc/collections/:collection/items/:feature", get(get_item))
+}
+
+async fn get_landing_page() -> Json<LandingPage> {
+  Json(LandingPage::new())
+}
+
+async fn get_conformance() -> Json<Conformance> {
+  Json(Conformance::new())
+}
+
+/// A collection per geotype, by name.
+async fn get_collections(
+  UserAccount(_account): UserAccount,
+  Extension(context): Extension<Context>,
+) -> Result<Json<Collections>, Error> {
+  let options = FindOptions::builder()
+    .sort(doc! { "name": 1 })
+    .build();
+  let geotypes = context.models.geotype.find(doc! {}, options).await?;
+  let collections = geotypes.iter().map(Collection::from).collect();
+
+  Ok(Json(Collections::new(collections)))
+}
+
+async fn get_collection(
+  UserAccount(_account): UserAccount,
+  Extension(context): Extension<Context>,
+  Path(collection): Path<String>,
+) -> Result<Json<Collection>, Error> {
+  let geotype = find_collection(&context, &collection).await?;
+
+  Ok(Json(Collection::from(&geotype)))
+}
+
+/// The geodata of a collection, newest first, in pages of `limit` features
+/// followed through `next` links. `bbox` keeps the features intersecting
+/// it, `datetime` those observed, or else created, within it.
+async fn get_items(
+  UserAccount(account): UserAccount,
+  Extension(context): Extension<Context>,
+  OriginalUri(uri): OriginalUri,
+  Path(collection): Path<String>,
+  Query(params): Query<ItemsQueryParams>,
+  Query(page): Query<PageQuery>,
+) -> Result<Response, Error> {
+  let geotype = find_collection(&context, &collection).await?;
+  let pagination = Pagination::new(&page, &context.settings.pagination, "created")?;
+  let mut query = doc! { "geotype": geotype.name.clone() };
+  if let Some(bbox) = &params.bbox {
+    let geometry = Geometry::covering(&ogc::bbox(bbox)?).to_query()?;
+    query.insert("location", doc! { "$geoIntersects": geometry });
+  }
+  if let Some(datetime) = &params.datetime {
+    query.extend(ogc::datetime_query(datetime)?);
+  }
+
+  let tenant = Tenant::load(&context, &account).await?;
+  let page = context
+    .models
+    .geodata
+    .find_page_scoped(&tenant, query, &pagination)
+    .await?
+    .map(PublicGeodata::from);
+
+  let mut links = vec![
+    Link::new(uri.to_string(), "self", geojson::MEDIA_TYPE),
+    Link::new(collection_href(&geotype), "collection", "application/json"),
+  ];
+  if let Some(cursor) = &page.next_cursor {
+    let next = pagination::next_page_uri(&uri, cursor);
+    links.push(Link::new(next, "next", geojson::MEDIA_TYPE));
+  }
+  let features = page.items.into_iter().map(Feature::from).collect();
+
+  debug!("Returning items of collection {}", geotype.name);
+  Ok(geojson_response(FeatureCollection::new(features, links, page.total)))
+}
+
+async fn get_item(
+  UserAccount(account): UserAccount,
+  Extension(context): Extension<Context>,
+  Path((collection, feature)): Path<(String, String)>,
+) -> Result<Response, Error> {
+  let geotype = find_collection(&context, &collection).await?;
+  // Ids of other records aren't features of the collection either.
+  let id = ObjectId::parse_str(&feature).map_err(|_| NotFound::new(String::from("feature")))?;
+  let tenant = Tenant::load(&context, &account).await?;
+  let geodata = context
+    .models
+    .geodata
+    .find_one_scoped(&tenant, doc! { "_id": id, "geotype": geotype.name.clone() }, None)
+    .await?
+    .ok_or_else(|| NotFound::new(String::from("feature")))?;
+
+  let href = collection_href(&geotype);
+  let links = vec![
+    Link::new(format!("{}/items/{}", href, feature), "self", geojson::MEDIA_TYPE),
+    Link::new(href, "collection", "application/json"),
+  ];
+  let feature = Feature::from(PublicGeodata::from(geodata));
+
+  Ok(geojson_response(Item { feature, links }))
+}
+
+async fn find_collection(context: &Context, collection: &str) -> Result<Geotype, Error> {
+  context
+    .models
+    .geotype
+    .find_by_name(collection)
+    .await?
+    .ok_or_else(|| Error::NotFound(NotFound::new(String::from("collection"))))
+}
+
+fn collection_href(geotype: &Geotype) -> String {
+  format!("{}/collections/{}", ogc::ROOT, geotype.name)
+}
+
+fn geojson_response<T: Serialize>(body: T) -> Response {
+  ([(header::CONTENT_TYPE, geojson::MEDIA_TYPE)], Json(body)).into_response()
+}
@@ -0,0 +1,199 @@
+use axum::{
+  extract::{Extension, Path, Query},
+  http::StatusCode,
+  routing::{delete, post, put},
+  Json, Router,
+};
+use bson::doc;
+use bson::oid::ObjectId;
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::common::authenticate_request::AdminAccount;
+use crate::common::models::ModelExt;
+use crate::common::pagination::{Page, PageQuery, Pagination};
+use crate::context::Context;
+use crate::errors::{BadRequest, Error, NotFound};
+use crate::models::account::PublicAccount;
+use crate::models::organization::{Organization, PublicOrganization};
+use crate::models::sharing_grant::{PublicSharingGrant, SharingGrant};
+use crate::routes::ADMIN_SCOPE;
+
+pub fn create_route() -> Router {
+  let admin = Router::new()
+    .route("/organizations", post(create_organization).get(query_organizations))
+    .route(
+      "/organizations/:id/grants",
+      post(create_sharing_grant).get(query_sharing_grants),
+    )
+    .route("/organizations/:id/grants/:grant", delete(revoke_sharing_grant))
+    .route("/accounts/:id/organization", put(set_account_organization));
+
+  Router::new().nest(ADMIN_SCOPE, admin)
+}
+
+async fn create_organization(
+  AdminAccount(admin): AdminAccount,
+  Extension(context): Extension<Context>,
+  Json(body): Json<CreateOrganization>,
+) -> Result<Json<PublicOrganization>, Error> {
+  if context
+    .models
+    .organization
+    .exists(doc! { "name": &body.name })
+    .await?
+  {
+    return Err(Error::BadRequest(BadRequest::new(
+      "name".to_owned(),
+      format!("Organization {} already exists", body.name),
+    )));
+  }
+
+  let organization = Organization::new(body.name);
+  let organization = context.models.organization.create(organization).await?;
+
+  debug!("Organization {} created by {}", organization.name, admin.id);
+  Ok(Json(PublicOrganization::from(organization)))
+}
+
+async fn query_organizations(
+  _admin: AdminAccount,
+  Extension(context): Extension<Context>,
+  Query(page): Query<PageQuery>,
+) -> Result<Json<Page<PublicOrganization>>, Error> {
+  let pagination = Pagination::new(&page, &context.settings.pagination, "created_at")?;
+  let organizations = context
+    .models
+    .organization
+    .find_page(doc! {}, &pagination)
+    .await?
+    .map(PublicOrganization::from);
+
+  Ok(Json(organizations))
+}
+
+/// Shares the geodata of an organization with another one. Replaces an
+/// existing grant between the two.
+async fn create_sharing_grant(
+  AdminAccount(admin): AdminAccount,
+  Extension(context): Extension<Context>,
+  Path(id): Path<String>,
+  Json(body): Json<CreateSharingGrant>,
+) -> Result<Json<PublicSharingGrant>, Error> {
+  let owner = find_organization(&context, &id).await?;
+  let grantee = find_organization(&context, &body.grantee).await?;
+
+  if owner == grantee {
+    return Err(Error::BadRequest(BadRequest::new(
+      "grantee".to_owned(),
+      "An organization can't share with itself".to_owned(),
+    )));
+  }
+
+  context
+    .models
+    .sharing_grant
+    .delete_many(doc! { "owner": owner, "grantee": grantee })
+    .await?;
+  let grant = SharingGrant::new(owner, grantee, body.geotypes, admin.id);
+  let grant = context.models.sharing_grant.create(grant).await?;
+
+  debug!("Organization {} shared with {} by {}", owner, grantee, admin.id);
+  Ok(Json(PublicSharingGrant::from(grant)))
+}
+
+/// Grants made by an organization.
+async fn query_sharing_grants(
+  _admin: AdminAccount,
+  Extension(context): Extension<Context>,
+  Path(id): Path<String>,
+  Query(page): Query<PageQuery>,
+) -> Result<Json<Page<PublicSharingGrant>>, Error> {
+  let pagination = Pagination::new(&page, &context.settings.pagination, "created_at")?;
+  let owner = ObjectId::parse_str(&id)?;
+  let grants = context
+    .models
+    .sharing_grant
+    .find_page(doc! { "owner": owner }, &pagination)
+    .await?
+    .map(PublicSharingGrant::from);
+
+  Ok(Json(grants))
+}
+
+async fn revoke_sharing_grant(
+  AdminAccount(admin): AdminAccount,
+  Extension(context): Extension<Context>,
+  Path((id, grant)): Path<(String, String)>,
+) -> Result<StatusCode, Error> {
+  let owner = ObjectId::parse_str(&id)?;
+  let grant = ObjectId::parse_str(&grant)?;
+  let result = context
+    .models
+    .sharing_grant
+    .delete_one(doc! { "_id": grant, "owner": owner })
+    .await?;
+
+  if result.deleted_count == 0 {
+    return Err(Error::NotFound(NotFound::new(String::from("sharing grant"))));
+  }
+
+  debug!("Sharing grant {} revoked by {}", grant, admin.id);
+  Ok(StatusCode::NO_CONTENT)
+}
+
+/// Moves an account into an organization, or out of any with `null`. Access
+/// tokens naming the previous organization stop working.
+async fn set_account_organization(
+  AdminAccount(admin): AdminAccount,
+  Extension(context): Extension<Context>,
+  Path(id): Path<String>,
+  Json(body): Json<AccountOrganization>,
+) -> Result<Json<PublicAccount>, Error> {
+  let id = ObjectId::parse_str(&id)?;
+  let organization = match &body.organization {
+    Some(organization) => Some(find_organization(&context, organization).await?),
+    None => None,
+  };
+
+  let account = context
+    .models
+    .account
+    .set_organization(&id, organization)
+    .await?
+    .ok_or_else(|| NotFound::new(String::from("account")))?;
+
+  context.cache.invalidate_account(&id);
+
+  debug!("Account {} moved to organization {:?} by {}", id, organization, admin.id);
+  Ok(Json(PublicAccount::from(account)))
+}
+
+async fn find_organization(context: &Context, id: &str) -> Result<ObjectId, Error> {
+  let id = ObjectId::parse_str(id)?;
+  context
+    .models
+    .organization
+    .find_by_id(&id)
+    .await?
+    .ok_or_else(|| NotFound::new(String::from("organization")))?;
+
+  Ok(id)
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateOrganization {
+  name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateSharingGrant {
+  grantee: String,
+  #[serde(default)]
+  geotypes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AccountOrganization {
+  organization: Option<String>,
+}
@@ -0,0 +1,181 @@
+use axum::{
+  extract::{Extension, Path, Query},
+  http::{header, HeaderMap, StatusCode},
+  response::{IntoResponse, Response},
+  routing::get,
+  Router,
+};
+use bson::{doc, Document};
+use std::collections::BTreeMap;
+use tracing::debug;
+use wither::mongodb::options::FindOptions;
+
+use crate::common::authenticate_request::UserAccount;
+use crate::common::geohash;
+use crate::common::geojson::{BoundingBox, Geometry};
+use crate::common::grid::{self, GeotypeCell};
+use crate::common::hasher;
+use crate::common::mvt::{self, Layer, TileId, Value};
+use crate::common::tenant::Tenant;
+use crate::context::Context;
+use crate::errors::{BadRequest, Error, NotFound};
+use crate::models::geodata::{Geodata, GeodataFilter};
+use crate::routes::USER_SCOPE;
+
+pub fn create_route() -> Router {
+  let user = Router::new().route("/tiles/:z/:x/:tile", get(get_tile));
+
+  Router::new().nest(USER_SCOPE, user)
+}
+
+/// Serves the geodata of a tile (`/tiles/{z}/{x}/{y}.mvt`) matching the
+/// attribute filters as a Mapbox Vector Tile, with a layer per geotype.
+/// Below `tiles.cluster_max_zoom`, geodata is clustered per geohash cell
+/// into Points summarizing the values of each cell. Tiles carry an ETag,
+/// and requests with a matching `If-None-Match` answer 304.
+async fn get_tile(
+  UserAccount(account): UserAccount,
+  Extension(context): Extension<Context>,
+  Path((z, x, tile)): Path<(u32, u32, String)>,
+  Query(filter): Query<GeodataFilter>,
+  headers: HeaderMap,
+) -> Result<Response, Error> {
+  let y = tile
+    .strip_suffix(".mvt")
+    .and_then(|y| y.parse::<u32>().ok())
+    .ok_or_else(|| invalid("tile", "Tiles are addressed as /tiles/{z}/{x}/{y}.mvt"))?;
+  let tile = TileId::new(z, x, y).ok_or_else(|| NotFound::new(String::from("tile")))?;
+
+  let settings = &context.settings.tiles;
+  let query = filter.to_query()?;
+  let tenant = Tenant::load(&context, &account).await?;
+  let layers = if tile.z < settings.cluster_max_zoom {
+    let precision = cluster_precision(tile.z);
+    let (width, height) = geohash::cell_size(precision);
+    // Cells centered in the tile may reach beyond it.
+    let bounds = tile.bounds();
+    let reach = BoundingBox {
+      west: (bounds.west - width).max(-180.0),
+      south: (bounds.south - height).max(-89.0),
+      east: (bounds.east + width).min(180.0),
+      north: (bounds.north + height).min(89.0),
+    };
+    let query = within(query, &reach)?;
+    let stages = grid::geotype_cells(precision, settings.max_features);
+    let cells = context
+      .models
+      .geodata
+      .aggregate_scoped(&tenant, query, stages)
+      .await?;
+    cluster_layers(&tile, cells)
+  } else {
+    let query = within(query, &tile.bounds())?;
+    let options = FindOptions::builder()
+      .sort(doc! { "_id": 1 })
+      .limit(settings.max_features)
+      .build();
+    let geodata = context
+      .models
+      .geodata
+      .find_scoped(&tenant, query, options)
+      .await?;
+    geodata_layers(&tile, geodata)
+  };
+
+  let body = mvt::encode(&layers);
+  let etag = format!("\"{}\"", hasher::hash_bytes(&body));
+  let headers_out = [
+    (header::ETAG, etag.clone()),
+    (header::CACHE_CONTROL, "private, no-cache".to_owned()),
+  ];
+  let if_none_match = headers
+    .get(header::IF_NONE_MATCH)
+    .and_then(|value| value.to_str().ok());
+  if let Some(if_none_match) = if_none_match {
+    if if_none_match.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*") {
+      return Ok((StatusCode::NOT_MODIFIED, headers_out).into_response());
+    }
+  }
+
+  debug!("Returning tile {}/{}/{} of {} bytes", tile.z, tile.x, tile.y, body.len());
+  Ok(
+    (
+      [(header::CONTENT_TYPE, mvt::MEDIA_TYPE.to_owned())],
+      headers_out,
+      body,
+    )
+      .into_response(),
+  )
+}
+
+/// The geohash precision clustering a tile of zoom level `z` into cells an
+/// eighth of its width or less.
+pub fn cluster_precision(z: u32) -> usize {
+  let tile_width = 360.0 / f64::from(1u32 << z);
+  (1..=geohash::PRECISION)
+    .find(|precision| geohash::cell_size(*precision).0 <= tile_width / 8.0)
+    .unwrap_or(geohash::PRECISION)
+}
+
+fn within(mut query: Document, bbox: &BoundingBox) -> Result<Document, Error> {
+  let geometry = Geometry::covering(bbox).to_query()?;
+  query.insert("location", doc! { "$geoIntersects": geometry });
+  Ok(query)
+}
+
+fn geodata_layers(tile: &TileId, geodata: Vec<Geodata>) -> Vec<Layer> {
+  let mut layers = BTreeMap::new();
+  for geodata in geodata {
+    let mut attributes = vec![
+      ("id", Value::String(geodata.id.unwrap().to_hex())),
+      ("value", Value::Double(geodata.value)),
+      ("quality", Value::Int(geodata.quality.into())),
+      ("source", Value::String(geodata.source.clone())),
+      ("created", Value::String(geodata.created.to_rfc3339_string())),
+    ];
+    if let Some(observed_at) = geodata.observed_at {
+      attributes.push(("observed_at", Value::String(observed_at.to_string())));
+    }
+    layers
+      .entry(geodata.geotype.clone())
+      .or_insert_with(|| Layer::new(&geodata.geotype))
+      .add(tile, &geodata.location, &attributes);
+  }
+  layers.into_values().collect()
+}
+
+fn cluster_layers(tile: &TileId, cells: Vec<GeotypeCell>) -> Vec<Layer> {
+  let mut layers = BTreeMap::new();
+  for GeotypeCell { geotype, cell } in cells {
+    let (lon, lat) = match geohash::bounds(&cell.cell) {
+      Some(bounds) => bounds.center(),
+      None => continue,
+    };
+    if !tile.contains(tile.project(lon, lat)) {
+      continue;
+    }
+
+    let mut attributes = vec![
+      ("cluster", Value::Bool(true)),
+      ("point_count", Value::Int(cell.count as i64)),
+      ("min", Value::Double(cell.min)),
+      ("max", Value::Double(cell.max)),
+      ("mean", Value::Double(cell.mean)),
+    ];
+    if let Some(weighted_mean) = cell.weighted_mean {
+      attributes.push(("weighted_mean", Value::Double(weighted_mean)));
+    }
+    let point = Geometry::Point {
+      coordinates: vec![lon, lat],
+    };
+    layers
+      .entry(geotype.clone())
+      .or_insert_with(|| Layer::new(&geotype))
+      .add(tile, &point, &attributes);
+  }
+  layers.into_values().collect()
+}
+
+fn invalid(field: &str, message: &str) -> Error {
+  Error::BadRequest(BadRequest::new(field.to_owned(), message.to_owned()))
+}
@@ -1,57 +1,65 @@
 use crate::context::Context;
 use crate::errors::Error;
-use crate::common::token::TokenAccount;
+use crate::common::authenticate_request::ValidatorAccount;
 use crate::common::date;
 use crate::models::geodata;
-use crate::models::geodata::{HashableGeodata};
+use crate::models::geodata::{Geodata, HashableGeodata};
 use crate::models::validation::{ValidationResult, ValidationResults, Validation, Validity};
 use crate::common::models::ModelExt;
+use crate::common::pagination::{PageQuery, Pagination};
+use crate::common::tenant::Tenant;
 use crate::common::anchor;
 use axum::{
-  extract::{Extension},
+  extract::{Extension, Query},
   routing::get,
   Json, Router,
 };
 use bson::doc;
-use wither::mongodb::options::FindOptions;
 
-use crate::common::token::VALIDATOR_PATH;
+use crate::routes::{self, LEGACY_VALIDATOR_PATH, VALIDATOR_SCOPE};
 
 pub fn create_route() -> Router {
-  let validation_path = format!("{}{}", VALIDATOR_PATH, "/validation");
-  Router::new().route(&validation_path, get(query_validation))
+  let validator = Router::new().route("/validation", get(query_validation));
+  routes::scope(VALIDATOR_SCOPE, LEGACY_VALIDATOR_PATH, validator)
 }
 // TODO: move the functionality of this endpoint to an independent daemon process
 async fn query_validation(
-  account: TokenAccount,
+  ValidatorAccount(account): ValidatorAccount,
   Extension(context): Extension<Context>,
+  Query(page): Query<PageQuery>,
 ) -> Result<Json<ValidationResults>, Error> {
+  let pagination = Pagination::new(&page, &context.settings.pagination, "created")?;
   let mut v_results =  ValidationResults::new(vec![]);
-  let limit = FindOptions::builder().limit(10).build();
+  let tenant = Tenant::load(&context, &account).await?;
   let mut validations = context
     .models
     .validation
-    .find(doc! {}, limit)
-    .await?
-    .into_iter()
-    .map(Into::into)
-    .collect::<Vec<Validation>>();
-  for validation in &mut validations {
+    .find_page_scoped(&tenant, doc! {}, &pagination)
+    .await?;
+  v_results.next_cursor = validations.next_cursor.take();
+  v_results.total = validations.total;
+  for validation in &mut validations.items {
     let geodata = context
       .models
       .geodata
       .find_one(doc! { "_id": validation.geodata}, None)
-      .await?
-      .map(HashableGeodata::from);
+      .await?;
+    let version = geodata.as_ref().map_or(0, |geodata| geodata.version);
+    let history_intact = match &geodata {
+      Some(geodata) => is_history_intact(&context, geodata, validation).await?,
+      None => false,
+    };
+    let geodata = geodata.map(HashableGeodata::from);
     let j_hashable = serde_json::to_string(&geodata).unwrap();
     let hash = geodata::hash_data(j_hashable).await?;
 
-    // validity check compares current hash result with original when created (validation.validities[0].hash)
-    // debug!("this hash: {} original hash: {:?}", &hash, &validation.validities[0].hash);
+    // validity check compares current hash result with the authorized one: the original when created
+    // (validation.validities[0].hash), or that of the latest revision made through the API
+    let validated = hash == validation.expected_hash() && history_intact;
     let validity = Validity::new(account.id, hash.clone());
     validation.validities.push(validity);
 
-    let v_result = ValidationResult::new(account.id, validation.geodata, hash == validation.validities[0].hash);
+    let v_result = ValidationResult::new(account.id, validation.geodata, version, validated);
     let succeeded = v_result.validated;
     v_results.results.push(v_result);
     let v_doc = bson::to_document(&validation).unwrap();
@@ -78,3 +86,45 @@ async fn query_validation(
 
   Ok(Json(v_results))
 }
+
+/// Checks the version chain of revised geodata. Every earlier version must
+/// still hash to its recorded hash and link to the version before it, the
+/// first version must match the hash recorded at creation, and the current
+/// version must link to the last one. Anything else means the history was
+/// altered outside of the API.
+async fn is_history_intact(
+  context: &Context,
+  geodata: &Geodata,
+  validation: &Validation,
+) -> Result<bool, Error> {
+  if geodata.version == 1 {
+    return Ok(geodata.previous_hash.is_none());
+  }
+
+  let versions = context
+    .models
+    .geodata_version
+    .find_for_geodata(geodata.id.as_ref().unwrap(), geodata.version)
+    .await?;
+  if versions.len() as i32 != geodata.version - 1 {
+    return Ok(false);
+  }
+
+  let mut previous_hash: Option<String> = None;
+  for (index, version) in versions.into_iter().enumerate() {
+    if version.version != index as i32 + 1 || version.previous_hash != previous_hash {
+      return Ok(false);
+    }
+    if index == 0 && version.hash != validation.validities[0].hash {
+      return Ok(false);
+    }
+
+    let hash = version.hash.clone();
+    if geodata::hash_geodata(version).await? != hash {
+      return Ok(false);
+    }
+    previous_hash = Some(hash);
+  }
+
+  Ok(geodata.previous_hash == previous_hash)
+}
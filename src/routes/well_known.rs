@@ -0,0 +1,14 @@
+use axum::{extract::Extension, routing::get, Json, Router};
+use jsonwebtoken::jwk::JwkSet;
+
+use crate::context::Context;
+
+pub fn create_route() -> Router {
+  Router::new().route("/.well-known/jwks.json", get(get_jwks))
+}
+
+/// Publishes the public keys that verify access tokens, so other services
+/// can verify them without holding a signing secret.
+async fn get_jwks(Extension(context): Extension<Context>) -> Json<JwkSet> {
+  Json(context.keys.jwks().clone())
+}
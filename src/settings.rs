@@ -18,9 +18,113 @@ pub struct Database {
   pub name: String,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct JwtKey {
+  pub kid: String,
+  // Paths of PEM encoded Ed25519 keys. Keys without a private key only
+  // verify tokens, e.g. while a rotated-out key's tokens expire.
+  pub private_key: Option<String>,
+  pub public_key: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Lockout {
+  // Failed logins in a row that lock an account temporarily.
+  pub max_attempts: i32,
+  // Seconds an account stays locked.
+  pub duration: i64,
+  // Delay after a failed login, doubled for each further failure.
+  pub base_delay_ms: u64,
+  pub max_delay_ms: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Argon2 {
+  // Argon2id cost of new password hashes. Hashes made with other costs are
+  // upgraded on the next successful login.
+  pub memory_kib: u32,
+  pub iterations: u32,
+  pub parallelism: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Mfa {
+  // Shown by authenticator apps next to the account.
+  pub issuer: String,
+  // Accounts holding any of these roles must use a second factor.
+  pub required_roles: Vec<String>,
+  // Seconds to complete the login with a second factor.
+  pub token_ttl: i64,
+  // Time steps of clock drift accepted either way.
+  pub skew: i64,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Auth {
+  // Signs HS256 tokens when no `keys` are configured (development only).
   pub secret: String,
+  #[serde(default)]
+  pub keys: Vec<JwtKey>,
+  // Kid of the key that signs new tokens, defaults to the first key with a
+  // private key.
+  pub signing_key: Option<String>,
+  // Lifetimes in seconds.
+  pub access_token_ttl: i64,
+  pub refresh_token_ttl: i64,
+  pub password_reset_ttl: i64,
+  // How long account states and revocations are cached, in seconds.
+  pub cache_ttl: u64,
+  pub argon2: Argon2,
+  pub lockout: Lockout,
+  pub mfa: Mfa,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Pagination {
+  // Page size when a request sets no `limit`.
+  pub default_limit: i64,
+  // Larger limits are capped to this.
+  pub max_limit: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Import {
+  // Accepted rows inserted per round trip.
+  pub batch_size: usize,
+  // FeatureCollections are read whole, unlike NDJSON and CSV which stream.
+  pub max_feature_collection_bytes: usize,
+  // Longest NDJSON or CSV line, which is held whole while it's read.
+  pub max_line_bytes: usize,
+  // Rejected rows listed in a report, past which they're only counted.
+  pub max_reported_rejections: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Series {
+  // Points, or buckets, a time series may hold.
+  pub max_points: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Grid {
+  // Cells a grid may hold.
+  pub max_cells: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Tiles {
+  // Lower zoom levels cluster geodata per geohash cell.
+  pub cluster_max_zoom: u32,
+  // Features, or clusters, a tile may hold. Tiles leave out the rest.
+  pub max_features: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Interpolation {
+  // Cells of an interpolated grid.
+  pub max_cells: usize,
+  // Points interpolated at once. Time grows with cells times points.
+  pub max_points: usize,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -36,6 +140,12 @@ pub struct Settings {
   pub logger: Logger,
   pub database: Database,
   pub auth: Auth,
+  pub pagination: Pagination,
+  pub import: Import,
+  pub series: Series,
+  pub grid: Grid,
+  pub tiles: Tiles,
+  pub interpolation: Interpolation,
   pub contract: Contract,
 }
 
@@ -1,14 +1,20 @@
 //! Provides context for localhost/test and an initialization function for integration test
 //! roles: admin, user, validator
 //! accounts: admin (role admin, user), user (role user), validator (role validator)
-//! Also provides access to docker commands
+//! Also provides access to docker commands and a notifier recording password reset tokens
+use async_trait::async_trait;
 use bson::doc;
+use geodata_rest::common::notifier::Notifier;
+use geodata_rest::errors::Error as AppError;
 use geodata_rest::common::models::ModelExt;
+use geodata_rest::common::keys::KeyStore;
 use geodata_rest::models::{Models, account::{self, Account}, role::Role};
+use geodata_rest::models::geotype::{Geotype, GeotypeDefinition};
 use geodata_rest::settings::Settings;
 use geodata_rest::context::Context;
 use geodata_rest::database::Database;
 use std::error::Error;
+use std::sync::{Arc, Mutex};
 use std::{ffi::OsStr, panic, process, str, time::Duration};
 use std::env;
 use tokio::time;
@@ -16,6 +22,44 @@ use tendermint_rpc as rpc;
 use tendermint_rpc::Client;
 const RPC_PORT2: u16 = 26657;
 
+/// Keeps the password reset tokens sent per email.
+#[derive(Clone, Default)]
+pub struct RecordingNotifier {
+  pub reset_tokens: Arc<Mutex<Vec<(String, String)>>>,
+}
+
+impl RecordingNotifier {
+  pub fn last_reset_token(&self, email: &str) -> Option<String> {
+    let tokens = self.reset_tokens.lock().unwrap();
+    tokens
+      .iter()
+      .rev()
+      .find(|(recipient, _)| recipient == email)
+      .map(|(_, token)| token.clone())
+  }
+
+  /// Waits for a reset token to be sent to `email`, as resets are issued
+  /// after the response.
+  pub async fn wait_reset_token(&self, email: &str) -> Option<String> {
+    for _ in 0..50 {
+      if let Some(token) = self.last_reset_token(email) {
+        return Some(token);
+      }
+      time::sleep(Duration::from_millis(100)).await;
+    }
+    None
+  }
+}
+
+#[async_trait]
+impl Notifier for RecordingNotifier {
+  async fn send_password_reset(&self, account: &Account, token: &str) -> Result<(), AppError> {
+    let mut tokens = self.reset_tokens.lock().unwrap();
+    tokens.push((account.email.clone(), token.to_owned()));
+    Ok(())
+  }
+}
+
 pub async fn get_testdb_context() -> Context {
     env::set_var("RUN_MODE", "test");
     let settings = match Settings::new() {
@@ -35,7 +79,12 @@ pub async fn get_testdb_context() -> Context {
       Err(err) => panic!("Failed to setup models {}", err),
     };
 
-    Context::new(models, settings.clone())
+    let keys = match KeyStore::from_settings(&settings.auth, &settings.environment) {
+      Ok(value) => value,
+      Err(err) => panic!("Failed to load token keys {:#}", err),
+    };
+
+    Context::new(models, settings.clone(), keys)
 }
 
 pub async fn initialize_testdb(context: &Context) -> Result<(), Box<dyn Error>> {
@@ -52,6 +101,55 @@ pub async fn initialize_testdb(context: &Context) -> Result<(), Box<dyn Error>>
   context.models.account.delete_many(doc! {}).await?;
   assert_eq!(context.models.account.count(doc! {}).await?, 0);
 
+  context.models.session.delete_many(doc! {}).await?;
+  assert_eq!(context.models.session.count(doc! {}).await?, 0);
+
+  context.models.revoked_token.delete_many(doc! {}).await?;
+  assert_eq!(context.models.revoked_token.count(doc! {}).await?, 0);
+
+  context.models.api_key.delete_many(doc! {}).await?;
+  assert_eq!(context.models.api_key.count(doc! {}).await?, 0);
+
+  context.models.audit_event.delete_many(doc! {}).await?;
+  assert_eq!(context.models.audit_event.count(doc! {}).await?, 0);
+
+  context.models.password_reset.delete_many(doc! {}).await?;
+  assert_eq!(context.models.password_reset.count(doc! {}).await?, 0);
+
+  context.models.organization.delete_many(doc! {}).await?;
+  assert_eq!(context.models.organization.count(doc! {}).await?, 0);
+
+  context.models.sharing_grant.delete_many(doc! {}).await?;
+  assert_eq!(context.models.sharing_grant.count(doc! {}).await?, 0);
+
+  context.models.anchor_request.delete_many(doc! {}).await?;
+  assert_eq!(context.models.anchor_request.count(doc! {}).await?, 0);
+
+  context.models.login_attempt.delete_many(doc! {}).await?;
+  assert_eq!(context.models.login_attempt.count(doc! {}).await?, 0);
+
+  context.models.geotype.delete_many(doc! {}).await?;
+  assert_eq!(context.models.geotype.count(doc! {}).await?, 0);
+
+  context.models.migration.delete_many(doc! {}).await?;
+  assert_eq!(context.models.migration.count(doc! {}).await?, 0);
+
+  // register the geotypes of the test geodata
+  let geotypes = [("Wind", "m/s", 0.0, 100.0), ("Temperature", "Cel", -90.0, 60.0)];
+  for (name, unit, min_value, max_value) in geotypes {
+    let geotype = Geotype::new(GeotypeDefinition {
+      name: name.to_string(),
+      unit: unit.to_string(),
+      description: None,
+      min_value: Some(min_value),
+      max_value: Some(max_value),
+      geometry_types: vec!["Point".to_string()],
+      min_quality: 1,
+      max_quality: 5,
+    });
+    context.models.geotype.create(geotype).await?;
+  }
+
   // create roles
   let role_user = Role::new("user".to_string());
   let role_user = context.models.role.create(role_user).await?;
@@ -62,7 +160,7 @@ pub async fn initialize_testdb(context: &Context) -> Result<(), Box<dyn Error>>
   assert_eq!(context.models.role.count(doc! {}).await?, 3);
 
   // create users
-  let password_hash = account::hash_password("test").await?;
+  let password_hash = account::hash_password("test", &context.settings.auth.argon2).await?;
   let admin = Account::new(
     "admin".to_string(),
     "admin@test.com".to_string(),
@@ -79,10 +177,12 @@ pub async fn initialize_testdb(context: &Context) -> Result<(), Box<dyn Error>>
   );
   context.models.account.create(user).await?;
 
+  // the validator keeps a bcrypt hash from before the switch to Argon2id
+  let legacy_hash = bcrypt::hash("test", 4)?;
   let validator = Account::new(
     "validator".to_string(),
     "validator@test.com".to_string(),
-    password_hash.clone(),
+    legacy_hash,
     vec![role_validator],
   );
   context.models.account.create(validator).await?;
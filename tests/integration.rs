@@ -6,14 +6,32 @@
 //! Validator authenticates and runs validation
 use axum::{extract::Extension, http::header, Router};
 use bson::{doc, oid::ObjectId};
+use geodata_rest::common::anchor_queue;
+use geodata_rest::common::date::Interval;
+use geodata_rest::common::export;
+use geodata_rest::common::geohash;
+use geodata_rest::common::geojson::{self, BoundingBox, FeatureCollection};
+use geodata_rest::common::grid::GridCell;
+use geodata_rest::common::hasher;
+use geodata_rest::common::import::{self, CsvHeader, Format};
+use geodata_rest::common::interpolation::{self, Grid, Method, Parameters, Sample};
+use geodata_rest::common::keys::KeyStore;
+use geodata_rest::common::migrations;
 use geodata_rest::common::models::ModelExt;
-use geodata_rest::common::token::{ADMIN_PATH, USER_PATH, VALIDATOR_PATH};
+use geodata_rest::common::mvt::{self, Layer, TileId};
+use geodata_rest::common::ogc;
+use geodata_rest::common::pagination::Page;
+use geodata_rest::common::series::{self, Bucket, TimeSeries};
+use geodata_rest::common::totp;
 use geodata_rest::context::Context;
 use geodata_rest::logger::Logger;
 use geodata_rest::models::account::PublicAccount;
-use geodata_rest::models::geodata::{Geometry, Location, PublicGeodata};
+use geodata_rest::models::geodata::{Geodata, GeodataFilter, Geometry, Location, PublicGeodata};
+use geodata_rest::models::geotype::PublicGeotype;
 use geodata_rest::models::validation::ValidationResults;
-use geodata_rest::routes;
+use geodata_rest::routes::geodata_import::ImportReport;
+use geodata_rest::routes::geodata_interpolation::Surface;
+use geodata_rest::routes::{self, ADMIN_SCOPE, LEGACY_USER_PATH, USER_SCOPE, VALIDATOR_SCOPE};
 use serde::{Deserialize, Serialize};
 use tower_http::{
   compression::CompressionLayer, propagate_header::PropagateHeaderLayer,
@@ -29,7 +47,17 @@ fn app(context: Context) -> Router {
   Router::new()
     .merge(routes::account::create_route())
     .merge(routes::geodata::create_route())
+    .merge(routes::geodata_grid::create_route())
+    .merge(routes::geodata_import::create_route())
+    .merge(routes::geodata_interpolation::create_route())
+    .merge(routes::geodata_series::create_route())
+    .merge(routes::geotype::create_route())
     .merge(routes::validation::create_route())
+    .merge(routes::api_key::create_route())
+    .merge(routes::ogc::create_route())
+    .merge(routes::organization::create_route())
+    .merge(routes::tiles::create_route())
+    .merge(routes::well_known::create_route())
     // High level logging of requests and responses
     .layer(
       trace::TraceLayer::new_for_http()
@@ -65,12 +93,19 @@ mod tests {
   async fn test_workflow() {
     let limit = FindOptions::builder().limit(10).build();
 
-    let context = get_testdb_context().await;
+    let notifier = RecordingNotifier::default();
+    let context = get_testdb_context().await.with_notifier(notifier.clone());
     initialize_testdb(&context).await.unwrap();
     let listener = TcpListener::bind("0.0.0.0:0".parse::<SocketAddr>().unwrap()).unwrap();
     let addr = listener.local_addr().unwrap();
 
     let validation_model = context.models.validation.clone();
+    let audit_event_model = context.models.audit_event.clone();
+    let account_model = context.models.account.clone();
+    let geodata_model = context.models.geodata.clone();
+    let login_attempt_model = context.models.login_attempt.clone();
+    let anchor_request_model = context.models.anchor_request.clone();
+    let argon2_settings = context.settings.auth.argon2.clone();
 
     tokio::spawn(async move {
       axum::Server::from_tcp(listener)
@@ -81,6 +116,22 @@ mod tests {
     });
 
     let client = hyper::Client::new();
+
+    // test: the JWKS publishes the configured verification key
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!("http://{}/.well-known/jwks.json", addr))
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let jwks: Value = serde_json::from_slice(&res_body).unwrap();
+    assert_eq!(jwks["keys"][0]["kid"], "test-ed25519");
+    assert_eq!(jwks["keys"][0]["crv"], "Ed25519");
     // test: authenticate admin with invalid password
     let mut body = AuthorizeBody {
       email: "admin@test.com".to_string(),
@@ -100,6 +151,29 @@ mod tests {
       .unwrap();
 
     assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let wrong_password: Value = serde_json::from_slice(&res_body).unwrap();
+
+    // test: unknown emails get the same response as wrong passwords
+    let unknown = AuthorizeBody {
+      email: "unknown@test.com".to_string(),
+      password: "invalid".to_string(),
+    };
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!("http://{}/accounts/authenticate", addr))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .body(Body::from(serde_json::to_vec(&json!(unknown)).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let unknown_email: Value = serde_json::from_slice(&res_body).unwrap();
+    assert_eq!(unknown_email, wrong_password);
 
     // test: authenticate admin with valid password
     body.password = "test".to_string();
@@ -125,6 +199,71 @@ mod tests {
     let admin_token = res.access_token;
     let admin_id = res.account.id;
 
+    // test: refresh rotates the refresh token
+    let refresh_body = RefreshBody {
+      refresh_token: res.refresh_token,
+    };
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!("http://{}/accounts/refresh", addr))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .body(Body::from(serde_json::to_vec(&json!(refresh_body)).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let refreshed: AuthenticateResponse = serde_json::from_slice(&res_body).unwrap();
+    assert_ne!(refreshed.refresh_token, refresh_body.refresh_token);
+
+    // test: reusing the rotated refresh token is rejected (UNAUTHORIZED)
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!("http://{}/accounts/refresh", addr))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .body(Body::from(serde_json::to_vec(&json!(refresh_body)).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // test: the reuse revoked the whole family, including the newest token
+    let refresh_body = RefreshBody {
+      refresh_token: refreshed.refresh_token,
+    };
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!("http://{}/accounts/refresh", addr))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .body(Body::from(serde_json::to_vec(&json!(refresh_body)).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // test: logout is idempotent for revoked tokens
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!("http://{}/accounts/logout", addr))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .body(Body::from(serde_json::to_vec(&json!(refresh_body)).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
     let docker_args: [&str; 12] = [
       "-d",
       "-e",
@@ -144,17 +283,16 @@ mod tests {
     poll_for_first_block().await;
     
     // build post /geodata request body
-    let geometry = Geometry {
-      r#type: "Point".to_string(),
+    let geometry = Geometry::Point {
       coordinates: vec![-73.91320, 40.68405],
     };
 
-    let location = Location {
-      r#type: "GeometryCollection".to_string(),
+    let location = Location::GeometryCollection {
       geometries: vec![geometry],
     };
 
-    let body = CreateGeodata {
+    let geodata_location = location.clone();
+    let geodata_body = CreateGeodata {
       account: res.account.id,
       location,
       geotype: "Wind".to_string(),
@@ -171,10 +309,10 @@ mod tests {
       .request(
         Request::builder()
           .method(http::Method::POST)
-          .uri(format!("http://{}{}/geodata", addr, ADMIN_PATH))
+          .uri(format!("http://{}{}/geodata", addr, ADMIN_SCOPE))
           .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
           .header(http::header::AUTHORIZATION, auth_bearer)
-          .body(Body::from(serde_json::to_vec(&json!(body)).unwrap()))
+          .body(Body::from(serde_json::to_vec(&json!(geodata_body)).unwrap()))
           .unwrap(),
       )
       .await
@@ -185,6 +323,8 @@ mod tests {
     let res_body: Value = serde_json::from_slice(&res_body).unwrap();
     let res: PublicGeodata = serde_json::from_value(res_body).unwrap();
     assert_eq!(res.account, admin_id);
+    assert_eq!(res.version, 1);
+    let geodata_id = res.id;
     // create geodata also creates initial validation
     // future validations will create hash and compare with original hash
     assert_eq!(validation_model.count(doc! {}).await.unwrap(), 1u64);
@@ -198,9 +338,9 @@ mod tests {
       .request(
         Request::builder()
           .method(http::Method::POST)
-          .uri(format!("http://{}{}/geodata", addr, ADMIN_PATH))
+          .uri(format!("http://{}{}/geodata", addr, ADMIN_SCOPE))
           .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
-          .body(Body::from(serde_json::to_vec(&json!(&body)).unwrap()))
+          .body(Body::from(serde_json::to_vec(&json!(&geodata_body)).unwrap()))
           .unwrap(),
       )
       .await
@@ -212,6 +352,207 @@ mod tests {
     let error: Value = serde_json::from_str(&res_body).unwrap();
     assert_eq!(error["message"], "Invalid authentication credentials");
 
+    // test: invalid geometries are rejected field by field (BAD_REQUEST)
+    let invalid_body = json!({
+      "location": {
+        "type": "GeometryCollection",
+        "geometries": [
+          { "type": "Point", "coordinates": [-73.9132, 95.0] },
+          {
+            "type": "Polygon",
+            "coordinates": [[[0.0, 0.0], [0.0, 1.0], [2.0, 0.0], [2.0, 2.0], [0.0, 0.0]]],
+          },
+        ],
+      },
+      "geotype": "Wind",
+      "value": 11.1,
+      "source": "Google Earth Engine",
+      "quality": 5,
+    });
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!("http://{}{}/geodata", addr, ADMIN_SCOPE))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .header(http::header::AUTHORIZATION, format!("Bearer {}", admin_token))
+          .body(Body::from(serde_json::to_vec(&invalid_body).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let error: Value = serde_json::from_slice(&res_body).unwrap();
+    let fields = error["errors"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .map(|error| error["field"].as_str().unwrap().to_string())
+      .collect::<Vec<String>>();
+    assert_eq!(
+      fields,
+      vec![
+        "location.geometries[0].coordinates",
+        "location.geometries[1].coordinates[0]",
+      ]
+    );
+    assert_eq!(validation_model.count(doc! {}).await.unwrap(), 1u64);
+
+    // test: admins register geotypes, names are unique regardless of case (CONFLICT)
+    let humidity = json!({
+      "name": "Humidity",
+      "unit": "%",
+      "description": "Relative humidity",
+      "min_value": 0.0,
+      "max_value": 100.0,
+      "geometry_types": ["Point", "Polygon"],
+      "min_quality": 1,
+      "max_quality": 3,
+    });
+    let mut duplicate = humidity.clone();
+    duplicate["name"] = json!("humidity");
+    let mut invalid = humidity.clone();
+    invalid["name"] = json!("Pressure");
+    invalid["unit"] = json!("hecto pascal");
+    invalid["geometry_types"] = json!(["Circle"]);
+    for (body, status) in [
+      (humidity, StatusCode::OK),
+      (duplicate, StatusCode::CONFLICT),
+      (invalid, StatusCode::BAD_REQUEST),
+    ] {
+      let response = client
+        .request(
+          Request::builder()
+            .method(http::Method::POST)
+            .uri(format!("http://{}{}/geotypes", addr, ADMIN_SCOPE))
+            .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .header(http::header::AUTHORIZATION, format!("Bearer {}", admin_token))
+            .body(Body::from(serde_json::to_vec(&body).unwrap()))
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), status);
+    }
+
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!("http://{}{}/geotypes/Humidity", addr, USER_SCOPE))
+          .header(http::header::AUTHORIZATION, format!("Bearer {}", admin_token))
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let geotype: PublicGeotype = serde_json::from_slice(&res_body).unwrap();
+    assert_eq!(geotype.unit, "%");
+    assert_eq!(geotype.geometry_types, vec!["Point", "Polygon"]);
+
+    // test: geodata of unknown geotypes, or outside their definition (BAD_REQUEST)
+    for (geotype, value, quality, fields) in [
+      ("wind", 11.1, 5, vec!["geotype"]),
+      ("Wind", -3.0, 5, vec!["value"]),
+      ("Humidity", 50.0, 5, vec!["quality"]),
+    ] {
+      let body = json!({
+        "location": { "type": "Point", "coordinates": [-73.9132, 40.68405] },
+        "geotype": geotype,
+        "value": value,
+        "source": "Google Earth Engine",
+        "quality": quality,
+      });
+      let response = client
+        .request(
+          Request::builder()
+            .method(http::Method::POST)
+            .uri(format!("http://{}{}/geodata", addr, ADMIN_SCOPE))
+            .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .header(http::header::AUTHORIZATION, format!("Bearer {}", admin_token))
+            .body(Body::from(serde_json::to_vec(&body).unwrap()))
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+      let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+      let error: Value = serde_json::from_slice(&res_body).unwrap();
+      let errors = error["errors"].as_array().unwrap();
+      let found = errors
+        .iter()
+        .map(|error| error["field"].as_str().unwrap())
+        .collect::<Vec<&str>>();
+      assert_eq!(found, fields);
+    }
+    assert_eq!(validation_model.count(doc! {}).await.unwrap(), 1u64);
+
+    // test: geotypes in use can't be removed (CONFLICT), others can
+    for (name, status) in [("Wind", StatusCode::CONFLICT), ("Humidity", StatusCode::NO_CONTENT)] {
+      let response = client
+        .request(
+          Request::builder()
+            .method(http::Method::DELETE)
+            .uri(format!("http://{}{}/geotypes/{}", addr, ADMIN_SCOPE, name))
+            .header(http::header::AUTHORIZATION, format!("Bearer {}", admin_token))
+            .body(Body::empty())
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), status);
+    }
+
+    // test: the migration registers the geotypes of geodata from before the registry
+    let legacy = Geodata::new(
+      admin_id,
+      None,
+      Geometry::Point { coordinates: vec![-73.9, 40.7] },
+      "Salinity".to_owned(),
+      35.0,
+      "legacy".to_owned(),
+      2,
+    );
+    geodata_model.create(legacy).await.unwrap();
+    migrations::run(&context.models).await.unwrap();
+    let salinity = context.models.geotype.find_by_name("Salinity").await.unwrap().unwrap();
+    assert_eq!((salinity.min_quality, salinity.max_quality), (2, 2));
+    assert_eq!(salinity.geometry_types, vec!["Point"]);
+    assert!(context.models.migration.is_applied("register_geotypes_in_use").await.unwrap());
+    let registered = context.models.geotype.register_in_use(&geodata_model).await.unwrap();
+    assert_eq!(registered, 0);
+
+    // test: the backfill stores the geohash of geodata from before geohashes, in
+    // batches, and marks geodata without positions so it isn't visited again
+    let positionless = Geodata::new(
+      admin_id,
+      None,
+      Location::GeometryCollection { geometries: vec![] },
+      "Salinity".to_owned(),
+      35.0,
+      "legacy".to_owned(),
+      2,
+    );
+    geodata_model.create(positionless).await.unwrap();
+    let legacy_filter = doc! { "source": "legacy" };
+    let unset = doc! { "$unset": { "geohash": "" } };
+    geodata_model.update_many(legacy_filter.clone(), unset, None).await.unwrap();
+    assert_eq!(geodata_model.backfill_geohashes(1).await.unwrap(), 1);
+    assert_eq!(geodata_model.backfill_geohashes(1).await.unwrap(), 1);
+    assert_eq!(geodata_model.backfill_geohashes(1).await.unwrap(), 0);
+    let legacy = geodata_model.find(legacy_filter.clone(), None).await.unwrap();
+    let geohashes = legacy.into_iter().map(|geodata| geodata.geohash.is_some()).collect::<Vec<_>>();
+    assert_eq!(geohashes.iter().filter(|geohash| **geohash).count(), 1);
+    let marked = doc! { "source": "legacy", "geohash": { "$exists": true, "$eq": null } };
+    assert!(geodata_model.exists(marked).await.unwrap());
+    migrations::backfill_geohashes(&context.models).await.unwrap();
+    assert!(context.models.migration.is_applied("backfill_geohashes").await.unwrap());
+
+    geodata_model.delete_many(legacy_filter).await.unwrap();
+    context.models.geotype.delete_one(doc! { "name": "Salinity" }).await.unwrap();
+
     // test: authenticate user with valid password
     let body = AuthorizeBody {
       email: "user@test.com".to_string(),
@@ -235,13 +576,15 @@ mod tests {
 
     assert_eq!(res.account.name, "user".to_string());
     let user_token = res.access_token;
+    let user_refresh_token = res.refresh_token;
+    let user_id = res.account.id;
 
     // test: get geodata for user
     let auth_bearer = format!("Bearer {}", user_token);
     let response = client
       .request(
         Request::builder()
-          .uri(format!("http://{}{}/geodata", addr, USER_PATH))
+          .uri(format!("http://{}{}/geodata", addr, USER_SCOPE))
           .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
           .header(http::header::AUTHORIZATION, &auth_bearer)
           .body(Body::empty())
@@ -252,18 +595,55 @@ mod tests {
     assert_eq!(response.status(), StatusCode::OK);
     let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
     let res_body: Value = serde_json::from_slice(&res_body).unwrap();
-    let res: Vec<PublicGeodata> = serde_json::from_value(res_body).unwrap();
-    assert_eq!(res.len(), 1);
+    let res: Page<PublicGeodata> = serde_json::from_value(res_body).unwrap();
+    assert_eq!(res.items.len(), 1);
 
-    // test: get geodata/near for user
+    // test: page through geodata one at a time
+    let second_geodata = Geodata::new(
+      admin_id,
+      None,
+      geodata_location.clone(),
+      "Wind".to_string(),
+      9.4,
+      "Google Earth Engine".to_string(),
+      4,
+    );
+    let second_geodata = geodata_model.create(second_geodata).await.unwrap();
+    let mut cursor: Option<String> = None;
+    let mut seen = vec![];
+    loop {
+      let uri = match &cursor {
+        Some(cursor) => format!("http://{}{}/geodata?limit=1&cursor={}", addr, USER_SCOPE, cursor),
+        None => format!("http://{}{}/geodata?limit=1", addr, USER_SCOPE),
+      };
+      let response = client
+        .request(
+          Request::builder()
+            .uri(uri)
+            .header(http::header::AUTHORIZATION, &auth_bearer)
+            .body(Body::empty())
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::OK);
+      let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+      let page: Page<PublicGeodata> = serde_json::from_slice(&res_body).unwrap();
+      assert_eq!(page.items.len(), 1);
+      assert_eq!(page.total, 2);
+      seen.push(page.items[0].id);
+      cursor = page.next_cursor;
+      if cursor.is_none() {
+        break;
+      }
+    }
+    assert_eq!(seen, vec![geodata_id, second_geodata.id.unwrap()]);
+
+    // test: newest first, and invalid limits and cursors (BAD_REQUEST)
     let response = client
       .request(
         Request::builder()
-          .uri(format!(
-            "http://{}{}/geodata/near?lon=-73.91320&lat=40.68405&min=0&max=10000",
-            addr, USER_PATH
-          ))
-          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .uri(format!("http://{}{}/geodata?sort=-created", addr, USER_SCOPE))
           .header(http::header::AUTHORIZATION, &auth_bearer)
           .body(Body::empty())
           .unwrap(),
@@ -272,42 +652,45 @@ mod tests {
       .unwrap();
     assert_eq!(response.status(), StatusCode::OK);
     let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
-    let res_body: Value = serde_json::from_slice(&res_body).unwrap();
-    let res: Vec<PublicGeodata> = serde_json::from_value(res_body).unwrap();
-    assert_eq!(res.len(), 1);
+    let page: Page<PublicGeodata> = serde_json::from_slice(&res_body).unwrap();
+    assert_eq!(page.items[0].id, second_geodata.id.unwrap());
+    assert!(page.next_cursor.is_none());
 
-    // test: authenticate validator with valid password
-    let body = AuthorizeBody {
-      email: "validator@test.com".to_string(),
-      password: "test".to_string(),
-    };
+    // test: geodata as a GeoJSON FeatureCollection, with a link to the next page
     let response = client
       .request(
         Request::builder()
-          .method(http::Method::POST)
-          .uri(format!("http://{}/accounts/authenticate", addr))
-          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
-          .body(Body::from(serde_json::to_vec(&json!(body)).unwrap()))
+          .uri(format!("http://{}{}/geodata?limit=1", addr, USER_SCOPE))
+          .header(http::header::ACCEPT, geojson::MEDIA_TYPE)
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .body(Body::empty())
           .unwrap(),
       )
       .await
       .unwrap();
     assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[http::header::CONTENT_TYPE], geojson::MEDIA_TYPE);
+    assert_eq!(response.headers()[http::header::VARY], "accept");
     let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
-    let res_body: Value = serde_json::from_slice(&res_body).unwrap();
-    let res: AuthenticateResponse = serde_json::from_value(res_body).unwrap();
-
-    assert_eq!(res.account.name, "validator".to_string());
-    let validator_token = res.access_token;
-    let validator_id = res.account.id;
+    let collection: FeatureCollection = serde_json::from_slice(&res_body).unwrap();
+    assert_eq!(collection.r#type, "FeatureCollection");
+    assert_eq!(collection.number_matched, 2);
+    assert_eq!(collection.number_returned, 1);
+    assert_eq!(collection.bbox, Some([-73.91320, 40.68405, -73.91320, 40.68405]));
+    let feature = &collection.features[0];
+    assert_eq!(feature.id, Some(geodata_id.to_hex()));
+    assert_eq!(feature.geometry, geodata_location);
+    assert_eq!(feature.properties["geotype"], "Wind");
+    assert!(feature.properties.get("location").is_none());
+    let next = collection.links.iter().find(|link| link.rel == "next").unwrap();
+    assert!(next.href.starts_with(&format!("{}/geodata?limit=1&cursor=", USER_SCOPE)));
 
-    // test: get validation for validator
-    let auth_bearer = format!("Bearer {}", validator_token);
+    // test: GeoJSON refused with q=0 gets JSON
     let response = client
       .request(
         Request::builder()
-          .uri(format!("http://{}{}/validation", addr, VALIDATOR_PATH))
-          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .uri(format!("http://{}{}/geodata?limit=1", addr, USER_SCOPE))
+          .header(http::header::ACCEPT, "application/geo+json;q=0, application/json")
           .header(http::header::AUTHORIZATION, &auth_bearer)
           .body(Body::empty())
           .unwrap(),
@@ -315,49 +698,2223 @@ mod tests {
       .await
       .unwrap();
     assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[http::header::VARY], "accept");
     let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
-    let res_body: Value = serde_json::from_slice(&res_body).unwrap();
-    // returns results of all validations
-    let validations: ValidationResults = serde_json::from_value(res_body).unwrap();
-    assert_eq!(validations.results.len(), 1);
+    let page: Page<PublicGeodata> = serde_json::from_slice(&res_body).unwrap();
+    assert_eq!(page.items.len(), 1);
 
-    // validation succeeded but hash not returned
-    assert_eq!(validations.results[0].validated, true);
-    assert_eq!(validation_model.count(doc! {}).await.unwrap(), 1u64);
-    
-    // one validation instance for each geodata instance, with multiple validities
-    let validations = validation_model.find(doc! {}, limit).await.unwrap();
+    // test: filter geodata by attributes
+    for (query, expected) in [
+      ("min_value=10", geodata_id),
+      ("geotype=Temperature,Wind&max_quality=4", second_geodata.id.unwrap()),
+      ("source=Google%20Earth%20Engine&min_quality=5&max_value=20", geodata_id),
+    ] {
+      let response = client
+        .request(
+          Request::builder()
+            .uri(format!("http://{}{}/geodata?{}", addr, USER_SCOPE, query))
+            .header(http::header::AUTHORIZATION, &auth_bearer)
+            .body(Body::empty())
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::OK);
+      let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+      let page: Page<PublicGeodata> = serde_json::from_slice(&res_body).unwrap();
+      assert_eq!(page.total, 1);
+      assert_eq!(page.items[0].id, expected);
+    }
 
-    // this validation process created another validity for
-    // the geodata instance within same validation instance
-    assert_eq!(validations[0].validities.len(), 2);
+    for query in [
+      "limit=0",
+      "cursor=invalid",
+      "sort=value",
+      "min_quality=5&max_quality=1",
+      "created_after=yesterday",
+      "geotype=,",
+    ] {
+      let response = client
+        .request(
+          Request::builder()
+            .uri(format!("http://{}{}/geodata?{}", addr, USER_SCOPE, query))
+            .header(http::header::AUTHORIZATION, &auth_bearer)
+            .body(Body::empty())
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+    geodata_model
+      .delete_one(doc! { "_id": second_geodata.id.unwrap() })
+      .await
+      .unwrap();
 
-    // original validity by admin
-    assert_eq!(validations[0].validities[0].account, admin_id);
+    // test: bulk import geodata from NDJSON, CSV and a GeoJSON FeatureCollection
+    let ndjson = [
+      json!({ "location": { "type": "Point", "coordinates": [-73.9, 40.7] },
+        "geotype": "Temperature", "value": 21.5, "source": "import", "quality": 3,
+        "observed_at": "2022-06-01T00:00:00Z/2022-06-02T00:00:00Z" })
+      .to_string(),
+      json!({ "location": { "type": "Point", "coordinates": [-73.9, 95.0] },
+        "geotype": "Temperature", "value": 21.5, "source": "import", "quality": 3 })
+      .to_string(),
+      String::new(),
+      json!({ "location": { "type": "Point", "coordinates": [-73.8, 40.6] },
+        "geotype": "Wind", "value": 4.2, "source": "import", "quality": 2 })
+      .to_string(),
+      "{ not json".to_string(),
+      json!({ "location": { "type": "Point", "coordinates": [-73.8, 40.6] },
+        "geotype": "Rain", "value": 1.0, "source": "import", "quality": 2 })
+      .to_string(),
+    ]
+    .join("\n");
+    let csv = "Latitude,Longitude,geotype,value,source,quality,observed_at\n\
+      40.7,-73.9,Temperature,19,import,4,2022-05-01T00:00:00Z\n\
+      40.7,-73.9,Temperature,warm,import,4,\n\
+      \"40.6\",-73.8,Wind,3.5,\"import\",1,\n";
+    let feature_collection = json!({
+      "type": "FeatureCollection",
+      "features": [
+        { "type": "Feature",
+          "geometry": { "type": "Point", "coordinates": [-73.7, 40.8] },
+          "properties": { "geotype": "Wind", "value": 5.0, "source": "import", "quality": 2 } },
+        { "type": "Feature", "geometry": null, "properties": {} },
+      ],
+    })
+    .to_string();
+    for (content_type, body, expected) in [
+      ("application/x-ndjson", ndjson, (2, vec![2, 5, 6], 6)),
+      ("text/csv; charset=utf-8", csv.to_string(), (2, vec![3], 4)),
+      ("application/geo+json", feature_collection, (1, vec![2], 2)),
+    ] {
+      let response = client
+        .request(
+          Request::builder()
+            .method(http::Method::POST)
+            .uri(format!("http://{}{}/geodata/import", addr, ADMIN_SCOPE))
+            .header(http::header::CONTENT_TYPE, content_type)
+            .header(http::header::AUTHORIZATION, format!("Bearer {}", admin_token))
+            .body(Body::from(body))
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::OK);
+      let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+      let report: ImportReport = serde_json::from_slice(&res_body).unwrap();
+      let rejected = report.rows.iter().map(|result| result.row).collect::<Vec<u64>>();
+      assert_eq!((report.accepted, rejected, report.completed_through), expected);
+      assert_eq!(report.rejected, report.rows.len() as u64);
+      assert!(report.rows.iter().all(|result| !result.errors.is_empty()));
+      assert!(!report.truncated);
+      assert!(report.error.is_none());
+    }
+    let imported = geodata_model.count(doc! { "source": "import" }).await.unwrap();
+    assert_eq!(imported, 5);
+    assert_eq!(anchor_request_model.count(doc! {}).await.unwrap(), 5);
 
-    // this validity by validator
-    assert_eq!(validations[0].validities[1].account, validator_id);
+    // test: a failed anchor request waits for its retry behind the others, and
+    // is given up on after too many failures
+    let first = anchor_request_model.claim_next(600).await.unwrap().unwrap();
+    let retry_at = Some(geodata_rest::common::date::after(60));
+    anchor_request_model
+      .release(&first.id.unwrap(), "unreachable".to_owned(), retry_at)
+      .await
+      .unwrap();
+    let second = anchor_request_model.claim_next(600).await.unwrap().unwrap();
+    assert_ne!(second.id, first.id);
+    assert_eq!(second.attempts, 0);
+    anchor_request_model
+      .release(&second.id.unwrap(), "unreachable".to_owned(), None)
+      .await
+      .unwrap();
+    let failed = anchor_request_model.find_by_id(&second.id.unwrap()).await.unwrap().unwrap();
+    assert!(failed.failed_at.is_some());
+    for _ in 0..3 {
+      let next = anchor_request_model.claim_next(600).await.unwrap().unwrap();
+      assert!(next.id != first.id && next.id != second.id);
+    }
+    assert!(anchor_request_model.claim_next(600).await.unwrap().is_none());
 
-    // hash from this validation matches hash from original validation
-    // i.e. validation suceeded
+    // test: an import of an unsupported type, or CSV without lat/lon columns (BAD_REQUEST)
+    for (content_type, body) in [
+      ("text/plain", "40.7,-73.9"),
+      ("text/csv", "geotype,value,source,quality\nWind,1,import,1"),
+    ] {
+      let response = client
+        .request(
+          Request::builder()
+            .method(http::Method::POST)
+            .uri(format!("http://{}{}/geodata/import", addr, ADMIN_SCOPE))
+            .header(http::header::CONTENT_TYPE, content_type)
+            .header(http::header::AUTHORIZATION, format!("Bearer {}", admin_token))
+            .body(Body::from(body))
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    // test: a line over import.max_line_bytes stops the import, which reports the
+    // rows imported before it (BAD_REQUEST)
+    let valid = json!({ "location": { "type": "Point", "coordinates": [-73.9, 40.7] },
+      "geotype": "Temperature", "value": 20.0, "source": "import-long", "quality": 3 });
+    let body = format!("{}\n{}\n{}\n{}\n", valid, valid, "x".repeat(2048), valid);
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!("http://{}{}/geodata/import", addr, ADMIN_SCOPE))
+          .header(http::header::CONTENT_TYPE, "application/x-ndjson")
+          .header(http::header::AUTHORIZATION, format!("Bearer {}", admin_token))
+          .body(Body::from(body))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let report: ImportReport = serde_json::from_slice(&res_body).unwrap();
+    assert_eq!((report.accepted, report.completed_through), (2, 2));
+    assert!(report.error.unwrap().contains("Line 3"));
+    let filter = doc! { "source": "import-long" };
+    assert_eq!(geodata_model.count(filter.clone()).await.unwrap(), 2);
+    let long_imported = geodata_model
+      .find(filter.clone(), None)
+      .await
+      .unwrap()
+      .into_iter()
+      .map(|geodata| geodata.id.unwrap())
+      .collect::<Vec<ObjectId>>();
+    validation_model
+      .delete_many(doc! { "geodata": { "$in": &long_imported } })
+      .await
+      .unwrap();
+    anchor_request_model
+      .delete_many(doc! { "geodata": { "$in": &long_imported } })
+      .await
+      .unwrap();
+    geodata_model.delete_many(filter).await.unwrap();
+
+    // test: an import stopped before accepting any row still reports the rejected
+    // ones, at most import.max_reported_rejections of them (BAD_REQUEST)
+    let body = format!("{}{}\n", "{}\n".repeat(4), "x".repeat(2048));
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!("http://{}{}/geodata/import", addr, ADMIN_SCOPE))
+          .header(http::header::CONTENT_TYPE, "application/x-ndjson")
+          .header(http::header::AUTHORIZATION, format!("Bearer {}", admin_token))
+          .body(Body::from(body))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let report: ImportReport = serde_json::from_slice(&res_body).unwrap();
+    assert_eq!((report.accepted, report.rejected, report.rows.len()), (0, 4, 3));
+    assert!(report.truncated);
+    assert!(report.error.unwrap().contains("Line 5"));
+
+    // test: filter geodata by observation time, which overlaps the range
+    for (query, expected) in [
+      ("observed_after=2022-06-01T12:00:00Z&observed_before=2022-06-03T00:00:00Z", 1),
+      ("observed_after=2022-04-01T00:00:00Z", 2),
+      ("observed_before=2022-05-01T00:00:01Z", 1),
+    ] {
+      let response = client
+        .request(
+          Request::builder()
+            .uri(format!("http://{}{}/geodata?source=import&{}", addr, USER_SCOPE, query))
+            .header(http::header::AUTHORIZATION, &auth_bearer)
+            .body(Body::empty())
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::OK);
+      let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+      let page: Page<PublicGeodata> = serde_json::from_slice(&res_body).unwrap();
+      assert_eq!(page.total, expected);
+    }
+    let observed = geodata_model
+      .find_one(doc! { "source": "import", "value": 21.5 }, None)
+      .await
+      .unwrap()
+      .unwrap();
     assert_eq!(
-      validations[0].validities[0].hash,
-      validations[0].validities[1].hash
+      observed.observed_at.unwrap().to_string(),
+      "2022-06-01T00:00:00Z/2022-06-02T00:00:00Z"
     );
-    exec_docker_command("kill", &[&container_id]);
-  }
-}
 
-#[derive(Debug, Serialize)]
-struct AuthorizeBody {
-  email: String,
-  password: String,
-}
+    // test: export the imported geodata as NDJSON, GeoJSON and CSV
+    for format in ["ndjson", "geojson", "csv"] {
+      let response = client
+        .request(
+          Request::builder()
+            .uri(format!(
+              "http://{}{}/geodata/export?format={}&source=import&bbox=-74,40,-73,41",
+              addr, USER_SCOPE, format
+            ))
+            .header(http::header::AUTHORIZATION, &auth_bearer)
+            .body(Body::empty())
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::OK);
+      assert!(response.headers()[http::header::CONTENT_DISPOSITION]
+        .to_str()
+        .unwrap()
+        .ends_with(&format!("geodata.{}\"", format)));
+      let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+      let res_body = String::from_utf8(res_body.to_vec()).unwrap();
+      match format {
+        "ndjson" => {
+          let lines = res_body.lines().collect::<Vec<&str>>();
+          assert_eq!(lines.len(), 5);
+          let geodata: PublicGeodata = serde_json::from_str(lines[0]).unwrap();
+          assert_eq!(geodata.source, "import");
+        }
+        "geojson" => {
+          let collection: Value = serde_json::from_str(&res_body).unwrap();
+          assert_eq!(collection["type"], "FeatureCollection");
+          assert_eq!(collection["features"].as_array().unwrap().len(), 5);
+        }
+        _ => {
+          let lines = res_body.lines().collect::<Vec<&str>>();
+          assert_eq!(lines.len(), 6);
+          assert!(lines[0].starts_with("id,lon,lat,geotype,value"));
+          assert!(lines[1].contains(",-73.9,40.7,Temperature,21.5,import,3,"));
+        }
+      }
+    }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct AuthenticateResponse {
-  access_token: String,
-  account: PublicAccount,
+    // test: exports are compressed for clients accepting it
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!("http://{}{}/geodata/export?source=import", addr, USER_SCOPE))
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .header(http::header::ACCEPT_ENCODING, "gzip")
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[http::header::CONTENT_ENCODING], "gzip");
+
+    // test: an unknown export format (BAD_REQUEST)
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!("http://{}{}/geodata/export?format=xml", addr, USER_SCOPE))
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // test: time series of a geotype around a point, by observation time
+    let series_uri = |query: &str| {
+      format!(
+        "http://{}{}/geodata/series?start=2022-01-01T00:00:00Z\
+        &end=2023-01-01T00:00:00Z&{}",
+        addr, USER_SCOPE, query
+      )
+    };
+    let response = client
+      .request(
+        Request::builder()
+          .uri(series_uri("geotype=Temperature&lon=-73.9&lat=40.7&radius=1000"))
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let series: TimeSeries = serde_json::from_slice(&res_body).unwrap();
+    let points = series.points.unwrap();
+    let values = points.iter().map(|point| point.value).collect::<Vec<f64>>();
+    assert_eq!(values, vec![19.0, 21.5]);
+    assert_eq!(points[1].time.to_rfc3339_string(), "2022-06-01T00:00:00Z");
+    assert!(series.buckets.is_none());
+
+    // test: monthly buckets of the series within a polygon
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(series_uri("geotype=Temperature&bucket=monthly&source=import"))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .body(Body::from(
+            json!({ "type": "Polygon", "coordinates": [
+              [[-74.0, 40.0], [-73.0, 40.0], [-73.0, 41.0], [-74.0, 41.0], [-74.0, 40.0]]
+            ] })
+            .to_string(),
+          ))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let series: TimeSeries = serde_json::from_slice(&res_body).unwrap();
+    assert_eq!(series.bucket, Some(Bucket::Monthly));
+    let buckets = series.buckets.unwrap();
+    assert_eq!(buckets.len(), 2);
+    assert_eq!(buckets[0].start.to_rfc3339_string(), "2022-05-01T00:00:00Z");
+    assert_eq!((buckets[1].count, buckets[1].mean), (1, 21.5));
+
+    // test: series of several geotypes, or without a location, are refused
+    for query in [
+      "geotype=Temperature&lon=-73.9&lat=40.7",
+      "geotype=Wind,Temperature&lon=-73.9&lat=40.7&radius=1000",
+    ] {
+      let response = client
+        .request(
+          Request::builder()
+            .uri(series_uri(query))
+            .header(http::header::AUTHORIZATION, &auth_bearer)
+            .body(Body::empty())
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    // test: grid of a geotype's values per geohash cell
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!(
+            "http://{}{}/geodata/grid?geotype=Temperature&source=import&bbox=-74,40,-73,41\
+            &precision=4",
+            addr, USER_SCOPE
+          ))
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], geojson::MEDIA_TYPE);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let grid: FeatureCollection = serde_json::from_slice(&res_body).unwrap();
+    assert_eq!(grid.features.len(), 1);
+    let cell = &grid.features[0];
+    assert_eq!(cell.id.as_deref(), Some("dr5r"));
+    assert_eq!(cell.geometry.type_name(), "Polygon");
+    assert_eq!(cell.properties["count"], 2);
+    assert_eq!(cell.properties["mean"], 20.25);
+    let weighted_mean = cell.properties["weighted_mean"].as_f64().unwrap();
+    assert!((weighted_mean - 140.5 / 7.0).abs() < 1e-9);
+
+    // test: grids too fine to hold are refused
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!(
+            "http://{}{}/geodata/grid?geotype=Temperature&bbox=-74,40,-73,41&precision=13",
+            addr, USER_SCOPE
+          ))
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // test: vector tiles of geodata, clustered at low zoom levels
+    let contains = |body: &[u8], text: &str| {
+      body.windows(text.len()).any(|window| window == text.as_bytes())
+    };
+    let mut etags = vec![];
+    for (tile, attribute) in [("14/4828/6160", "source"), ("4/4/6", "point_count")] {
+      let response = client
+        .request(
+          Request::builder()
+            .uri(format!(
+              "http://{}{}/tiles/{}.mvt?source=import&geotype=Temperature",
+              addr, USER_SCOPE, tile
+            ))
+            .header(http::header::AUTHORIZATION, &auth_bearer)
+            .body(Body::empty())
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::OK);
+      assert_eq!(response.headers()[header::CONTENT_TYPE], mvt::MEDIA_TYPE);
+      etags.push(response.headers()[header::ETAG].clone());
+      let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+      assert!(contains(&res_body, "Temperature"));
+      assert!(contains(&res_body, attribute));
+    }
+
+    // test: tiles answer 304 while their ETag matches
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!(
+            "http://{}{}/tiles/14/4828/6160.mvt?source=import&geotype=Temperature",
+            addr, USER_SCOPE
+          ))
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .header(http::header::IF_NONE_MATCH, &etags[0])
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    // test: tiles outside the pyramid, or not addressed as .mvt
+    for (tile, status) in [
+      ("3/8/0.mvt", StatusCode::NOT_FOUND),
+      ("3/1/1.png", StatusCode::BAD_REQUEST),
+    ] {
+      let response = client
+        .request(
+          Request::builder()
+            .uri(format!("http://{}{}/tiles/{}", addr, USER_SCOPE, tile))
+            .header(http::header::AUTHORIZATION, &auth_bearer)
+            .body(Body::empty())
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), status);
+    }
+
+    // test: interpolate a geotype onto a grid as JSON and as an ESRI ASCII grid
+    let surface_uri = |query: &str| {
+      format!(
+        "http://{}{}/geodata/interpolate?geotype=Temperature&source=import\
+        &bbox=-74,40.6,-73.8,40.8&{}",
+        addr, USER_SCOPE, query
+      )
+    };
+    let response = client
+      .request(
+        Request::builder()
+          .uri(surface_uri("resolution=0.1&power=2"))
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let surface: Surface = serde_json::from_slice(&res_body).unwrap();
+    assert_eq!((surface.points, surface.grid.ncols, surface.grid.nrows), (2, 2, 2));
+    // Both points share a position, so they weigh the same everywhere.
+    for value in surface.grid.values.iter().flatten() {
+      assert!((value.unwrap() - 20.25).abs() < 1e-9);
+    }
+
+    let response = client
+      .request(
+        Request::builder()
+          .uri(surface_uri("resolution=0.1&radius=1000&format=asc"))
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let asc = String::from_utf8(res_body.to_vec()).unwrap();
+    assert!(asc.starts_with("ncols 2\nnrows 2\nxllcorner -74\n"));
+    assert!(asc.ends_with("-9999 -9999\n-9999 -9999\n"));
+
+    // test: grids beyond `interpolation.max_cells` are refused
+    let response = client
+      .request(
+        Request::builder()
+          .uri(surface_uri("resolution=0.001"))
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // test: the OGC API landing page and conformance declaration are public
+    for (path, fixture) in [
+      ("/ogc", include_str!("fixtures/ogc/landing.json")),
+      ("/ogc/conformance", include_str!("fixtures/ogc/conformance.json")),
+    ] {
+      let response = client
+        .request(
+          Request::builder()
+            .uri(format!("http://{}{}", addr, path))
+            .body(Body::empty())
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::OK);
+      let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+      let document: serde_json::Value = serde_json::from_slice(&res_body).unwrap();
+      assert_eq!(document, serde_json::from_str::<serde_json::Value>(fixture).unwrap());
+    }
+
+    // test: a collection per geotype
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!("http://{}/ogc/collections", addr))
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let collections: serde_json::Value = serde_json::from_slice(&res_body).unwrap();
+    let wind = collections["collections"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .find(|collection| collection["id"] == "Wind")
+      .unwrap();
+    let fixture = include_str!("fixtures/ogc/collection_wind.json");
+    assert_eq!(wind, &serde_json::from_str::<serde_json::Value>(fixture).unwrap());
+
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!("http://{}/ogc/collections/Humidity", addr))
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // test: items of a collection within a bbox, paged through next links
+    let mut items_uri = format!(
+      "http://{}/ogc/collections/Temperature/items?bbox=-74,40.6,-73.8,40.8&limit=1",
+      addr
+    );
+    let mut ids = vec![];
+    loop {
+      let response = client
+        .request(
+          Request::builder()
+            .uri(&items_uri)
+            .header(http::header::AUTHORIZATION, &auth_bearer)
+            .body(Body::empty())
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::OK);
+      assert_eq!(response.headers()[http::header::CONTENT_TYPE], geojson::MEDIA_TYPE);
+      let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+      let items: FeatureCollection = serde_json::from_slice(&res_body).unwrap();
+      assert_eq!(items.number_returned, 1);
+      ids.push(items.features[0].id.clone().unwrap());
+      match items.links.iter().find(|link| link.rel == "next") {
+        Some(next) => items_uri = format!("http://{}{}", addr, next.href),
+        None => break,
+      }
+    }
+    assert!(ids.len() >= 2);
+
+    // test: items within a world bbox, with heights or not, are all the items
+    let fixture = include_str!("fixtures/ogc/items_world.json");
+    let fixture = serde_json::from_str::<serde_json::Value>(fixture).unwrap();
+    for bbox in ["-180,-90,180,90", "-180,-90,-100,180,90,100"] {
+      let response = client
+        .request(
+          Request::builder()
+            .uri(format!("http://{}/ogc/collections/Temperature/items?bbox={}", addr, bbox))
+            .header(http::header::AUTHORIZATION, &auth_bearer)
+            .body(Body::empty())
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::OK);
+      let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+      let items: serde_json::Value = serde_json::from_slice(&res_body).unwrap();
+      // ids and dates differ from run to run
+      let mut features = items["features"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|feature| {
+          let properties = &feature["properties"];
+          json!({
+            "type": feature["type"],
+            "geometry": feature["geometry"],
+            "properties": {
+              "geotype": properties["geotype"],
+              "value": properties["value"],
+              "source": properties["source"],
+              "quality": properties["quality"],
+            },
+          })
+        })
+        .collect::<Vec<serde_json::Value>>();
+      features.sort_by(|a, b| {
+        let value = |feature: &serde_json::Value| feature["properties"]["value"].as_f64();
+        value(a).partial_cmp(&value(b)).unwrap()
+      });
+      let items = json!({
+        "type": items["type"],
+        "numberMatched": items["numberMatched"],
+        "numberReturned": items["numberReturned"],
+        "features": features,
+      });
+      assert_eq!(items, fixture);
+    }
+
+    // test: a bbox across the antimeridian, and a limit above the maximum, which
+    // pagination lowers to it
+    for (query, matched) in [("bbox=170,-10,-170,10", 0), ("limit=100000", 2)] {
+      let response = client
+        .request(
+          Request::builder()
+            .uri(format!("http://{}/ogc/collections/Temperature/items?{}", addr, query))
+            .header(http::header::AUTHORIZATION, &auth_bearer)
+            .body(Body::empty())
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::OK);
+      let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+      let items: FeatureCollection = serde_json::from_slice(&res_body).unwrap();
+      assert_eq!(items.number_matched, matched);
+    }
+
+    // test: items observed, or else created, within a datetime interval
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!(
+            "http://{}/ogc/collections/Temperature/items?bbox=-74,40.6,-73.8,40.8\
+            &datetime=2022-05-15T00:00:00Z/2022-12-31T00:00:00Z",
+            addr
+          ))
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let items: FeatureCollection = serde_json::from_slice(&res_body).unwrap();
+    assert_eq!(items.number_matched, 1);
+    let feature = &items.features[0];
+    assert_eq!(feature.properties["value"], 21.5);
+
+    // test: an item by id, only within its own collection
+    let id = feature.id.clone().unwrap();
+    for (collection, status) in [("Temperature", StatusCode::OK), ("Wind", StatusCode::NOT_FOUND)] {
+      let response = client
+        .request(
+          Request::builder()
+            .uri(format!("http://{}/ogc/collections/{}/items/{}", addr, collection, id))
+            .header(http::header::AUTHORIZATION, &auth_bearer)
+            .body(Body::empty())
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), status);
+      if status == StatusCode::OK {
+        let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let item: serde_json::Value = serde_json::from_slice(&res_body).unwrap();
+        assert_eq!(item["id"], id.as_str());
+        let links = item["links"].as_array().unwrap();
+        assert!(links.iter().any(|link| link["rel"] == "collection"));
+      }
+    }
+
+    let imported = geodata_model
+      .find(doc! { "source": "import" }, None)
+      .await
+      .unwrap()
+      .into_iter()
+      .map(|geodata| geodata.id.unwrap())
+      .collect::<Vec<ObjectId>>();
+    validation_model
+      .delete_many(doc! { "geodata": { "$in": &imported } })
+      .await
+      .unwrap();
+    geodata_model.delete_many(doc! { "source": "import" }).await.unwrap();
+    anchor_request_model.delete_many(doc! {}).await.unwrap();
+
+    // test: get geodata for user through the deprecated path alias
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!("http://{}{}/geodata", addr, LEGACY_USER_PATH))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["deprecation"], "true");
+
+    // test: admin issues an API key for the user, scoped to the user role
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!("http://{}{}/api-keys", addr, ADMIN_SCOPE))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .header(http::header::AUTHORIZATION, format!("Bearer {}", admin_token))
+          .body(Body::from(
+            serde_json::to_vec(&json!({
+              "account": user_id.to_hex(),
+              "name": "sensor",
+              "scopes": ["user"],
+            }))
+            .unwrap(),
+          ))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let created: Value = serde_json::from_slice(&res_body).unwrap();
+    let api_key = created["key"].as_str().unwrap().to_string();
+    let api_key_id = created["api_key"]["id"].as_str().unwrap().to_string();
+    assert!(api_key.starts_with("gdk_"));
+    assert_eq!(created["api_key"]["prefix"].as_str().unwrap().len(), 16);
+
+    // test: the API key is accepted in place of a bearer JWT
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!("http://{}{}/geodata", addr, USER_SCOPE))
+          .header(http::header::AUTHORIZATION, format!("Bearer {}", api_key))
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // test: a revoked API key is rejected (UNAUTHORIZED)
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::DELETE)
+          .uri(format!("http://{}{}/api-keys/{}", addr, ADMIN_SCOPE, api_key_id))
+          .header(http::header::AUTHORIZATION, format!("Bearer {}", admin_token))
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!("http://{}{}/geodata", addr, USER_SCOPE))
+          .header(http::header::AUTHORIZATION, format!("Bearer {}", api_key))
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // test: post geodata as user (FORBIDDEN, missing admin role)
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!("http://{}{}/geodata", addr, ADMIN_SCOPE))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .body(Body::from(serde_json::to_vec(&json!(&geodata_body)).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // test: get geodata/near for user
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!(
+            "http://{}{}/geodata/near?lon=-73.91320&lat=40.68405&min=0&max=10000",
+            addr, USER_SCOPE
+          ))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let res_body: Value = serde_json::from_slice(&res_body).unwrap();
+    let res: Page<PublicGeodata> = serde_json::from_value(res_body).unwrap();
+    assert_eq!(res.items.len(), 1);
+
+    // test: get geodata within a bounding box or a circle
+    for (query, total) in [
+      ("bbox=-74,40.6,-73.8,40.8", 1),
+      ("bbox=-74,40.6,-73.8,40.8&geotype=Temperature", 0),
+      ("bbox=2.2,48.8,2.4,48.9", 0),
+      ("bbox=-180,-90,180,90", 1),
+      ("bbox=-170,-80,100,80", 1),
+      // the geodata is north of the box, but south of the great circle between
+      // its north corners
+      ("bbox=-120,38,-30,40.6", 0),
+      ("lon=-73.9132&lat=40.684&radius=1000", 1),
+    ] {
+      let response = client
+        .request(
+          Request::builder()
+            .uri(format!("http://{}{}/geodata/within?{}", addr, USER_SCOPE, query))
+            .header(http::header::AUTHORIZATION, &auth_bearer)
+            .body(Body::empty())
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::OK);
+      let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+      let page: Page<PublicGeodata> = serde_json::from_slice(&res_body).unwrap();
+      assert_eq!(page.total, total);
+    }
+
+    // test: get geodata within and intersecting a polygon
+    let polygon = json!({
+      "type": "Polygon",
+      "coordinates": [[[-74.0, 40.6], [-73.8, 40.6], [-73.8, 40.8], [-74.0, 40.8], [-74.0, 40.6]]],
+    });
+    for operator in ["within", "intersects"] {
+      let response = client
+        .request(
+          Request::builder()
+            .method(http::Method::POST)
+            .uri(format!("http://{}{}/geodata/{}", addr, USER_SCOPE, operator))
+            .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .header(http::header::AUTHORIZATION, &auth_bearer)
+            .body(Body::from(serde_json::to_vec(&polygon).unwrap()))
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::OK);
+      let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+      let page: Page<PublicGeodata> = serde_json::from_slice(&res_body).unwrap();
+      assert_eq!(page.total, 1);
+    }
+
+    // test: invalid spatial queries (BAD_REQUEST)
+    let open_ring = json!({
+      "type": "Polygon",
+      "coordinates": [[[-74.0, 40.6], [-73.8, 40.6], [-73.8, 40.8], [-74.0, 40.8]]],
+    });
+    let point = json!({ "type": "Point", "coordinates": [-73.9132, 40.68405] });
+    for body in [open_ring, point] {
+      let response = client
+        .request(
+          Request::builder()
+            .method(http::Method::POST)
+            .uri(format!("http://{}{}/geodata/within", addr, USER_SCOPE))
+            .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .header(http::header::AUTHORIZATION, &auth_bearer)
+            .body(Body::from(serde_json::to_vec(&body).unwrap()))
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+    for query in ["bbox=-73.8,40.6,-74,40.8", "lon=-73.9&lat=40.6&radius=-1", "bbox=1,2,3"] {
+      let response = client
+        .request(
+          Request::builder()
+            .uri(format!("http://{}{}/geodata/within?{}", addr, USER_SCOPE, query))
+            .header(http::header::AUTHORIZATION, &auth_bearer)
+            .body(Body::empty())
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    // test: authenticate validator with valid password
+    let body = AuthorizeBody {
+      email: "validator@test.com".to_string(),
+      password: "test".to_string(),
+    };
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!("http://{}/accounts/authenticate", addr))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .body(Body::from(serde_json::to_vec(&json!(body)).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let res_body: Value = serde_json::from_slice(&res_body).unwrap();
+
+    // the validator role requires a second factor, so the password only
+    // yields an MFA token, with enrollment still outstanding
+    assert!(res_body.get("access_token").is_none());
+    assert_eq!(res_body["enrollment_required"], json!(true));
+    let mfa_token = res_body["mfa_token"].as_str().unwrap().to_string();
+
+    // test: the MFA token is no access token (UNAUTHORIZED)
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!("http://{}{}/validation", addr, VALIDATOR_SCOPE))
+          .header(http::header::AUTHORIZATION, format!("Bearer {}", mfa_token))
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // test: enroll an authenticator with the MFA token
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!("http://{}/accounts/mfa/enroll", addr))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .body(Body::from(serde_json::to_vec(&json!({ "mfa_token": mfa_token })).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let enrollment: MfaEnrollment = serde_json::from_slice(&res_body).unwrap();
+    assert!(enrollment.otpauth_uri.starts_with("otpauth://totp/"));
+    assert_eq!(enrollment.recovery_codes.len(), 10);
+    let totp_secret = totp::base32_decode(&enrollment.secret).unwrap();
+    let totp_step = totp::step(chrono::Utc::now().timestamp()) as u64;
+    let totp_code = totp::code_at(&totp_secret, totp_step);
+
+    // test: a wrong code does not complete the login (UNAUTHORIZED)
+    let wrong_code = format!("{:06}", (totp_code.parse::<u32>().unwrap() + 1) % 1_000_000);
+    let mfa_body = json!({ "mfa_token": mfa_token, "code": wrong_code });
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!("http://{}/accounts/authenticate/mfa", addr))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .body(Body::from(serde_json::to_vec(&mfa_body).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // test: the current code completes the login and enables MFA
+    let mfa_body = json!({ "mfa_token": mfa_token, "code": totp_code });
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!("http://{}/accounts/authenticate/mfa", addr))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .body(Body::from(serde_json::to_vec(&mfa_body).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let res: AuthenticateResponse = serde_json::from_slice(&res_body).unwrap();
+
+    assert_eq!(res.account.name, "validator".to_string());
+    assert!(res.account.mfa_enabled);
+    let validator_token = res.access_token;
+    let validator_id = res.account.id;
+
+    // test: a code works only once (UNAUTHORIZED)
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!("http://{}/accounts/authenticate/mfa", addr))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .body(Body::from(serde_json::to_vec(&mfa_body).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // test: a recovery code completes the login once
+    let recovery_code = &enrollment.recovery_codes[0];
+    let mfa_body = json!({ "mfa_token": mfa_token, "recovery_code": recovery_code });
+    for status in [StatusCode::OK, StatusCode::UNAUTHORIZED] {
+      let response = client
+        .request(
+          Request::builder()
+            .method(http::Method::POST)
+            .uri(format!("http://{}/accounts/authenticate/mfa", addr))
+            .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(serde_json::to_vec(&mfa_body).unwrap()))
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), status);
+    }
+
+    // test: bcrypt hash upgraded to Argon2id after login
+    let validator = account_model.find_by_id(&validator_id).await.unwrap().unwrap();
+    assert!(validator.password.starts_with("$argon2id$"));
+    assert!(!validator.needs_rehash(&argon2_settings));
+
+    // test: get validation for validator
+    let auth_bearer = format!("Bearer {}", validator_token);
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!("http://{}{}/validation", addr, VALIDATOR_SCOPE))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let res_body: Value = serde_json::from_slice(&res_body).unwrap();
+    // returns results of all validations
+    let validations: ValidationResults = serde_json::from_value(res_body).unwrap();
+    assert_eq!(validations.results.len(), 1);
+
+    // validation succeeded but hash not returned
+    assert_eq!(validations.results[0].validated, true);
+    assert_eq!(validation_model.count(doc! {}).await.unwrap(), 1u64);
+    
+    // one validation instance for each geodata instance, with multiple validities
+    let validations = validation_model.find(doc! {}, limit).await.unwrap();
+
+    // this validation process created another validity for
+    // the geodata instance within same validation instance
+    assert_eq!(validations[0].validities.len(), 2);
+
+    // original validity by admin
+    assert_eq!(validations[0].validities[0].account, admin_id);
+
+    // this validity by validator
+    assert_eq!(validations[0].validities[1].account, validator_id);
+
+    // hash from this validation matches hash from original validation
+    // i.e. validation suceeded
+    assert_eq!(
+      validations[0].validities[0].hash,
+      validations[0].validities[1].hash
+    );
+
+    // test: admin revises geodata, which becomes version 2
+    let admin_bearer = format!("Bearer {}", admin_token);
+    let geodata_uri = format!("http://{}{}/geodata/{}", addr, ADMIN_SCOPE, geodata_id);
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::PATCH)
+          .uri(&geodata_uri)
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .header(http::header::AUTHORIZATION, &admin_bearer)
+          .body(Body::from(serde_json::to_vec(&json!({ "value": 12.5 })).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let res: PublicGeodata = serde_json::from_slice(&res_body).unwrap();
+    assert_eq!(res.version, 2);
+    assert_eq!(res.value, 12.5);
+
+    // test: the revision is authorized, and queued to be anchored with the hash it
+    // replaced
+    let revision_request = anchor_request_model
+      .find_one(doc! { "geodata": geodata_id, "version": 2 }, None)
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(revision_request.previous_hash, Some(validations[0].validities[0].hash.clone()));
+    assert!(revision_request.next_attempt_at.is_none());
+    let validation = validation_model
+      .find_one(doc! { "geodata": geodata_id }, None)
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(validation.authorized_hash, Some(revision_request.hash.clone()));
+
+    // test: a revision in flight makes another of the same version a conflict, and
+    // the anchor worker drops the request of a revision that was never written
+    anchor_request_model.delete_many(doc! {}).await.unwrap();
+    let mut unwritten = geodata_model.find_by_id(&geodata_id).await.unwrap().unwrap();
+    unwritten.version = 3;
+    unwritten.previous_hash = Some(revision_request.hash.clone());
+    let now = geodata_rest::common::date::now();
+    anchor_request_model
+      .enqueue_revision(&unwritten, "unwritten".to_owned(), now)
+      .await
+      .unwrap();
+    let conflict = anchor_request_model
+      .enqueue_revision(&unwritten, "unwritten".to_owned(), now)
+      .await;
+    assert_eq!(conflict.unwrap_err().status(), StatusCode::CONFLICT);
+    assert!(anchor_queue::anchor_next(&context).await.unwrap());
+    assert_eq!(anchor_request_model.count(doc! {}).await.unwrap(), 0);
+
+    // test: an empty revision is rejected (BAD_REQUEST)
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::PATCH)
+          .uri(&geodata_uri)
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .header(http::header::AUTHORIZATION, &admin_bearer)
+          .body(Body::from(serde_json::to_vec(&json!({})).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // test: get geodata by id, and its earlier versions
+    let user_geodata_uri = format!("http://{}{}/geodata/{}", addr, USER_SCOPE, geodata_id);
+    let response = client
+      .request(
+        Request::builder()
+          .uri(&user_geodata_uri)
+          .header(http::header::AUTHORIZATION, &admin_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let res: PublicGeodata = serde_json::from_slice(&res_body).unwrap();
+    assert_eq!(res.version, 2);
+
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!("{}/versions", user_geodata_uri))
+          .header(http::header::AUTHORIZATION, &admin_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let versions: Value = serde_json::from_slice(&res_body).unwrap();
+    assert_eq!(versions.as_array().unwrap().len(), 1);
+    assert_eq!(versions[0]["version"], json!(1));
+    assert_eq!(versions[0]["value"], json!(11.1));
+
+    // test: validation accepts the authorized revision
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!("http://{}{}/validation", addr, VALIDATOR_SCOPE))
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let validations: ValidationResults = serde_json::from_slice(&res_body).unwrap();
+    assert_eq!(validations.results[0].version, 2);
+    assert!(validations.results[0].validated);
+
+    // test: validation detects changes made outside of the API
+    geodata_model
+      .update_one(doc! { "_id": geodata_id }, doc! { "$set": { "value": 99.0 } }, None)
+      .await
+      .unwrap();
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!("http://{}{}/validation", addr, VALIDATOR_SCOPE))
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let validations: ValidationResults = serde_json::from_slice(&res_body).unwrap();
+    assert!(!validations.results[0].validated);
+
+    // test: deleted geodata is no longer found (NOT_FOUND)
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::DELETE)
+          .uri(&geodata_uri)
+          .header(http::header::AUTHORIZATION, &admin_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = client
+      .request(
+        Request::builder()
+          .uri(&user_geodata_uri)
+          .header(http::header::AUTHORIZATION, &admin_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // test: admin locks the validator, whose token stops working (LOCKED)
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!(
+            "http://{}{}/accounts/{}/lock",
+            addr,
+            ADMIN_SCOPE,
+            validator_id.to_hex()
+          ))
+          .header(http::header::AUTHORIZATION, format!("Bearer {}", admin_token))
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!("http://{}{}/validation", addr, VALIDATOR_SCOPE))
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::LOCKED);
+
+    // test: logging in to a locked account answers like wrong credentials,
+    // and failures count per email, registered or not (UNAUTHORIZED)
+    for email in ["validator@test.com", "nobody@test.com"] {
+      let body = AuthorizeBody {
+        email: email.to_string(),
+        password: "test".to_string(),
+      };
+      let response = client
+        .request(
+          Request::builder()
+            .method(http::Method::POST)
+            .uri(format!("http://{}/accounts/authenticate", addr))
+            .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(serde_json::to_vec(&json!(body)).unwrap()))
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+    for email in ["validator@test.com", "nobody@test.com"] {
+      let query = doc! { "email_hash": hasher::hash(email), "failures": 1 };
+      assert!(login_attempt_model.exists(query).await.unwrap());
+    }
+
+    // test: logging out revokes the user's access token (UNAUTHORIZED)
+    let auth_bearer = format!("Bearer {}", user_token);
+    let refresh_body = RefreshBody {
+      refresh_token: user_refresh_token,
+    };
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!("http://{}/accounts/logout", addr))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .body(Body::from(serde_json::to_vec(&json!(refresh_body)).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!("http://{}{}/geodata", addr, USER_SCOPE))
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // test: repeated wrong passwords lock the user out, even for the right one
+    let mut body = AuthorizeBody {
+      email: "user@test.com".to_string(),
+      password: "invalid".to_string(),
+    };
+    for _ in 0..5 {
+      let response = client
+        .request(
+          Request::builder()
+            .method(http::Method::POST)
+            .uri(format!("http://{}/accounts/authenticate", addr))
+            .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(serde_json::to_vec(&json!(body)).unwrap()))
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    body.password = "test".to_string();
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!("http://{}/accounts/authenticate", addr))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .body(Body::from(serde_json::to_vec(&json!(body)).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+      audit_event_model
+        .count(doc! { "kind": "account.lockout", "account": user_id })
+        .await
+        .unwrap(),
+      1u64
+    );
+
+    // test: a password reset is accepted for unknown emails as well
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!("http://{}/accounts/password/reset", addr))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .body(Body::from(serde_json::to_vec(&json!({ "email": "nobody@test.com" })).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+    // test: the locked out user resets the password with the notified token
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!("http://{}/accounts/password/reset", addr))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .body(Body::from(serde_json::to_vec(&json!({ "email": "user@test.com" })).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let reset_token = notifier.wait_reset_token("user@test.com").await.unwrap();
+    assert!(notifier.last_reset_token("nobody@test.com").is_none());
+
+    // while an admin lock holds it answers LOCKED without using up the token
+    let reset_body = json!({ "token": reset_token, "new_password": "new password" });
+    for (locked_at, expected) in [
+      (bson::Bson::DateTime(geodata_rest::common::date::now()), StatusCode::LOCKED),
+      (bson::Bson::Null, StatusCode::NO_CONTENT),
+    ] {
+      account_model
+        .update_one(doc! { "_id": user_id }, doc! { "$set": { "locked_at": locked_at } }, None)
+        .await
+        .unwrap();
+      let response = client
+        .request(
+          Request::builder()
+            .method(http::Method::POST)
+            .uri(format!("http://{}/accounts/password/reset/confirm", addr))
+            .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(serde_json::to_vec(&reset_body).unwrap()))
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), expected);
+    }
+
+    // test: reset tokens work only once (UNAUTHORIZED)
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!("http://{}/accounts/password/reset/confirm", addr))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .body(Body::from(serde_json::to_vec(&reset_body).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // test: the reset lifted the lockout and the new password works
+    body.password = "new password".to_string();
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!("http://{}/accounts/authenticate", addr))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .body(Body::from(serde_json::to_vec(&json!(body)).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let res: AuthenticateResponse = serde_json::from_slice(&res_body).unwrap();
+    let user_token = res.access_token;
+
+    // test: admin creates two organizations
+    let admin_bearer = format!("Bearer {}", admin_token);
+    let mut organization_ids = vec![];
+    for name in ["north", "south"] {
+      let response = client
+        .request(
+          Request::builder()
+            .method(http::Method::POST)
+            .uri(format!("http://{}{}/organizations", addr, ADMIN_SCOPE))
+            .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .header(http::header::AUTHORIZATION, &admin_bearer)
+            .body(Body::from(serde_json::to_vec(&json!({ "name": name })).unwrap()))
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::OK);
+      let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+      let res_body: Value = serde_json::from_slice(&res_body).unwrap();
+      organization_ids.push(res_body["id"].as_str().unwrap().to_string());
+    }
+    let (north_id, south_id) = (&organization_ids[0], &organization_ids[1]);
+
+    // geodata owned by north
+    let north_geodata = Geodata::new(
+      admin_id,
+      Some(ObjectId::parse_str(north_id).unwrap()),
+      geodata_location.clone(),
+      "Temperature".to_string(),
+      21.5,
+      "north station".to_string(),
+      4,
+    );
+    let north_geodata = geodata_model.create(north_geodata).await.unwrap();
+
+    // test: accounts outside of north don't see its geodata
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!("http://{}{}/geodata", addr, USER_SCOPE))
+          .header(http::header::AUTHORIZATION, format!("Bearer {}", user_token))
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let geodata: Page<PublicGeodata> = serde_json::from_slice(&res_body).unwrap();
+    assert!(!geodata.items.is_empty());
+    assert!(geodata.items.iter().all(|geodata| geodata.organization.is_none()));
+
+    // test: moving the user into south invalidates its token (UNAUTHORIZED)
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::PUT)
+          .uri(format!("http://{}{}/accounts/{}/organization", addr, ADMIN_SCOPE, user_id))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .header(http::header::AUTHORIZATION, &admin_bearer)
+          .body(Body::from(serde_json::to_vec(&json!({ "organization": south_id })).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!("http://{}{}/geodata", addr, USER_SCOPE))
+          .header(http::header::AUTHORIZATION, format!("Bearer {}", user_token))
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!("http://{}/accounts/authenticate", addr))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .body(Body::from(serde_json::to_vec(&json!(body)).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let res: AuthenticateResponse = serde_json::from_slice(&res_body).unwrap();
+    assert_eq!(res.account.organization.unwrap().to_hex(), *south_id);
+    let user_bearer = format!("Bearer {}", res.access_token);
+
+    // test: south sees neither north's geodata nor geodata without organization
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!("http://{}{}/geodata", addr, USER_SCOPE))
+          .header(http::header::AUTHORIZATION, &user_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let geodata: Page<PublicGeodata> = serde_json::from_slice(&res_body).unwrap();
+    assert!(geodata.items.is_empty());
+
+    // test: once north shares its temperatures with south, south sees them
+    let grant_body = json!({ "grantee": south_id, "geotypes": ["Temperature"] });
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!("http://{}{}/organizations/{}/grants", addr, ADMIN_SCOPE, north_id))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .header(http::header::AUTHORIZATION, &admin_bearer)
+          .body(Body::from(serde_json::to_vec(&grant_body).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let res_body: Value = serde_json::from_slice(&res_body).unwrap();
+    let grant_id = res_body["id"].as_str().unwrap().to_string();
+
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!("http://{}{}/geodata", addr, USER_SCOPE))
+          .header(http::header::AUTHORIZATION, &user_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let geodata: Page<PublicGeodata> = serde_json::from_slice(&res_body).unwrap();
+    assert_eq!(geodata.items.len(), 1);
+    assert_eq!(geodata.items[0].id, north_geodata.id.unwrap());
+
+    // test: revoking the grant hides north's geodata again
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::DELETE)
+          .uri(format!(
+            "http://{}{}/organizations/{}/grants/{}",
+            addr, ADMIN_SCOPE, north_id, grant_id
+          ))
+          .header(http::header::AUTHORIZATION, &admin_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!("http://{}{}/geodata", addr, USER_SCOPE))
+          .header(http::header::AUTHORIZATION, &user_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    let res_body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let geodata: Page<PublicGeodata> = serde_json::from_slice(&res_body).unwrap();
+    assert!(geodata.items.is_empty());
+
+    // test: changing a password requires the current one (UNAUTHORIZED)
+    let auth_bearer = format!("Bearer {}", admin_token);
+    let change_body = json!({ "current_password": "invalid", "new_password": "new password" });
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!("http://{}/accounts/password", addr))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .body(Body::from(serde_json::to_vec(&change_body).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // test: a password change invalidates the tokens issued before
+    let change_body = json!({ "current_password": "test", "new_password": "new password" });
+    let response = client
+      .request(
+        Request::builder()
+          .method(http::Method::POST)
+          .uri(format!("http://{}/accounts/password", addr))
+          .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .body(Body::from(serde_json::to_vec(&change_body).unwrap()))
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = client
+      .request(
+        Request::builder()
+          .uri(format!("http://{}{}/geodata", addr, USER_SCOPE))
+          .header(http::header::AUTHORIZATION, &auth_bearer)
+          .body(Body::empty())
+          .unwrap(),
+      )
+      .await
+      .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+      audit_event_model
+        .count(doc! { "kind": "account.password_change", "account": admin_id })
+        .await
+        .unwrap(),
+      1u64
+    );
+
+    exec_docker_command("kill", &[&container_id]);
+  }
+
+  #[test]
+  fn test_geodata_filter() {
+    let filter = GeodataFilter {
+      geotype: Some("Wind, Temperature".to_string()),
+      min_quality: Some(3),
+      max_value: Some(20.0),
+      created_before: Some("2022-06-01T00:00:00Z".to_string()),
+      ..Default::default()
+    };
+    let before = bson::DateTime::parse_rfc3339_str("2022-06-01T00:00:00Z").unwrap();
+    assert_eq!(
+      filter.to_query().unwrap(),
+      doc! {
+        "geotype": { "$in": ["Wind", "Temperature"] },
+        "quality": { "$gte": 3 },
+        "value": { "$lte": 20.0 },
+        "created": { "$lt": before },
+      }
+    );
+    assert_eq!(GeodataFilter::default().to_query().unwrap(), doc! {});
+
+    let filter = GeodataFilter {
+      min_value: Some(f64::NAN),
+      ..Default::default()
+    };
+    assert!(filter.to_query().is_err());
+
+    let filter = GeodataFilter {
+      observed_after: Some("2022-06-01T00:00:00Z".to_string()),
+      observed_before: Some("2022-06-02T00:00:00Z".to_string()),
+      ..Default::default()
+    };
+    let after = bson::DateTime::parse_rfc3339_str("2022-06-01T00:00:00Z").unwrap();
+    let before = bson::DateTime::parse_rfc3339_str("2022-06-02T00:00:00Z").unwrap();
+    assert_eq!(
+      filter.to_query().unwrap(),
+      doc! {
+        "observed_at.end": { "$gte": after },
+        "observed_at.start": { "$lt": before },
+      }
+    );
+
+    let interval = Interval::parse("2022-06-01T00:00:00Z/2022-06-02T00:00:00Z").unwrap();
+    assert_eq!((interval.start, interval.end), (after, before));
+    assert!(Interval::parse("2022-06-01T00:00:00Z").unwrap().is_instant());
+    assert!(Interval::parse("2022-06-02T00:00:00Z/2022-06-01T00:00:00Z").is_err());
+    assert!(Interval::parse("yesterday").is_err());
+  }
+
+  #[test]
+  fn test_geojson() {
+    let bbox = BoundingBox::parse("-74, 40.6, -73.8, 40.8").unwrap();
+    let polygon = geojson::Geometry::from_bbox(&bbox);
+    assert!(polygon.is_areal());
+    assert!(polygon.validate().is_ok());
+    assert!(BoundingBox::parse("-74,40.6,-73.8").is_err());
+    assert!(BoundingBox::parse("-74,40.6,-73.8,95").is_err());
+
+    // query polygons follow the parallels of the box, in parts narrower than a
+    // hemisphere, with the poles as single vertices
+    let narrow = geojson::Geometry::covering(&BoundingBox::parse("-120,38,-30,40.6").unwrap());
+    match &narrow {
+      geojson::Geometry::Polygon { coordinates } => {
+        assert!(coordinates[0].windows(2).all(|edge| (edge[1][0] - edge[0][0]).abs() <= 1.0));
+        assert!(coordinates[0].iter().all(|position| position[1] >= 38.0));
+      }
+      _ => panic!("expected a Polygon"),
+    }
+    for (bbox, parts) in [("-180,-90,180,90", 4), ("-170,-80,100,80", 3)] {
+      let covering = geojson::Geometry::covering(&BoundingBox::parse(bbox).unwrap());
+      let polygons = match covering {
+        geojson::Geometry::MultiPolygon { coordinates } => coordinates,
+        _ => panic!("expected a MultiPolygon"),
+      };
+      assert_eq!(polygons.len(), parts);
+      for rings in polygons {
+        let ring = &rings[0];
+        assert!(ring.windows(2).all(|edge| edge[0] != edge[1]));
+        assert!(ring.iter().all(|position| position[0].abs() <= 180.0));
+        let polygon = geojson::Geometry::Polygon { coordinates: rings };
+        assert!(polygon.validate().is_ok());
+      }
+    }
+
+    let line = geojson::Geometry::LineString {
+      coordinates: vec![vec![0.0, 0.0]],
+    };
+    assert!(line.validate().is_err());
+    let point = geojson::Geometry::Point {
+      coordinates: vec![181.0, 0.0],
+    };
+    assert!(point.validate().is_err());
+    assert!(geojson::circle(0.0, 0.0, 0.0).is_err());
+
+    // clockwise exterior ring, and a hole crossing it
+    let clockwise = vec![
+      vec![0.0, 0.0],
+      vec![0.0, 1.0],
+      vec![1.0, 1.0],
+      vec![1.0, 0.0],
+      vec![0.0, 0.0],
+    ];
+    let mut counterclockwise = clockwise.clone();
+    counterclockwise.reverse();
+    let crossing_hole = vec![
+      vec![0.5, 0.5],
+      vec![0.5, 2.0],
+      vec![0.8, 2.0],
+      vec![0.8, 0.5],
+      vec![0.5, 0.5],
+    ];
+    for (rings, valid) in [
+      (vec![counterclockwise.clone()], true),
+      (vec![clockwise], false),
+      (vec![counterclockwise, crossing_hole], false),
+    ] {
+      let polygon = geojson::Geometry::Polygon { coordinates: rings };
+      assert_eq!(polygon.validate().is_ok(), valid);
+    }
+
+    // a bowtie crosses itself, and geometries past the position cap aren't checked
+    let bowtie = vec![
+      vec![0.0, 0.0],
+      vec![4.0, 0.0],
+      vec![4.0, 2.0],
+      vec![1.0, -1.0],
+      vec![0.0, 2.0],
+      vec![0.0, 0.0],
+    ];
+    let polygon = geojson::Geometry::Polygon { coordinates: vec![bowtie] };
+    assert!(polygon.validate().is_err());
+    let line = geojson::Geometry::LineString {
+      coordinates: (0..=geojson::MAX_POSITIONS)
+        .map(|i| vec![i as f64 / 1000.0, 0.0])
+        .collect(),
+    };
+    let errors = line.errors("location");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].field, "location.coordinates");
+
+    let nested = geojson::Geometry::GeometryCollection {
+      geometries: vec![geojson::Geometry::GeometryCollection { geometries: vec![] }],
+    };
+    assert!(nested.validate().is_err());
+  }
+
+  #[test]
+  fn test_import() {
+    assert_eq!(Format::from_content_type("text/csv; charset=utf-8"), Some(Format::Csv));
+    assert_eq!(Format::from_content_type("application/x-ndjson"), Some(Format::NdJson));
+    assert_eq!(Format::from_content_type("text/plain"), None);
+
+    assert_eq!(
+      import::split_csv_line(r#"a,"b,c","say ""hi""",,"#).unwrap(),
+      vec!["a", "b,c", r#"say "hi""#, "", ""]
+    );
+    assert!(import::split_csv_line(r#"a,"b"#).is_err());
+
+    let header = CsvHeader::parse("source,LNG,lat,geotype,value,quality,notes").unwrap();
+    let row = header.parse_row("sensor,-73.9,40.7,Wind,4.5,2,calm").unwrap();
+    assert_eq!(row.location, geojson::Geometry::Point { coordinates: vec![-73.9, 40.7] });
+    assert_eq!(row.source, "sensor");
+    let errors = header.parse_row("sensor,-73.9,40.7,,NaN,high,").unwrap_err();
+    let fields = errors.iter().map(|error| error.field.as_str()).collect::<Vec<&str>>();
+    assert_eq!(fields, vec!["quality"]);
+    let errors = header.parse_row("sensor,-73.9,40.7,,NaN,2,").unwrap_err();
+    let fields = errors.iter().map(|error| error.field.as_str()).collect::<Vec<&str>>();
+    assert_eq!(fields, vec!["geotype", "value"]);
+    assert!(header.parse_row("sensor,-73.9").is_err());
+    assert!(CsvHeader::parse("lat,geotype,value,source,quality").is_err());
+
+    let header = CsvHeader::parse("lon,lat,geotype,value,source,quality,observed_at").unwrap();
+    let row = header.parse_row("-73.9,40.7,Wind,4.5,sensor,2,2022-06-01T00:00:00Z").unwrap();
+    assert!(row.observed_at.unwrap().is_instant());
+    let row = header.parse_row("-73.9,40.7,Wind,4.5,sensor,2,").unwrap();
+    assert!(row.observed_at.is_none());
+    let errors = header.parse_row("-73.9,40.7,Wind,4.5,sensor,2,June").unwrap_err();
+    assert_eq!(errors[0].field, "observed_at");
+  }
+
+  #[test]
+  fn test_export() {
+    assert_eq!(export::csv_field("Wind"), "Wind");
+    assert_eq!(export::csv_field("a,b"), r#""a,b""#);
+    assert_eq!(export::csv_field(r#"say "hi""#), r#""say ""hi""""#);
+    assert_eq!(export::Format::GeoJson.header(), r#"{"type":"FeatureCollection","features":["#);
+    assert_eq!(export::Format::NdJson.footer(), "");
+  }
+
+  #[test]
+  fn test_series() {
+    assert_eq!(
+      Bucket::Daily.start(),
+      doc! { "$dateFromParts": {
+        "year": { "$year": "$time" },
+        "month": { "$month": "$time" },
+        "day": { "$dayOfMonth": "$time" },
+      } }
+    );
+    assert!(!Bucket::Monthly.start().get_document("$dateFromParts").unwrap().contains_key("day"));
+    assert!(Bucket::Hourly.start().get_document("$dateFromParts").unwrap().contains_key("hour"));
+
+    let start = bson::DateTime::parse_rfc3339_str("2022-06-01T00:00:00Z").unwrap();
+    let end = bson::DateTime::parse_rfc3339_str("2022-07-01T00:00:00Z").unwrap();
+    let stages = series::points(start, end, 10);
+    assert_eq!(stages[1], doc! { "$match": { "time": { "$gte": start, "$lt": end } } });
+    assert_eq!(stages[3], doc! { "$limit": 10_i64 });
+    let stages = series::buckets(start, end, Bucket::Hourly, 10);
+    let group = stages[2].get_document("$group").unwrap();
+    assert_eq!(group.get_document("_id").unwrap(), &Bucket::Hourly.start());
+  }
+
+  #[test]
+  fn test_geohash() {
+    assert_eq!(geohash::encode(-5.6, 42.6, 5), "ezs42");
+    assert_eq!(geohash::encode(-73.9, 40.7, 4), "dr5r");
+    let bbox = geohash::bounds("ezs42").unwrap();
+    assert!(bbox.west <= -5.6 && -5.6 <= bbox.east);
+    assert!(bbox.south <= 42.6 && 42.6 <= bbox.north);
+    assert!((bbox.east - bbox.west - 360.0 / 4096.0).abs() < 1e-12);
+    assert_eq!(geohash::bounds("ezs4a"), None);
+
+    let location = Geometry::GeometryCollection {
+      geometries: vec![
+        Geometry::Point { coordinates: vec![-74.0, 40.6] },
+        Geometry::Point { coordinates: vec![-73.8, 40.8] },
+      ],
+    };
+    let hash = geohash::of(&location).unwrap();
+    assert_eq!(hash.len(), geohash::PRECISION);
+    assert_eq!(hash, geohash::encode(-73.9, 40.7, geohash::PRECISION));
+
+    let feature = geojson::Feature::from(GridCell {
+      cell: "dr5r".to_owned(),
+      count: 2,
+      min: 19.0,
+      max: 21.5,
+      mean: 20.25,
+      weighted_mean: None,
+    });
+    assert_eq!(feature.id.as_deref(), Some("dr5r"));
+    assert!(!feature.properties.contains_key("cell"));
+    assert_eq!(feature.properties["weighted_mean"], serde_json::Value::Null);
+  }
+
+  #[test]
+  fn test_mvt() {
+    let tile = TileId::new(1, 0, 0).unwrap();
+    let bounds = tile.bounds();
+    assert_eq!((bounds.west, bounds.east, bounds.south), (-180.0, 0.0, 0.0));
+    assert!((bounds.north - mvt::MAX_LATITUDE).abs() < 1e-9);
+    assert_eq!(tile.project(-180.0, mvt::MAX_LATITUDE), (0, 0));
+    assert_eq!(tile.project(0.0, 0.0), (4096, 4096));
+    assert!(!tile.contains((4096, 0)));
+    assert_eq!(TileId::new(2, 4, 0), None);
+
+    let mut layer = Layer::new("Wind");
+    let point = Geometry::Point { coordinates: vec![-180.0, 90.0] };
+    layer.add(&tile, &point, &[("value", mvt::Value::Double(4.2))]);
+    let encoded = mvt::encode(&[layer, Layer::new("Empty")]);
+    // A single layer of version 2, named Wind
+    assert_eq!(encoded[0], 0x1a);
+    assert_eq!(&encoded[2..10], &[0x78, 2, 0x0a, 4, b'W', b'i', b'n', b'd']);
+    // Tags, then a Point moved to the corner of the tile
+    let feature = [0x12, 11, 0x12, 2, 0, 0, 0x18, 1, 0x22, 3, 9, 0, 0];
+    assert!(encoded.windows(feature.len()).any(|window| window == feature));
+    assert!(!encoded.windows(5).any(|window| window == b"Empty"));
+
+    let ring = vec![
+      vec![-180.0, 0.0],
+      vec![0.0, 0.0],
+      vec![0.0, mvt::MAX_LATITUDE],
+      vec![-180.0, 0.0],
+    ];
+    let mut layer = Layer::new("Rain");
+    layer.add(&tile, &Geometry::Polygon { coordinates: vec![ring] }, &[]);
+    let encoded = mvt::encode(&[layer]);
+    // MoveTo, LineTo 2 positions, ClosePath
+    let commands = [9, 0, 8192, 18, 8192, 0, 0, 8191, 15];
+    let varints = commands.iter().flat_map(|command: &u32| match command {
+      0..=127 => vec![*command as u8],
+      _ => vec![(command & 0x7f) as u8 | 0x80, (command >> 7) as u8],
+    });
+    let varints = varints.collect::<Vec<u8>>();
+    assert!(encoded.windows(varints.len()).any(|window| window == varints));
+
+    assert_eq!(routes::tiles::cluster_precision(0), 1);
+    assert_eq!(routes::tiles::cluster_precision(4), 3);
+    assert_eq!(geohash::cell_size(1), (45.0, 45.0));
+    assert_eq!(geohash::cell_size(2), (11.25, 5.625));
+  }
+
+  #[test]
+  fn test_interpolation() {
+    let degree = interpolation::distance(0.0, 0.0, 0.0, 1.0);
+    assert!((degree - 111_319.5).abs() < 1.0);
+
+    let bbox = BoundingBox::parse("0,0,0.3,0.1").unwrap();
+    assert_eq!(Grid::size(&bbox, 0.1), (3, 1));
+    let samples = [
+      Sample { lon: 0.05, lat: 0.05, value: 10.0 },
+      Sample { lon: 0.25, lat: 0.05, value: 20.0 },
+    ];
+    let mut parameters = Parameters {
+      method: Method::Idw,
+      power: 2.0,
+      radius: None,
+    };
+    let grid = interpolation::interpolate(&samples, &bbox, 0.1, &parameters);
+    let values = grid.values[0].iter().map(|value| value.unwrap()).collect::<Vec<f64>>();
+    assert_eq!(values[0], 10.0);
+    assert!((values[1] - 15.0).abs() < 1e-9);
+    assert_eq!(values[2], 20.0);
+
+    parameters.radius = Some(5_000.0);
+    let grid = interpolation::interpolate(&samples, &bbox, 0.1, &parameters);
+    assert_eq!(grid.values, vec![vec![Some(10.0), None, Some(20.0)]]);
+
+    parameters.method = Method::Nearest;
+    parameters.radius = None;
+    let grid = interpolation::interpolate(&samples[..1], &bbox, 0.1, &parameters);
+    assert_eq!(grid.values, vec![vec![Some(10.0); 3]]);
+    assert_eq!(grid.to_csv().lines().nth(2), Some("0.15000000000000002,0.05,10"));
+    let asc = grid.to_asc();
+    assert!(asc.contains("yllcorner 0\ncellsize 0.1\nNODATA_value -9999\n10 10 10\n"));
+  }
+
+  #[test]
+  fn test_ogc() {
+    let june = bson::DateTime::parse_rfc3339_str("2022-06-01T00:00:00Z").unwrap();
+    let query = ogc::datetime_query("2022-06-01T00:00:00Z").unwrap();
+    assert_eq!(
+      query,
+      doc! { "$or": [
+        { "observed_at.end": { "$gte": june }, "observed_at.start": { "$lte": june } },
+        { "observed_at": null, "created": { "$gte": june, "$lte": june } },
+      ] }
+    );
+
+    let query = ogc::datetime_query("../2022-06-01T00:00:00Z").unwrap();
+    assert_eq!(
+      query,
+      doc! { "$or": [
+        { "observed_at.start": { "$lte": june } },
+        { "observed_at": null, "created": { "$lte": june } },
+      ] }
+    );
+    let query = ogc::datetime_query("2022-06-01T00:00:00Z/").unwrap();
+    assert_eq!(
+      query,
+      doc! { "$or": [
+        { "observed_at.end": { "$gte": june } },
+        { "observed_at": null, "created": { "$gte": june } },
+      ] }
+    );
+    assert_eq!(ogc::datetime_query("../..").unwrap(), doc! {});
+
+    for invalid in ["..", "June", "2022-06-02T00:00:00Z/2022-06-01T00:00:00Z"] {
+      assert!(ogc::datetime_query(invalid).is_err());
+    }
+
+    // bboxes with heights, across the antimeridian, or the whole world
+    let bbox = ogc::bbox("-74,40.6,0,-73.8,40.8,100").unwrap();
+    assert_eq!(bbox.to_array(), [-74.0, 40.6, -73.8, 40.8]);
+    let bbox = ogc::bbox("170,-10,-170,10").unwrap();
+    assert_eq!(bbox.to_array(), [170.0, -10.0, 190.0, 10.0]);
+    match geojson::Geometry::covering(&bbox) {
+      geojson::Geometry::Polygon { coordinates } => {
+        assert!(coordinates[0].iter().all(|position| position[0].abs() <= 180.0));
+        assert!(coordinates[0].contains(&vec![-170.0, 10.0]));
+      }
+      _ => panic!("expected a Polygon"),
+    }
+    assert!(ogc::bbox("-180,-90,180,90").is_ok());
+    for invalid in ["1,2,3", "1,2,3,4,5", "190,-10,-170,10", "10,10,10,20"] {
+      assert!(ogc::bbox(invalid).is_err());
+    }
+  }
+
+  #[test]
+  fn test_keys() {
+    std::env::set_var("RUN_MODE", "test");
+    let mut auth = geodata_rest::settings::Settings::new().unwrap().auth;
+    assert!(KeyStore::from_settings(&auth, "production").is_ok());
+
+    // A public key that doesn't match the signing key is refused.
+    auth.keys[0].public_key = "tests/fixtures/keys/other-ed25519.pub.pem".to_owned();
+    let err = KeyStore::from_settings(&auth, "production").err().unwrap();
+    assert!(format!("{:#}", err).contains("doesn't match its private key"));
+
+    // Without keys, only development falls back to the shared secret.
+    auth.keys.clear();
+    auth.signing_key = None;
+    assert!(KeyStore::from_settings(&auth, "development").is_ok());
+    assert!(KeyStore::from_settings(&auth, "production").is_err());
+  }
+
+  #[test]
+  fn test_anchor_queue() {
+    assert_eq!(anchor_queue::retry_delay(1), Some(30));
+    assert_eq!(anchor_queue::retry_delay(3), Some(120));
+    assert_eq!(anchor_queue::retry_delay(9), Some(3600));
+    assert_eq!(anchor_queue::retry_delay(anchor_queue::MAX_ATTEMPTS), None);
+  }
+
+  #[test]
+  fn test_totp() {
+    // RFC 6238 appendix B SHA1 vectors, truncated to 6 digits
+    let secret = b"12345678901234567890";
+    for (time, code) in [
+      (59, "287082"),
+      (1111111109, "081804"),
+      (1111111111, "050471"),
+      (1234567890, "005924"),
+      (2000000000, "279037"),
+    ] {
+      assert_eq!(totp::code_at(secret, totp::step(time) as u64), code);
+    }
+
+    let encoded = totp::base32_encode(secret);
+    assert_eq!(encoded, "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+    assert_eq!(totp::base32_decode(&encoded).unwrap(), secret.to_vec());
+    assert_eq!(totp::verify(&encoded, "050471", 1111111111 + 30, 1), Some(37037037));
+    assert_eq!(totp::verify(&encoded, "050471", 1111111111 + 90, 1), None);
+  }
+}
+
+#[derive(Debug, Serialize)]
+struct AuthorizeBody {
+  email: String,
+  password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AuthenticateResponse {
+  access_token: String,
+  refresh_token: String,
+  account: PublicAccount,
+}
+
+#[derive(Debug, Deserialize)]
+struct MfaEnrollment {
+  secret: String,
+  otpauth_uri: String,
+  recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct RefreshBody {
+  refresh_token: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]